@@ -1,5 +1,10 @@
 /// This module provides fancy terminal banners and decorative elements
 /// to enhance the visual appearance of the application.
+use crate::{
+    icons::{icon, Icon},
+    messages::{self, Message},
+    term_utils,
+};
 use colored::*;
 
 /// Returns the main application banner with Google Cloud SSH Manager title
@@ -7,7 +12,7 @@ use colored::*;
 /// # Returns
 /// * A colorful banner string ready to be printed to the terminal
 pub fn main_banner() -> String {
-    let border = "═".repeat(60);
+    let border = "═".repeat(term_utils::layout_width().min(60));
 
     format!(
         r#"
@@ -27,11 +32,11 @@ pub fn main_banner() -> String {
         "██║  ███╗██║     ██║     ██║   ██║██║   ██║██║  ██║".bright_cyan(),
         "v0.1.0".bright_white(),
         "██║   ██║██║     ██║     ██║   ██║██║   ██║██║  ██║".bright_cyan(),
-        "Secure • Fast • Simple".bright_white().italic(),
+        messages::text(Message::Tagline).bright_white().italic(),
         "╚██████╔╝╚██████╗███████╗╚██████╔╝╚██████╔╝██████╔╝".bright_cyan(),
         "by Your Name".bright_black(),
         " ╚═════╝  ╚═════╝╚══════╝ ╚═════╝  ╚═════╝ ╚═════╝ ".bright_cyan(),
-        "Rust-powered CLI tool".bright_black().italic(),
+        messages::text(Message::Subtitle).bright_black().italic(),
         border.bright_blue()
     )
 }
@@ -44,7 +49,8 @@ pub fn main_banner() -> String {
 /// # Returns
 /// * A formatted section header
 pub fn section_header(title: &str) -> String {
-    let pad_len = (50 - title.len()) / 2;
+    let width = term_utils::layout_width().min(52);
+    let pad_len = width.saturating_sub(term_utils::display_width(title) + 2) / 2;
     let padding = "─".repeat(pad_len);
 
     format!(
@@ -63,7 +69,18 @@ pub fn section_header(title: &str) -> String {
 /// # Returns
 /// * A formatted success message
 pub fn success_message(message: &str) -> String {
-    format!("✅ {}", message.green().bold())
+    format!("{} {}", icon(Icon::Success), message.green().bold())
+}
+
+/// Returns a formatted error message
+///
+/// # Arguments
+/// * `message` - The error message
+///
+/// # Returns
+/// * A formatted error message
+pub fn error_message(message: &str) -> String {
+    format!("{} {}", icon(Icon::Error), message.red().bold())
 }
 
 /// Returns a formatted information message
@@ -74,7 +91,55 @@ pub fn success_message(message: &str) -> String {
 /// # Returns
 /// * A formatted info message
 pub fn info_message(message: &str) -> String {
-    format!("ℹ️  {}", message.blue())
+    format!("{}  {}", icon(Icon::Info), message.blue())
+}
+
+/// Returns a formatted warning message
+///
+/// # Arguments
+/// * `message` - The warning message
+///
+/// # Returns
+/// * A formatted warning message
+pub fn warning_message(message: &str) -> String {
+    format!("{}  {}", icon(Icon::Warning), message.yellow())
+}
+
+/// Returns the line naming the project, account and configuration in use
+///
+/// Unknown values are shown as "(unset)"; a production project is shown in red.
+///
+/// # Arguments
+/// * `project` - The gcloud project
+/// * `account` - The gcloud account
+/// * `configuration` - The gcloud configuration name
+/// * `production` - Whether the project looks like a production project
+///
+/// # Returns
+/// * A formatted context line
+pub fn context_line(
+    project: Option<&str>,
+    account: Option<&str>,
+    configuration: Option<&str>,
+    production: bool,
+) -> String {
+    let unset = "(unset)";
+    let project_name = project.unwrap_or(unset);
+    let project_text = if production {
+        format!("{} (production)", project_name).red().bold()
+    } else {
+        project_name.bright_cyan()
+    };
+
+    format!(
+        "{} {}   {} {}   {} {}",
+        "project:".bright_black(),
+        project_text,
+        "account:".bright_black(),
+        account.unwrap_or(unset).bright_cyan(),
+        "configuration:".bright_black(),
+        configuration.unwrap_or(unset).bright_cyan()
+    )
 }
 
 /// Returns a formatted box with the SSH command
@@ -85,21 +150,106 @@ pub fn info_message(message: &str) -> String {
 /// # Returns
 /// * A formatted box containing the SSH command
 pub fn ssh_command_box(command: &str) -> String {
-    let width = command.len() + 8;
-    let horizontal = "─".repeat(width);
+    ssh_command_box_with_width(command, term_utils::layout_width())
+}
+
+/// Returns the SSH command box followed by the raw command on its own line
+///
+/// The extra line has no styles or box glyphs, so it can be selected and
+/// copied as is.
+///
+/// # Arguments
+/// * `command` - The SSH command
+///
+/// # Returns
+/// * The formatted box and the plain command line
+pub fn ssh_command_box_copyable(command: &str) -> String {
+    format!("{}{}", ssh_command_box(command), command)
+}
+
+/// Returns a formatted box with the SSH command, fitted to a given width
+///
+/// Commands too long for the width are wrapped at spaces, so flags and their
+/// values move to the next line together with the rest of the command.
+///
+/// # Arguments
+/// * `command` - The SSH command
+/// * `width` - Maximum width of the box in columns
+///
+/// # Returns
+/// * A formatted box containing the SSH command
+pub fn ssh_command_box_with_width(command: &str, width: usize) -> String {
+    // Each line is framed as "│   text   │"
+    const FRAME: usize = 8;
+
+    let lines = if term_utils::display_width(command) + FRAME > width {
+        wrap_command(command, width.saturating_sub(FRAME).max(1))
+    } else {
+        vec![command.to_string()]
+    };
+    let inner = lines
+        .iter()
+        .map(|line| term_utils::display_width(line))
+        .max()
+        .unwrap_or(0);
+    let horizontal = "─".repeat(inner + FRAME - 2);
+
+    let body: Vec<String> = lines
+        .iter()
+        .map(|line| {
+            let padding = " ".repeat(inner - term_utils::display_width(line));
+            format!("│   {}{}   │", line.bright_white().bold(), padding)
+        })
+        .collect();
 
     format!(
-        r#"
-┌{}┐
-│   {}   │
-└{}┘
-"#,
+        "\n┌{}┐\n{}\n└{}┘\n",
         horizontal.bright_blue(),
-        command.bright_white().bold(),
+        body.join("\n"),
         horizontal.bright_blue()
     )
 }
 
+/// Wraps a shell command at flag boundaries
+///
+/// A flag and the value following it (e.g. `-J host`) are kept on one line;
+/// segments that are wider than the line on their own are wrapped at spaces.
+fn wrap_command(command: &str, width: usize) -> Vec<String> {
+    let mut segments: Vec<String> = Vec::new();
+    let mut tokens = command.split_whitespace().peekable();
+    while let Some(token) = tokens.next() {
+        let mut segment = token.to_string();
+        if token.starts_with('-') {
+            if let Some(value) = tokens.next_if(|next| !next.starts_with('-')) {
+                segment.push(' ');
+                segment.push_str(value);
+            }
+        }
+        segments.push(segment);
+    }
+
+    let mut lines: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for segment in segments {
+        let needed = term_utils::display_width(&segment) + usize::from(!current.is_empty());
+        if !current.is_empty() && term_utils::display_width(&current) + needed > width {
+            lines.push(std::mem::take(&mut current));
+        }
+        if term_utils::display_width(&segment) > width {
+            lines.extend(term_utils::wrap_text(&segment, width));
+            continue;
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(&segment);
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
 /// Returns a spinner animation frame for progress indication
 ///
 /// # Arguments
@@ -124,22 +274,208 @@ pub fn spinner_frame(frame: usize) -> &'static str {
 /// * `name` - The VM name
 /// * `zone` - The VM zone
 /// * `ip` - The VM IP address, if available
+/// * `ipv6` - The VM IPv6 address, if available
+/// * `nics` - Number of network interfaces, shown when there is more than one
 ///
 /// # Returns
 /// * A formatted VM list item
-pub fn vm_list_item(index: usize, name: &str, zone: &str, ip: Option<&str>) -> String {
-    let ip_display = match ip {
-        Some(ip) => format!("🌐 {}", ip.bright_white()),
-        None => "⚠️  No external IP".bright_black().to_string(),
-    };
+pub fn vm_list_item(
+    index: usize,
+    name: &str,
+    zone: &str,
+    ip: Option<&str>,
+    ipv6: Option<&str>,
+    nics: usize,
+    pinned: bool,
+) -> String {
+    vm_list_fields(
+        index,
+        &[
+            ListField::Name(name),
+            ListField::Zone(zone),
+            ListField::Address {
+                ip,
+                ipv6,
+                nics,
+                pinned,
+            },
+        ],
+    )
+}
 
-    let index_str = format!("[{}]", index + 1);
+/// One field of a VM list item, styled according to what it shows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListField<'a> {
+    /// The VM name
+    Name(&'a str),
+    /// The zone, or `project/zone` in merged listings
+    Zone(&'a str),
+    /// External addresses, with the interface count when there is more than one
+    /// and a pin when the IPv4 address is static
+    Address {
+        ip: Option<&'a str>,
+        ipv6: Option<&'a str>,
+        nics: usize,
+        pinned: bool,
+    },
+    /// Lifecycle status such as `RUNNING`
+    Status(&'a str),
+    /// Machine type name such as `e2-medium`
+    Machine(&'a str),
+    /// Labels as `key=value` pairs
+    Labels(String),
+}
 
-    format!(
-        "{} {} {} {}",
-        index_str.bright_yellow().bold(),
-        name.bright_cyan().bold(),
-        format!("({})", zone).bright_black(),
+/// Returns a VM list item made of the given fields, in order
+///
+/// Empty status, machine type and label fields are left out.
+///
+/// # Arguments
+/// * `index` - The VM index number
+/// * `fields` - The fields to show
+///
+/// # Returns
+/// * A formatted VM list item
+pub fn vm_list_fields(index: usize, fields: &[ListField]) -> String {
+    let mut parts = vec![format!("[{}]", index + 1)
+        .bright_yellow()
+        .bold()
+        .to_string()];
+    for field in fields {
+        let part = match field {
+            ListField::Name(name) => name.bright_cyan().bold().to_string(),
+            ListField::Zone(zone) => format!("({})", zone).bright_black().to_string(),
+            ListField::Address {
+                ip,
+                ipv6,
+                nics,
+                pinned,
+            } => address_field(*ip, *ipv6, *nics, *pinned),
+            ListField::Status("") => continue,
+            ListField::Status(status) => match *status {
+                "RUNNING" => status.green().to_string(),
+                "TERMINATED" | "STOPPED" | "STOPPING" | "SUSPENDED" => status.red().to_string(),
+                _ => status.yellow().to_string(),
+            },
+            ListField::Machine("") => continue,
+            ListField::Machine(machine) => format!("[{}]", machine).bright_black().to_string(),
+            ListField::Labels(labels) if labels.is_empty() => continue,
+            ListField::Labels(labels) => format!("{{{}}}", labels).bright_black().to_string(),
+        };
+        parts.push(part);
+    }
+    parts.join(" ")
+}
+
+/// Formats the external addresses of a list item
+fn address_field(ip: Option<&str>, ipv6: Option<&str>, nics: usize, pinned: bool) -> String {
+    let mut ip_display = match (ip, ipv6) {
+        (Some(ip), Some(ipv6)) => format!(
+            "{} {} {}",
+            icon(Icon::IpAddress),
+            ip.bright_white(),
+            ipv6.bright_white()
+        ),
+        (Some(ip), None) | (None, Some(ip)) => {
+            format!("{} {}", icon(Icon::IpAddress), ip.bright_white())
+        }
+        (None, None) => format!("{}  No external IP", icon(Icon::Warning))
+            .bright_black()
+            .to_string(),
+    };
+    if pinned && ip.is_some() {
+        ip_display = format!("{} {}", ip_display, icon(Icon::StaticIp));
+    }
+
+    if nics > 1 {
+        format!(
+            "{} {}",
+            ip_display,
+            format!("[{} NICs]", nics).bright_black()
+        )
+    } else {
         ip_display
-    )
+    }
+}
+
+/// Returns a sample of every display element, for previewing the current settings
+///
+/// # Returns
+/// * The banner, a section header, VM list items, messages and a command box
+pub fn preview() -> String {
+    let items = [
+        ("web-1", "us-central1-a", Some("34.68.10.21"), None, 1, true),
+        ("batch-stopped", "europe-west1-b", None, None, 1, false),
+        (
+            "db-staging",
+            "us-east1-c",
+            Some("35.196.4.7"),
+            Some("2600:1900:4000::7"),
+            1,
+            false,
+        ),
+        (
+            "fw-appliance",
+            "us-east1-c",
+            Some("35.196.9.1"),
+            None,
+            3,
+            false,
+        ),
+    ];
+
+    let mut sections = vec![main_banner(), section_header("VM SELECTION")];
+    sections.extend(items.iter().enumerate().map(
+        |(index, (name, zone, ip, ipv6, nics, pinned))| {
+            vm_list_item(index, name, zone, *ip, *ipv6, *nics, *pinned)
+        },
+    ));
+    sections.push(String::new());
+    sections.push(success_message("SSH key successfully copied to VM: web-1"));
+    sections.push(info_message("SSH key already authorized on VM: web-1"));
+    sections.push(warning_message("VM does not have an external IP address"));
+    sections.push(ssh_command_box("ssh user@34.68.10.21"));
+    sections.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that the copyable variant ends with the bare command line
+    #[test]
+    fn test_ssh_command_box_copyable() {
+        let command = "ssh deploy@34.68.10.21";
+        let output = ssh_command_box_copyable(command);
+        assert_eq!(output.lines().last(), Some(command));
+        assert!(output.starts_with(&ssh_command_box(command)));
+    }
+
+    /// Test that a command wider than the terminal is wrapped inside the box
+    #[test]
+    fn test_ssh_command_box_wraps_long_command() {
+        let command = format!(
+            "ssh -J bastion.example.com -i ~/.ssh/id_ed25519 {}user@34.68.10.21",
+            "-o ServerAliveInterval=30 ".repeat(6)
+        );
+        assert!(command.len() >= 200);
+
+        let boxed = term_utils::strip_ansi(&ssh_command_box_with_width(&command, 80));
+        let lines: Vec<&str> = boxed.lines().filter(|line| !line.is_empty()).collect();
+        assert!(lines.len() > 3);
+        assert!(lines
+            .iter()
+            .any(|line| line.contains("-J bastion.example.com")));
+        for line in &lines {
+            assert!(
+                term_utils::display_width(line) <= 80,
+                "line too wide: {:?}",
+                line
+            );
+            assert_eq!(
+                term_utils::display_width(line),
+                term_utils::display_width(lines[0])
+            );
+        }
+    }
 }
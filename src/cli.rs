@@ -0,0 +1,1225 @@
+/// This module parses command-line arguments into a structured set of options
+/// that the application flow consults.
+use crate::inventory::InventoryFormat;
+use std::{net::IpAddr, path::PathBuf, time::Duration};
+
+/// What the user asked the tool to do
+#[derive(Debug, Clone)]
+pub enum Invocation {
+    /// Print the help message
+    Help,
+    /// Print version information
+    Version,
+    /// Check for updates
+    Update,
+    /// Run the interactive flow with the given options
+    Run(Box<Options>),
+}
+
+/// How the public key is transferred to the VM
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CopyMethod {
+    /// Try `gcloud compute scp` first and fall back to a remote echo
+    #[default]
+    Auto,
+    /// Upload the key with `gcloud compute scp`
+    Scp,
+    /// Echo the key inside a remote shell command
+    Echo,
+    /// Add the key to the instance's `ssh-keys` metadata
+    Metadata,
+}
+
+impl CopyMethod {
+    /// The name accepted by `--copy-method`
+    pub fn name(self) -> &'static str {
+        match self {
+            CopyMethod::Auto => "auto",
+            CopyMethod::Scp => "scp",
+            CopyMethod::Echo => "echo",
+            CopyMethod::Metadata => "metadata",
+        }
+    }
+}
+
+impl std::str::FromStr for CopyMethod {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "auto" => Ok(CopyMethod::Auto),
+            "scp" => Ok(CopyMethod::Scp),
+            "echo" => Ok(CopyMethod::Echo),
+            "metadata" => Ok(CopyMethod::Metadata),
+            _ => Err(format!(
+                "Invalid copy method '{}': expected auto, scp, echo or metadata",
+                value
+            )),
+        }
+    }
+}
+
+/// The kind of SSH key pair to use and, when missing, generate
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyType {
+    /// `id_rsa`, generated with `gcloud compute ssh-keys create`
+    #[default]
+    Rsa,
+    /// `id_ed25519`, generated with `ssh-keygen`
+    Ed25519,
+    /// `id_ecdsa`, generated with `ssh-keygen`
+    Ecdsa,
+}
+
+impl KeyType {
+    /// The name accepted by `--key-type`, which is also `ssh-keygen -t`'s name
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyType::Rsa => "rsa",
+            KeyType::Ed25519 => "ed25519",
+            KeyType::Ecdsa => "ecdsa",
+        }
+    }
+
+    /// File name of the private key; the public key adds `.pub`
+    pub fn file_name(self) -> &'static str {
+        match self {
+            KeyType::Rsa => "id_rsa",
+            KeyType::Ed25519 => "id_ed25519",
+            KeyType::Ecdsa => "id_ecdsa",
+        }
+    }
+}
+
+impl std::str::FromStr for KeyType {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "rsa" => Ok(KeyType::Rsa),
+            "ed25519" => Ok(KeyType::Ed25519),
+            "ecdsa" => Ok(KeyType::Ecdsa),
+            _ => Err(format!(
+                "Invalid key type '{}': expected rsa, ed25519 or ecdsa",
+                value
+            )),
+        }
+    }
+}
+
+/// What to do when the selected VM has no external IP
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NoExternalIpFallback {
+    /// Stop with an error
+    #[default]
+    Error,
+    /// Connect with `gcloud compute ssh --tunnel-through-iap`
+    Iap,
+    /// Connect to the VM's internal DNS name, as with `--internal-dns`
+    Internal,
+    /// Connect with `gcloud compute ssh`, which picks a route itself
+    GcloudSsh,
+}
+
+impl NoExternalIpFallback {
+    /// The name accepted by `--no-external-ip-fallback`
+    pub fn name(self) -> &'static str {
+        match self {
+            NoExternalIpFallback::Error => "error",
+            NoExternalIpFallback::Iap => "iap",
+            NoExternalIpFallback::Internal => "internal",
+            NoExternalIpFallback::GcloudSsh => "gcloud-ssh",
+        }
+    }
+}
+
+impl std::str::FromStr for NoExternalIpFallback {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "error" => Ok(NoExternalIpFallback::Error),
+            "iap" => Ok(NoExternalIpFallback::Iap),
+            "internal" => Ok(NoExternalIpFallback::Internal),
+            "gcloud-ssh" => Ok(NoExternalIpFallback::GcloudSsh),
+            _ => Err(format!(
+                "Invalid fallback '{}': expected error, iap, internal or gcloud-ssh",
+                value
+            )),
+        }
+    }
+}
+
+/// A field shown for each VM in the selection menu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    /// VM name
+    Name,
+    /// Zone (with the project in merged listings)
+    Zone,
+    /// Lifecycle status
+    Status,
+    /// External addresses
+    Ip,
+    /// Machine type
+    Machine,
+    /// Labels
+    Labels,
+}
+
+impl Column {
+    /// Every column, in the order they are listed in errors
+    pub const ALL: [Column; 6] = [
+        Column::Name,
+        Column::Zone,
+        Column::Status,
+        Column::Ip,
+        Column::Machine,
+        Column::Labels,
+    ];
+
+    /// The columns shown when none are chosen
+    pub const DEFAULT: [Column; 3] = [Column::Name, Column::Zone, Column::Ip];
+
+    /// The name accepted by `--columns`
+    pub fn name(self) -> &'static str {
+        match self {
+            Column::Name => "name",
+            Column::Zone => "zone",
+            Column::Status => "status",
+            Column::Ip => "ip",
+            Column::Machine => "machine",
+            Column::Labels => "labels",
+        }
+    }
+}
+
+/// Parses a list of column names such as `name,status,ip`
+///
+/// # Arguments
+/// * `names` - The column names, in display order
+///
+/// # Returns
+/// * `Result<Vec<Column>, String>` - The columns, or an error naming the valid set
+pub fn parse_columns<S: AsRef<str>>(names: &[S]) -> Result<Vec<Column>, String> {
+    let mut columns = Vec::new();
+    for name in names {
+        let name = name.as_ref().trim();
+        let column = Column::ALL
+            .into_iter()
+            .find(|column| column.name() == name)
+            .ok_or_else(|| {
+                let valid: Vec<&str> = Column::ALL.iter().map(|column| column.name()).collect();
+                format!(
+                    "Invalid column '{}': expected one of {}",
+                    name,
+                    valid.join(", ")
+                )
+            })?;
+        if !columns.contains(&column) {
+            columns.push(column);
+        }
+    }
+    if columns.is_empty() {
+        return Err("No columns given: expected e.g. name,zone,ip".to_string());
+    }
+    Ok(columns)
+}
+
+/// How the connection details are printed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InfoFormat {
+    /// Colored labels and a framed command box
+    #[default]
+    Pretty,
+    /// Unstyled `Label: value` lines
+    Plain,
+    /// Quoted `export NAME='value'` lines for `eval`
+    Env,
+}
+
+impl std::str::FromStr for InfoFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "pretty" => Ok(InfoFormat::Pretty),
+            "plain" => Ok(InfoFormat::Plain),
+            "env" => Ok(InfoFormat::Env),
+            _ => Err(format!(
+                "Invalid info format '{}': expected pretty, plain or env",
+                value
+            )),
+        }
+    }
+}
+
+/// What to do with the selected VM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Copy the SSH key and print the connection command (the default flow)
+    CopyKey,
+    /// Connect through `gcloud compute ssh`
+    Connect,
+    /// Copy the SSH key, then connect with plain `ssh`
+    CopyAndConnect,
+    /// Only print the connection command
+    PrintCommand,
+    /// Run a command on the VM (prompted for when empty)
+    Run(String),
+    /// Attach to the VM's serial console
+    SerialConsole,
+    /// Start the VM
+    Start,
+    /// Stop the VM
+    Stop,
+    /// Delete the VM
+    Delete,
+    /// Forward a local port to a VM port through an IAP tunnel
+    Tunnel { local: u16, remote: u16 },
+    /// Write a `~/.ssh/config` entry that reaches the VM through IAP
+    WriteSshConfig,
+}
+
+impl Action {
+    /// Label shown in the action menu
+    pub fn label(&self) -> &'static str {
+        match self {
+            Action::CopyKey => "Copy key & print command",
+            Action::Connect => "Connect (gcloud compute ssh)",
+            Action::CopyAndConnect => "Copy key & connect",
+            Action::PrintCommand => "Print command",
+            Action::Run(_) => "Run command",
+            Action::SerialConsole => "Serial console",
+            Action::Start => "Start VM",
+            Action::Stop => "Stop VM",
+            Action::Delete => "Delete VM",
+            Action::Tunnel { .. } => "Open IAP tunnel",
+            Action::WriteSshConfig => "Write IAP entry to ~/.ssh/config",
+        }
+    }
+
+    /// The command-line arguments that select this action again
+    ///
+    /// The default key copy is selected with `--no-menu`, which skips the menu
+    /// and copies the key.
+    pub fn flag_args(&self) -> Vec<String> {
+        let flag = match self {
+            Action::CopyKey => "--no-menu",
+            Action::Connect => "--connect",
+            Action::CopyAndConnect => "--copy-and-connect",
+            Action::PrintCommand => "--print-only",
+            Action::SerialConsole => "--serial",
+            Action::Start => "--start",
+            Action::Stop => "--stop",
+            Action::Delete => "--delete",
+            Action::WriteSshConfig => "--write-ssh-config",
+            Action::Run(command) => return vec!["--run".to_string(), command.clone()],
+            Action::Tunnel { local, remote } => {
+                return vec!["--tunnel".to_string(), format!("{}:{}", local, remote)]
+            }
+        };
+        vec![flag.to_string()]
+    }
+}
+
+/// Options collected from the command line
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Static hosts file to read instead of listing VMs through gcloud
+    pub hosts_file: Option<PathBuf>,
+
+    /// Replace emoji with ASCII markers
+    pub no_emoji: bool,
+
+    /// Disable ANSI colors and styles
+    pub no_color: bool,
+
+    /// Skip the banner and welcome line and go straight to the first section
+    pub no_welcome: bool,
+
+    /// Skip typing effects, spinners and progress bars
+    pub no_animations: bool,
+
+    /// How the public key is transferred to the VM
+    pub copy_method: CopyMethod,
+
+    /// Check that the copied key is authorized on the VM afterwards
+    pub verify: bool,
+
+    /// How long a key pushed to instance metadata stays valid
+    pub expire_after: Option<Duration>,
+
+    /// VM to star or unstar as a favorite
+    pub favorite: Option<String>,
+
+    /// Only list favorite VMs
+    pub favorites_only: bool,
+
+    /// Named gcloud configuration passed to every gcloud call
+    pub configuration: Option<String>,
+
+    /// gcloud binary to run instead of `gcloud` from PATH
+    pub gcloud_bin: Option<PathBuf>,
+
+    /// Skip confirmation prompts
+    pub yes: bool,
+
+    /// VM name, or part of one, to use instead of showing the selection menu
+    pub vm: Option<String>,
+
+    /// List every instance, ignoring the configured filter and limit
+    pub all: bool,
+
+    /// Maximum number of instances to list
+    pub limit: Option<u32>,
+
+    /// Action to run on the selected VM instead of showing the action menu
+    pub action: Option<Action>,
+
+    /// Use the linear copy-key flow instead of the action menu
+    pub no_menu: bool,
+
+    /// Allow destructive actions together with `--yes`
+    pub force: bool,
+
+    /// Print diagnostics to stderr
+    pub verbose: bool,
+
+    /// Only list VMs in this zone (full name, abbreviation or unique prefix)
+    pub zone: Option<String>,
+
+    /// Only list VMs whose name contains this text, ignoring case
+    pub name_filter: Option<String>,
+
+    /// Remote username, overriding hosts-file users and instance labels
+    pub user: Option<String>,
+
+    /// Directory holding the SSH key pair, instead of `~/.ssh`
+    pub ssh_dir: Option<PathBuf>,
+
+    /// Render sample output with the current display settings and exit
+    pub preview: bool,
+
+    /// Print the effective settings and where each came from, then exit
+    pub print_config: bool,
+
+    /// Connect over the external IPv6 address instead of IPv4
+    pub ipv6: bool,
+
+    /// Connect to the VM's internal DNS name instead of an external address
+    pub internal_dns: bool,
+
+    /// Pick a static external IP over an ephemeral one when the VM has both
+    pub prefer_static_ip: bool,
+
+    /// Connect through a matching `Host` entry in `~/.ssh/config` when there is one
+    pub use_ssh_config: bool,
+
+    /// How to connect when the selected VM has no external IP
+    pub no_external_ip_fallback: Option<NoExternalIpFallback>,
+
+    /// Treat the selected VM as Windows (RDP instructions, no key copy)
+    pub windows: bool,
+
+    /// Print the listed instances as an Ansible inventory instead of running the flow
+    pub inventory: Option<InventoryFormat>,
+
+    /// Named profile from the configuration file
+    pub profile: Option<String>,
+
+    /// Fields shown for each VM in the selection menu, in order
+    pub columns: Option<Vec<Column>>,
+
+    /// Saved listing query from the configuration file
+    pub query: Option<String>,
+
+    /// gcloud project passed to every gcloud call
+    pub project: Option<String>,
+
+    /// Fetch and show the VM's SSH host key fingerprints
+    pub show_host_key: bool,
+
+    /// Glob matching the public keys to choose from when copying a key
+    pub identity_glob: Option<String>,
+
+    /// The kind of key pair to use and generate
+    pub key_type: KeyType,
+
+    /// Time the startup phases instead of running the flow (hidden)
+    pub profile_startup: bool,
+
+    /// Layout width in columns (0 detects the terminal width)
+    pub width: Option<usize>,
+
+    /// Fail instead of generating an SSH key when none exists
+    pub no_key_gen: bool,
+
+    /// Only list VMs and show commands; refuse anything that changes state
+    pub read_only: bool,
+
+    /// File to write the raw SSH command line to instead of printing the box
+    pub write_command: Option<PathBuf>,
+
+    /// Check SSH access to the listed VMs before selection
+    pub check_access: bool,
+
+    /// Service account impersonated by every gcloud call
+    pub impersonate: Option<String>,
+
+    /// How the connection details are printed
+    pub info_format: InfoFormat,
+
+    /// Network interface to connect through (0 is `nic0`)
+    pub nic: Option<usize>,
+
+    /// Projects whose VMs are listed together
+    pub projects: Vec<String>,
+
+    /// List VMs from every project the account can see
+    pub all_projects: bool,
+
+    /// Local address ssh binds to (`ssh -b`)
+    pub bind: Option<IpAddr>,
+
+    /// Select several VMs and run the action on each
+    pub multi: bool,
+
+    /// Print the bare SSH command under the box for copying
+    pub copyable: bool,
+
+    /// Pass the VM's details to the spawned ssh/gcloud process as environment variables
+    pub export_env: bool,
+
+    /// Page long remote command and serial console output
+    pub pager: bool,
+
+    /// Print the listed VMs as JSON and exit
+    pub json: bool,
+
+    /// Redraw the VM list periodically instead of running the flow
+    pub watch: bool,
+
+    /// Seconds between `--watch` refreshes
+    pub watch_interval: Option<u64>,
+
+    /// Skip the background check for a newer release at startup
+    pub no_update_check: bool,
+
+    /// Ring the terminal bell when the flow finishes
+    pub bell: bool,
+
+    /// Print a command that selects the same VM and action again
+    pub show_reconnect: bool,
+
+    /// Most gcloud processes run at once when listing several projects
+    pub max_concurrency: Option<usize>,
+}
+
+impl Options {
+    /// The fields shown for each VM in the selection menu
+    pub fn list_columns(&self) -> &[Column] {
+        self.columns.as_deref().unwrap_or(&Column::DEFAULT)
+    }
+
+    /// The time between `--watch` refreshes
+    pub fn watch_every(&self) -> Duration {
+        Duration::from_secs(self.watch_interval.unwrap_or(DEFAULT_WATCH_INTERVAL_SECS))
+    }
+}
+
+/// Seconds between `--watch` refreshes unless `--watch-interval` is given
+pub const DEFAULT_WATCH_INTERVAL_SECS: u64 = 5;
+
+/// A group of options listed together in `--help`
+#[derive(Debug, Clone, Copy)]
+pub struct HelpSection {
+    /// Section heading
+    pub title: &'static str,
+    /// Pairs of flag usage and description
+    pub entries: &'static [(&'static str, &'static str)],
+}
+
+/// Every user-facing option, grouped for `--help`
+pub const HELP_SECTIONS: &[HelpSection] = &[
+    HelpSection {
+        title: "GENERAL",
+        entries: &[
+            ("-h, --help", "Print this help message"),
+            ("-v, --version", "Print version information"),
+            ("--update", "Check for updates and install them"),
+            (
+                "--no-update-check",
+                "Don't look for a newer release in the background at startup",
+            ),
+            ("-y, --yes", "Skip confirmation prompts"),
+            (
+                "--verbose",
+                "Print diagnostics (commands run, with keys redacted) to stderr",
+            ),
+            (
+                "--profile <NAME>",
+                "Use a [profiles.<NAME>] bundle from the config file",
+            ),
+            (
+                "--configuration <NAME>",
+                "Use a named gcloud configuration for every gcloud call",
+            ),
+            (
+                "--gcloud-bin <PATH>",
+                "Run this gcloud binary instead of the one on PATH (also GCLOUD_SSH_GCLOUD_BIN)",
+            ),
+            (
+                "--impersonate <SA_EMAIL>",
+                "Impersonate a service account for every gcloud call",
+            ),
+            (
+                "--print-config",
+                "Print the effective settings and the source of each (JSON with --json)",
+            ),
+        ],
+    },
+    HelpSection {
+        title: "SELECTION",
+        entries: &[
+            (
+                "--vm <NAME>",
+                "Use the VM whose name matches (exact, or a unique part of it)",
+            ),
+            (
+                "--all",
+                "List all instances, ignoring the configured filter and limit",
+            ),
+            ("--limit <N>", "List at most N instances"),
+            (
+                "--columns <COLUMNS>",
+                "Menu fields in order, from name,zone,status,ip,machine,labels (default name,zone,ip)",
+            ),
+            (
+                "--project <ID>",
+                "Run every gcloud call in this project instead of the active one",
+            ),
+            (
+                "--projects <ID,ID,...>",
+                "List VMs from several projects in one menu",
+            ),
+            (
+                "--all-projects",
+                "List VMs from every project the account can see",
+            ),
+            (
+                "--max-concurrency <N>",
+                "List at most N projects at once (default 4)",
+            ),
+            (
+                "--zone <ZONE>",
+                "Only list VMs in a zone (accepts abbreviations like uc1a)",
+            ),
+            (
+                "--filter <TEXT>",
+                "Only list VMs whose name contains TEXT (case-insensitive)",
+            ),
+            (
+                "--query <NAME>",
+                "Apply the filters saved as [queries.<NAME>] in the config file",
+            ),
+            (
+                "--hosts-file <PATH>",
+                "Select from a name,host,user CSV or JSON file instead of gcloud",
+            ),
+            (
+                "--favorite <NAME>",
+                "Star or unstar a VM so it is listed first",
+            ),
+            ("--favorites-only", "Only list starred VMs"),
+            (
+                "--multi",
+                "Select several VMs to copy the key to, --start, --stop or --delete",
+            ),
+            (
+                "--check-access",
+                "Check SSH access first and mark inaccessible VMs with a lock",
+            ),
+        ],
+    },
+    HelpSection {
+        title: "ACTIONS",
+        entries: &[
+            (
+                "--connect",
+                "Connect with gcloud compute ssh after selection",
+            ),
+            (
+                "--copy-and-connect",
+                "Copy the SSH key, then connect with ssh",
+            ),
+            ("--print-only", "Only print the SSH command (no key copy)"),
+            ("--run <CMD>", "Run a command on the selected VM"),
+            ("--serial", "Attach to the VM's serial console"),
+            (
+                "--tunnel <LOCAL:REMOTE>",
+                "Forward a local port to a VM port through IAP until Ctrl-C",
+            ),
+            (
+                "--write-ssh-config",
+                "Add a Host entry to ~/.ssh/config that tunnels through IAP",
+            ),
+            ("--start", "Start the selected VM"),
+            ("--stop", "Stop the selected VM (asks for confirmation)"),
+            (
+                "--delete",
+                "Delete the selected VM (type its name to confirm)",
+            ),
+            ("--force", "Allow --delete together with --yes"),
+            (
+                "--read-only",
+                "Only list VMs and print commands; refuse key, metadata, VM and local state changes",
+            ),
+            (
+                "--no-menu",
+                "Skip the action menu and copy the key, then print the command",
+            ),
+        ],
+    },
+    HelpSection {
+        title: "KEYS AND CONNECTION",
+        entries: &[
+            (
+                "--ssh-dir <PATH>",
+                "Directory holding the SSH key pair (default: ~/.ssh)",
+            ),
+            (
+                "--identity-glob <PATTERN>",
+                "Pick the public key to copy from a glob (e.g. '~/.ssh/id_*_gcp*')",
+            ),
+            (
+                "--key-type <TYPE>",
+                "Key pair to use or generate: rsa (default), ed25519 or ecdsa",
+            ),
+            (
+                "--no-key-gen",
+                "Fail instead of generating an SSH key when none exists",
+            ),
+            (
+                "--copy-method <METHOD>",
+                "How the public key is sent to the VM: auto, scp, echo or metadata",
+            ),
+            (
+                "--verify",
+                "After copying the key, check that the VM actually authorizes it",
+            ),
+            (
+                "--copy-metadata-key",
+                "Add the key to the instance's ssh-keys metadata (same as --copy-method metadata)",
+            ),
+            (
+                "--expire-after <DURATION>",
+                "With --copy-method metadata, revoke the key after e.g. 90m, 8h or 7d",
+            ),
+            (
+                "--user <USER>",
+                "Remote username (overrides the ssh-user label)",
+            ),
+            ("--ipv6", "Connect over the VM's external IPv6 address"),
+            (
+                "--prefer-static-ip",
+                "Connect to a static (reserved) external IP when the VM also has ephemeral ones",
+            ),
+            (
+                "--internal-dns",
+                "Connect to <name>.<zone>.c.<project>.internal (over a VPN or inside the VPC)",
+            ),
+            (
+                "--no-external-ip-fallback <FALLBACK>",
+                "Without an external IP: error, iap, internal or gcloud-ssh (default error)",
+            ),
+            (
+                "--use-ssh-config",
+                "Use `ssh <alias>` when a Host in ~/.ssh/config matches the VM's name or address",
+            ),
+            (
+                "--windows",
+                "Treat the VM as Windows: show RDP instructions and skip the key copy",
+            ),
+            (
+                "--export-env",
+                "Set GCLOUD_VM_NAME, GCLOUD_VM_ZONE and GCLOUD_VM_IP for --connect/--run",
+            ),
+            (
+                "--bind <ADDR>",
+                "Bind ssh to a local address on multi-homed machines (ssh -b)",
+            ),
+            (
+                "--nic <N>",
+                "Connect through network interface N (0 is nic0) on multi-NIC VMs",
+            ),
+            (
+                "--show-host-key",
+                "Show the VM's SSH host key fingerprints to verify the first connection",
+            ),
+        ],
+    },
+    HelpSection {
+        title: "OUTPUT",
+        entries: &[
+            ("--no-emoji", "Use plain ASCII markers instead of emoji"),
+            (
+                "--no-welcome",
+                "Skip the banner and welcome line (also ui.show_welcome = false)",
+            ),
+            (
+                "--no-animations",
+                "Skip typing effects, spinners and progress bars (also under NO_COLOR or CI)",
+            ),
+            (
+                "--bell",
+                "Ring the terminal bell when done (also ui.bell_on_complete = true)",
+            ),
+            (
+                "--show-reconnect",
+                "Afterwards, print a gcloud-ssh command that repeats this selection",
+            ),
+            ("--no-color", "Disable colors (also NO_COLOR=1)"),
+            (
+                "--width <N>",
+                "Lay output out for N columns (0 detects the terminal width)",
+            ),
+            (
+                "--preview",
+                "Show sample output with the current display settings and exit",
+            ),
+            (
+                "--copyable",
+                "Also print the bare SSH command under the box (automatic when piped)",
+            ),
+            (
+                "--pager",
+                "Page --run and serial console output with $PAGER (or less) on a terminal",
+            ),
+            (
+                "--no-pager",
+                "Print output directly (overrides an earlier --pager)",
+            ),
+            (
+                "--info-format <FORMAT>",
+                "Print connection details as pretty, plain or env (export lines for eval)",
+            ),
+            (
+                "--write-command <PATH>",
+                "Write the raw SSH command to a file (or /dev/stdout) instead of the box",
+            ),
+            ("--json", "Print the listed VMs as a JSON array and exit"),
+            (
+                "--watch",
+                "Redraw the VM list with statuses every few seconds until Ctrl-C",
+            ),
+            (
+                "--watch-interval <SECONDS>",
+                "Seconds between --watch refreshes (default 5)",
+            ),
+            (
+                "--ansible",
+                "Print the listed VMs as an INI Ansible inventory and exit",
+            ),
+            (
+                "--ansible-yaml",
+                "Print the listed VMs as a YAML Ansible inventory and exit",
+            ),
+        ],
+    },
+];
+
+/// Parses a `LOCAL:REMOTE` port pair for `--tunnel`
+fn parse_tunnel(value: &str) -> Result<Action, String> {
+    let invalid = || format!("Invalid tunnel '{}': expected LOCAL:REMOTE ports", value);
+    let (local, remote) = value.split_once(':').ok_or_else(invalid)?;
+    Ok(Action::Tunnel {
+        local: local.trim().parse().map_err(|_| invalid())?,
+        remote: remote.trim().parse().map_err(|_| invalid())?,
+    })
+}
+
+/// Checks that an `--impersonate` value looks like a service account email
+fn parse_service_account(value: &str) -> Result<String, String> {
+    let valid = match value.split_once('@') {
+        Some((account, domain)) => {
+            !account.is_empty()
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !domain.contains('@')
+                && !value.chars().any(char::is_whitespace)
+        }
+        None => false,
+    };
+    if !valid {
+        return Err(format!(
+            "Invalid service account '{}': expected an email such as name@project.iam.gserviceaccount.com",
+            value
+        ));
+    }
+    Ok(value.to_string())
+}
+
+/// Parses a positive duration such as `90s`, `30m`, `8h`, `7d` or `2w`
+///
+/// # Arguments
+/// * `value` - A whole number followed by a single unit letter
+///
+/// # Returns
+/// * `Option<Duration>` - The duration, or None for a malformed or zero value
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let unit = value.chars().last()?;
+    let seconds = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    let amount: u64 = value[..value.len() - 1].parse().ok()?;
+    amount
+        .checked_mul(seconds)
+        .filter(|total| *total > 0)
+        .map(Duration::from_secs)
+}
+
+/// Takes the value following a flag, failing if it is missing
+fn take_value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+    args.next()
+        .filter(|value| !value.starts_with("--"))
+        .ok_or_else(|| format!("Option {} requires a value", flag))
+}
+
+/// Parses command-line arguments (without the program name)
+///
+/// # Arguments
+/// * `args` - The arguments to parse
+///
+/// # Returns
+/// * `Result<Invocation, String>` - The requested invocation or a usage error
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Invocation, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Invocation::Help),
+            "-v" | "--version" => return Ok(Invocation::Version),
+            "--update" => return Ok(Invocation::Update),
+            "--hosts-file" => {
+                options.hosts_file = Some(PathBuf::from(take_value(&mut args, &arg)?));
+            }
+            "--ssh-dir" => options.ssh_dir = Some(PathBuf::from(take_value(&mut args, &arg)?)),
+            "-y" | "--yes" => options.yes = true,
+            "--verbose" => options.verbose = true,
+            "--vm" => options.vm = Some(take_value(&mut args, &arg)?),
+            "--all" => options.all = true,
+            "--user" => options.user = Some(take_value(&mut args, &arg)?),
+            "--zone" => options.zone = Some(take_value(&mut args, &arg)?),
+            "--filter" => options.name_filter = Some(take_value(&mut args, &arg)?),
+            "--width" => {
+                let value = take_value(&mut args, &arg)?;
+                options.width = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid width '{}': expected a number", value))?,
+                );
+            }
+            "--bind" => {
+                let value = take_value(&mut args, &arg)?;
+                options.bind = Some(value.parse().map_err(|_| {
+                    format!("Invalid bind address '{}': expected an IP address", value)
+                })?);
+            }
+            "--project" => options.project = Some(take_value(&mut args, &arg)?),
+            "--projects" => {
+                let value = take_value(&mut args, &arg)?;
+                options.projects = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|project| !project.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                if options.projects.is_empty() {
+                    return Err(format!("Invalid projects '{}': expected ID,ID,...", value));
+                }
+            }
+            "--all-projects" => options.all_projects = true,
+            "--multi" => options.multi = true,
+            "--copyable" => options.copyable = true,
+            "--export-env" => options.export_env = true,
+            "--pager" => options.pager = true,
+            "--no-pager" => options.pager = false,
+            "--json" => options.json = true,
+            "--watch" => options.watch = true,
+            "--no-external-ip-fallback" => {
+                options.no_external_ip_fallback = Some(take_value(&mut args, &arg)?.parse()?);
+            }
+            "--watch-interval" => {
+                let value = take_value(&mut args, &arg)?;
+                options.watch_interval = Some(
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|seconds: &u64| *seconds > 0)
+                        .ok_or_else(|| {
+                            format!("Invalid interval '{}': expected a positive number", value)
+                        })?,
+                );
+            }
+            "--no-update-check" => options.no_update_check = true,
+            "--nic" => {
+                let value = take_value(&mut args, &arg)?;
+                options.nic = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid NIC '{}': expected a number", value))?,
+                );
+            }
+            "--max-concurrency" => {
+                let value = take_value(&mut args, &arg)?;
+                options.max_concurrency = Some(
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|n: &usize| *n > 0)
+                        .ok_or_else(|| {
+                            format!("Invalid concurrency '{}': expected a number from 1", value)
+                        })?,
+                );
+            }
+            "--limit" => {
+                let value = take_value(&mut args, &arg)?;
+                options.limit = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid limit '{}': expected a number", value))?,
+                );
+            }
+            "--no-emoji" => options.no_emoji = true,
+            "--no-welcome" => options.no_welcome = true,
+            "--no-animations" => options.no_animations = true,
+            "--bell" => options.bell = true,
+            "--show-reconnect" => options.show_reconnect = true,
+            "--no-color" => options.no_color = true,
+            "--preview" => options.preview = true,
+            "--print-config" => options.print_config = true,
+            "--ipv6" => options.ipv6 = true,
+            "--prefer-static-ip" => options.prefer_static_ip = true,
+            "--internal-dns" => options.internal_dns = true,
+            "--use-ssh-config" => options.use_ssh_config = true,
+            "--windows" => options.windows = true,
+            "--show-host-key" => options.show_host_key = true,
+            "--check-access" => options.check_access = true,
+            "--profile-startup" => options.profile_startup = true,
+            "--no-key-gen" => options.no_key_gen = true,
+            "--read-only" => options.read_only = true,
+            "--write-command" => {
+                options.write_command = Some(PathBuf::from(take_value(&mut args, &arg)?));
+            }
+            "--identity-glob" => options.identity_glob = Some(take_value(&mut args, &arg)?),
+            "--key-type" => options.key_type = take_value(&mut args, &arg)?.parse()?,
+            "--ansible" => options.inventory = Some(InventoryFormat::Ini),
+            "--ansible-yaml" => options.inventory = Some(InventoryFormat::Yaml),
+            "--copy-metadata-key" => options.copy_method = CopyMethod::Metadata,
+            "--copy-method" => options.copy_method = take_value(&mut args, &arg)?.parse()?,
+            "--expire-after" => {
+                let value = take_value(&mut args, &arg)?;
+                options.expire_after = Some(parse_duration(&value).ok_or_else(|| {
+                    format!(
+                        "Invalid duration '{}': expected a number with s, m, h, d or w (e.g. 8h)",
+                        value
+                    )
+                })?);
+            }
+            "--info-format" => options.info_format = take_value(&mut args, &arg)?.parse()?,
+            "--favorite" => options.favorite = Some(take_value(&mut args, &arg)?),
+            "--favorites-only" => options.favorites_only = true,
+            "--connect" => options.action = Some(Action::Connect),
+            "--copy-and-connect" => options.action = Some(Action::CopyAndConnect),
+            "--print-only" => options.action = Some(Action::PrintCommand),
+            "--run" => options.action = Some(Action::Run(take_value(&mut args, &arg)?)),
+            "--serial" => options.action = Some(Action::SerialConsole),
+            "--tunnel" => options.action = Some(parse_tunnel(&take_value(&mut args, &arg)?)?),
+            "--write-ssh-config" => options.action = Some(Action::WriteSshConfig),
+            "--start" => options.action = Some(Action::Start),
+            "--stop" => options.action = Some(Action::Stop),
+            "--delete" => options.action = Some(Action::Delete),
+            "--force" => options.force = true,
+            "--no-menu" => options.no_menu = true,
+            "--verify" => options.verify = true,
+            "--profile" => options.profile = Some(take_value(&mut args, &arg)?),
+            "--query" => options.query = Some(take_value(&mut args, &arg)?),
+            "--columns" => {
+                let value = take_value(&mut args, &arg)?;
+                let names: Vec<&str> = value.split(',').collect();
+                options.columns = Some(parse_columns(&names)?);
+            }
+            "--configuration" => options.configuration = Some(take_value(&mut args, &arg)?),
+            "--gcloud-bin" => {
+                options.gcloud_bin = Some(PathBuf::from(take_value(&mut args, &arg)?));
+            }
+            "--impersonate" => {
+                options.impersonate = Some(parse_service_account(&take_value(&mut args, &arg)?)?);
+            }
+            _ => return Err(format!("Unknown option: {}", arg)),
+        }
+    }
+
+    Ok(Invocation::Run(Box::new(options)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_options(args: &[&str]) -> Result<Options, String> {
+        match parse(args.iter().map(|arg| arg.to_string()))? {
+            Invocation::Run(options) => Ok(*options),
+            other => Err(format!("unexpected invocation: {:?}", other)),
+        }
+    }
+
+    /// Test that value-taking flags reject a missing value
+    #[test]
+    fn test_missing_value() {
+        assert!(parse_options(&["--hosts-file"]).is_err());
+    }
+
+    /// Test that action flags select the matching action
+    #[test]
+    fn test_action_flags() {
+        let options = parse_options(&["--run", "uptime"]).unwrap();
+        assert_eq!(options.action, Some(Action::Run("uptime".to_string())));
+        assert_eq!(parse_options(&[]).unwrap().action, None);
+
+        let options = parse_options(&["--tunnel", "15432:5432"]).unwrap();
+        assert_eq!(
+            options.action,
+            Some(Action::Tunnel {
+                local: 15432,
+                remote: 5432
+            })
+        );
+        assert!(parse_options(&["--tunnel", "5432"]).is_err());
+    }
+
+    /// Test that every option documented in --help is accepted by the parser
+    #[test]
+    fn test_help_lists_known_flags() {
+        for section in HELP_SECTIONS {
+            for (usage, _) in section.entries {
+                let mut words = usage.split(", ").last().unwrap().split(' ');
+                let mut args = vec![words.next().unwrap()];
+                if words.next().is_some() {
+                    args.push(match usage {
+                        _ if usage.contains("<METHOD>") => "scp",
+                        _ if usage.contains("<FORMAT>") => "env",
+                        _ if usage.contains("<ADDR>") => "10.0.0.5",
+                        _ if usage.contains("<LOCAL:REMOTE>") => "5432:5432",
+                        _ if usage.contains("<SA_EMAIL>") => "ops@acme.iam.gserviceaccount.com",
+                        _ if usage.contains("<DURATION>") => "8h",
+                        _ if usage.contains("<COLUMNS>") => "name,status",
+                        _ if usage.contains("<FALLBACK>") => "iap",
+                        _ if usage.contains("<TYPE>") => "ed25519",
+                        _ => "1",
+                    });
+                }
+                assert!(
+                    parse(args.iter().map(|arg| arg.to_string())).is_ok(),
+                    "{}",
+                    usage
+                );
+            }
+        }
+    }
+
+    /// Test that --impersonate only accepts email-shaped values
+    #[test]
+    fn test_impersonate() {
+        let sa = "deployer@acme-prod.iam.gserviceaccount.com";
+        let options = parse_options(&["--impersonate", sa]).unwrap();
+        assert_eq!(options.impersonate.as_deref(), Some(sa));
+
+        for invalid in ["deployer", "@acme.com", "deployer@localhost", "a@b@c.com"] {
+            assert!(
+                parse_options(&["--impersonate", invalid]).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    /// Test that columns keep their order and unknown names list the valid set
+    #[test]
+    fn test_columns() {
+        let options = parse_options(&["--columns", "status,name,ip,name"]).unwrap();
+        assert_eq!(
+            options.columns,
+            Some(vec![Column::Status, Column::Name, Column::Ip])
+        );
+
+        let err = parse_options(&["--columns", "name,region"]).unwrap_err();
+        assert_eq!(
+            err,
+            "Invalid column 'region': expected one of name, zone, status, ip, machine, labels"
+        );
+        assert!(parse_options(&["--columns", ""]).is_err());
+    }
+
+    /// Test that --project sets the project for every gcloud call, unlike --projects
+    #[test]
+    fn test_project() {
+        assert_eq!(parse_options(&[]).unwrap().project, None);
+
+        let options = parse_options(&["--project", "acme-dev"]).unwrap();
+        assert_eq!(options.project.as_deref(), Some("acme-dev"));
+        assert!(options.projects.is_empty());
+    }
+
+    /// Test that key expiry durations accept each unit and reject malformed values
+    #[test]
+    fn test_expire_after() {
+        let options = parse_options(&["--expire-after", "8h"]).unwrap();
+        assert_eq!(options.expire_after, Some(Duration::from_secs(8 * 3600)));
+        assert_eq!(parse_duration("90s"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("2w"), Some(Duration::from_secs(14 * 86_400)));
+
+        for invalid in ["8", "h", "0d", "-1h", "1.5h", "8 hours"] {
+            assert!(parse_duration(invalid).is_none(), "{}", invalid);
+        }
+    }
+
+    /// Test that the key type defaults to RSA and names the matching key files
+    #[test]
+    fn test_key_type() {
+        assert_eq!(parse_options(&[]).unwrap().key_type, KeyType::Rsa);
+
+        let options = parse_options(&["--key-type", "ed25519"]).unwrap();
+        assert_eq!(options.key_type, KeyType::Ed25519);
+        assert_eq!(options.key_type.file_name(), "id_ed25519");
+        assert!(parse_options(&["--key-type", "dsa"]).is_err());
+    }
+
+    /// Test that the watch interval defaults to five seconds and must be positive
+    #[test]
+    fn test_watch_interval() {
+        let options = parse_options(&["--watch"]).unwrap();
+        assert!(options.watch);
+        assert_eq!(options.watch_every(), Duration::from_secs(5));
+
+        let options = parse_options(&["--watch", "--watch-interval", "30"]).unwrap();
+        assert_eq!(options.watch_every(), Duration::from_secs(30));
+        assert!(parse_options(&["--watch-interval", "0"]).is_err());
+    }
+
+    /// Test that the hosts file path is captured
+    #[test]
+    fn test_hosts_file() {
+        let options = parse_options(&["--hosts-file", "hosts.csv"]).unwrap();
+        assert_eq!(options.hosts_file, Some(PathBuf::from("hosts.csv")));
+    }
+}
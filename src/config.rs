@@ -1,5 +1,5 @@
-/// This module provides configuration options for the application
-/// These settings control the appearance and behavior of the terminal interface
+//! This module provides configuration options for the application
+//! These settings control the appearance and behavior of the terminal interface
 
 /// Application title displayed in various places
 pub const APP_TITLE: &str = "Google Cloud SSH Manager";
@@ -15,7 +15,8 @@ pub const AUTHOR: &str = "Your Name";
 
 /// Configuration for terminal animations
 pub mod animations {
-    /// Whether to enable animations
+    /// Whether to enable animations; `--no-animations`, `NO_COLOR` and `CI`
+    /// turn them off at runtime (see [`crate::settings::Settings::animations`])
     pub const ENABLED: bool = true;
 
     /// Typing effect speed (milliseconds per character)
@@ -116,4 +117,13 @@ pub mod emojis {
 
     /// Zone/location icon
     pub const ZONE: &str = "📍";
+
+    /// Favorite VM marker
+    pub const FAVORITE: &str = "⭐";
+
+    /// Marker for VMs the user cannot SSH into
+    pub const LOCKED: &str = "🔒";
+
+    /// Marker for static (reserved) external IPs
+    pub const STATIC_IP: &str = "📌";
 }
@@ -0,0 +1,378 @@
+/// This module reports the settings actually in effect after flags, the
+/// environment, the project-local file, the selected profile and the user
+/// configuration file have been layered, together with where each value came from.
+use crate::{
+    cli::Options,
+    gcloud,
+    settings::{self, Settings},
+    term_utils,
+    user_config::{LocalConfig, UserConfig},
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::{env, fmt};
+
+/// Where an effective value came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    /// A command-line flag
+    Flag,
+    /// An environment variable
+    Env,
+    /// The nearest `.gcloud-ssh.toml`
+    LocalFile,
+    /// The profile selected with `--profile`
+    Profile,
+    /// The user configuration file
+    File,
+    /// Detection of the terminal's capabilities
+    Terminal,
+    /// The built-in default
+    Default,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Source::Flag => "flag",
+            Source::Env => "env",
+            Source::LocalFile => "local file",
+            Source::Profile => "profile",
+            Source::File => "file",
+            Source::Terminal => "terminal",
+            Source::Default => "default",
+        })
+    }
+}
+
+/// One effective setting
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Entry {
+    /// Dotted name, e.g. `listing.default_filter`
+    pub key: String,
+
+    /// The value in effect (`null` when unset)
+    pub value: Value,
+
+    /// Where the value came from
+    pub source: Source,
+}
+
+/// The configuration at each stage of resolution
+pub struct Layers<'a> {
+    /// Options exactly as given on the command line
+    pub flags: &'a Options,
+
+    /// Options after the local file and profile filled them in
+    pub options: &'a Options,
+
+    /// The configuration file as loaded, before the profile and local file
+    pub file: &'a UserConfig,
+
+    /// The configuration after the profile and local file were applied
+    pub resolved: &'a UserConfig,
+
+    /// The project-local file, if one was found
+    pub local: Option<&'a LocalConfig>,
+
+    /// The installed runtime settings
+    pub settings: &'a Settings,
+}
+
+/// Lists every effective setting with its source
+///
+/// # Arguments
+/// * `layers` - The configuration at each stage of resolution
+///
+/// # Returns
+/// * `Vec<Entry>` - Command-line level values first, then the configuration file keys
+pub fn entries(layers: &Layers) -> Vec<Entry> {
+    let Layers {
+        flags,
+        options,
+        local,
+        settings,
+        ..
+    } = layers;
+    let local_value =
+        |field: fn(&LocalConfig) -> &Option<String>| local.and_then(|local| field(local).clone());
+    let filled = |given: &Option<String>, resolved: &Option<String>, local: Option<String>| {
+        if given.is_some() {
+            Source::Flag
+        } else if resolved.is_some() && *resolved == local {
+            Source::LocalFile
+        } else if resolved.is_some() {
+            Source::Profile
+        } else {
+            Source::Default
+        }
+    };
+    let flag_or_default = |given: bool| {
+        if given {
+            Source::Flag
+        } else {
+            Source::Default
+        }
+    };
+
+    let mut entries = vec![
+        entry(
+            "project",
+            &options.project,
+            filled(
+                &flags.project,
+                &options.project,
+                local_value(|local| &local.project),
+            ),
+        ),
+        entry(
+            "zone",
+            &options.zone,
+            filled(&flags.zone, &options.zone, local_value(|local| &local.zone)),
+        ),
+        entry(
+            "user",
+            &options.user,
+            filled(&flags.user, &options.user, local_value(|local| &local.user)),
+        ),
+        entry(
+            "configuration",
+            &options.configuration,
+            filled(&flags.configuration, &options.configuration, None),
+        ),
+        entry(
+            "gcloud_bin",
+            &gcloud::resolve_program(
+                flags.gcloud_bin.as_deref(),
+                env::var_os(gcloud::GCLOUD_BIN_ENV).as_deref(),
+            ),
+            if flags.gcloud_bin.is_some() {
+                Source::Flag
+            } else if env::var_os(gcloud::GCLOUD_BIN_ENV).is_some_and(|value| !value.is_empty()) {
+                Source::Env
+            } else {
+                Source::Default
+            },
+        ),
+        entry(
+            "profile",
+            &options.profile,
+            flag_or_default(flags.profile.is_some()),
+        ),
+        entry(
+            "query",
+            &options.query,
+            flag_or_default(flags.query.is_some()),
+        ),
+        entry(
+            "yes",
+            &options.yes,
+            if flags.yes {
+                Source::Flag
+            } else if options.yes {
+                Source::Profile
+            } else {
+                Source::Default
+            },
+        ),
+        entry(
+            "emoji",
+            &settings.emoji,
+            if flags.no_emoji {
+                Source::Flag
+            } else if options.no_emoji {
+                Source::Profile
+            } else if settings::env_flag(settings::NO_EMOJI_ENV) {
+                Source::Env
+            } else if !term_utils::rich_terminal() {
+                Source::Terminal
+            } else {
+                Source::Default
+            },
+        ),
+        entry(
+            "color",
+            &settings.color,
+            if flags.no_color {
+                Source::Flag
+            } else if env::var_os(settings::NO_COLOR_ENV).is_some_and(|value| !value.is_empty()) {
+                Source::Env
+            } else if !term_utils::rich_terminal() {
+                Source::Terminal
+            } else {
+                Source::Default
+            },
+        ),
+        entry(
+            "animations",
+            &settings.animations,
+            if flags.no_animations {
+                Source::Flag
+            } else if env::var_os(settings::NO_COLOR_ENV).is_some_and(|value| !value.is_empty())
+                || settings::env_flag(settings::CI_ENV)
+            {
+                Source::Env
+            } else {
+                Source::Default
+            },
+        ),
+        entry("verbose", &settings.verbose, flag_or_default(flags.verbose)),
+        entry(
+            "width",
+            &settings.width,
+            flag_or_default(flags.width.is_some()),
+        ),
+        entry(
+            "read_only",
+            &settings.read_only,
+            flag_or_default(flags.read_only),
+        ),
+    ];
+    entries.extend(file_entries(layers));
+    entries
+}
+
+/// Lists the configuration file keys, telling file values from defaults and overrides
+fn file_entries(layers: &Layers) -> Vec<Entry> {
+    let defaults = flatten(&UserConfig::default());
+    let file = flatten(layers.file);
+    let local_filter = layers.local.is_some_and(|local| local.filter.is_some());
+
+    flatten(layers.resolved)
+        .into_iter()
+        .map(|(key, value)| {
+            let source = if file.iter().any(|(k, v)| *k == key && *v != value) {
+                // Only the listing filter can come from the local file
+                if key == "listing.default_filter" && local_filter {
+                    Source::LocalFile
+                } else {
+                    Source::Profile
+                }
+            } else if defaults.iter().any(|(k, v)| *k == key && *v == value) {
+                Source::Default
+            } else {
+                Source::File
+            };
+            Entry { key, value, source }
+        })
+        .collect()
+}
+
+/// Flattens the configuration into `section.key` pairs, leaving out the profiles and queries
+fn flatten(config: &UserConfig) -> Vec<(String, Value)> {
+    let Ok(Value::Object(sections)) = serde_json::to_value(config) else {
+        return Vec::new();
+    };
+
+    let mut pairs = Vec::new();
+    for (section, values) in sections {
+        if section == "profiles" || section == "queries" {
+            continue;
+        }
+        if let Value::Object(values) = values {
+            for (key, value) in values {
+                pairs.push((format!("{}.{}", section, key), value));
+            }
+        }
+    }
+    pairs
+}
+
+/// Builds an entry from any serializable value
+fn entry(key: &str, value: &impl Serialize, source: Source) -> Entry {
+    Entry {
+        key: key.to_string(),
+        value: serde_json::to_value(value).unwrap_or(Value::Null),
+        source,
+    }
+}
+
+/// Renders entries as aligned `key = value  (source)` lines
+///
+/// # Arguments
+/// * `entries` - The entries to show
+///
+/// # Returns
+/// * `String` - One line per entry
+pub fn render(entries: &[Entry]) -> String {
+    let width = entries
+        .iter()
+        .map(|entry| entry.key.len())
+        .max()
+        .unwrap_or(0);
+    entries
+        .iter()
+        .map(|entry| {
+            let value = match &entry.value {
+                Value::Null => "(unset)".to_string(),
+                value => value.to_string(),
+            };
+            format!(
+                "{:width$} = {}  ({})\n",
+                entry.key,
+                value,
+                entry.source,
+                width = width
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that each layer is named as the source of the values it set
+    #[test]
+    fn test_entries_sources() {
+        let file = UserConfig::parse(
+            "[listing]\nlimit = 10\n\n[profiles.ops]\nfilter = \"labels.team=ops\"\nconfiguration = \"ops\"\n",
+        )
+        .unwrap();
+        let local = LocalConfig {
+            zone: Some("us-central1-a".to_string()),
+            ..Default::default()
+        };
+        let flags = Options {
+            project: Some("acme-dev".to_string()),
+            profile: Some("ops".to_string()),
+            ..Default::default()
+        };
+
+        let mut options = flags.clone();
+        let mut resolved = file.clone();
+        resolved.resolve(Some(&local), &mut options).unwrap();
+        let entries = entries(&Layers {
+            flags: &flags,
+            options: &options,
+            file: &file,
+            resolved: &resolved,
+            local: Some(&local),
+            settings: &Settings::DEFAULT,
+        });
+        let source = |key: &str| {
+            entries
+                .iter()
+                .find(|entry| entry.key == key)
+                .map(|entry| entry.source)
+                .unwrap()
+        };
+
+        assert_eq!(source("project"), Source::Flag);
+        assert_eq!(source("zone"), Source::LocalFile);
+        assert_eq!(source("configuration"), Source::Profile);
+        assert_eq!(source("user"), Source::Default);
+        assert_eq!(source("listing.default_filter"), Source::Profile);
+        assert_eq!(source("listing.limit"), Source::File);
+        assert_eq!(source("keys.generate"), Source::Default);
+        assert!(!entries
+            .iter()
+            .any(|entry| entry.key.starts_with("profiles")));
+
+        let text = render(&entries);
+        assert!(text.contains("listing.limit"));
+        assert!(text.contains("= 10  (file)"));
+        assert!(text.contains("= (unset)  (default)"));
+    }
+}
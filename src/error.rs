@@ -0,0 +1,313 @@
+/// This module defines the application's error type. Each variant has a stable
+/// `kind()` name for machine-readable output and an `exit_code()`, so scripts
+/// can tell failures apart without parsing messages.
+use std::io;
+use thiserror::Error;
+
+/// Exit code for failures without a more specific code
+pub const EXIT_FAILURE: i32 = 1;
+
+/// Exit code for invalid flags, configuration or missing prerequisites
+pub const EXIT_USAGE: i32 = 2;
+
+/// Exit code when nothing matched (no VMs, no such VM, no matching key)
+pub const EXIT_NOT_FOUND: i32 = 3;
+
+/// Exit code when gcloud or an operation on the VM failed
+pub const EXIT_OPERATION_FAILED: i32 = 4;
+
+/// Exit code when the user cancelled, matching the shell's code for Ctrl-C
+pub const EXIT_CANCELLED: i32 = 130;
+
+/// Errors reported by the application
+#[derive(Error, Debug)]
+pub enum AppError {
+    /// `gcloud` could not create the missing SSH key pair
+    #[error("No SSH key found and failed to generate one: {0}")]
+    SshKeyGeneration(String),
+
+    /// No SSH key exists and key generation is turned off
+    #[error("No SSH key found in {0} and key generation is disabled")]
+    KeyGenerationDisabled(String),
+
+    /// Listing instances through gcloud failed
+    #[error("Failed to list VM instances: {0}")]
+    VmListing(String),
+
+    /// The listing succeeded but returned no instances
+    #[error("No VM instances found in the active project")]
+    NoVmsFound,
+
+    /// A VM could not be chosen (menu failure, unknown zone, no terminal)
+    #[error("Failed to select VM: {0}")]
+    VmSelection(String),
+
+    /// The user dismissed the selection menu
+    #[error("VM selection was cancelled")]
+    SelectionCancelled,
+
+    /// Copying the public key to the VM failed
+    #[error("Failed to copy SSH key to VM: {0}")]
+    KeyCopy(String),
+
+    /// `--verify` did not find the copied key on the VM
+    #[error("The SSH key could not be verified on {0}")]
+    KeyNotVerified(String),
+
+    /// `--filter` matched no instance names
+    #[error("No VM instances have a name containing '{0}'")]
+    NoVmsMatchFilter(String),
+
+    /// `--zone` matched a zone without instances
+    #[error("No VM instances found in zone {0}")]
+    NoVmsInZone(String),
+
+    /// `--expire-after` was combined with a copy method that appends to authorized_keys
+    #[error(
+        "--expire-after only works with --copy-method metadata; keys copied with {0} cannot expire"
+    )]
+    ExpiryNeedsMetadata(String),
+
+    /// No public key matched `--identity-glob`
+    #[error("No public key matches '{0}'")]
+    NoIdentityMatch(String),
+
+    /// `--vm` matched no instance
+    #[error("No VM named '{0}' was found")]
+    VmNotFound(String),
+
+    /// `--vm` matched several instances where only one can be used
+    #[error("'{0}' matches several VMs ({1}); use a longer name or --zone")]
+    AmbiguousVm(String, String),
+
+    /// `--favorites-only` was given but nothing is starred
+    #[error("No favorite VMs found; star one with --favorite <NAME>")]
+    NoFavorites,
+
+    /// `--configuration` named a configuration gcloud doesn't know
+    #[error("Unknown gcloud configuration '{0}' (available: {1})")]
+    UnknownConfiguration(String, String),
+
+    /// Starting the VM failed
+    #[error("Failed to start VM: {0}")]
+    VmStart(String),
+
+    /// Stopping the VM failed
+    #[error("Failed to stop VM: {0}")]
+    VmStop(String),
+
+    /// Deleting the VM failed
+    #[error("Failed to delete VM: {0}")]
+    VmDelete(String),
+
+    /// Reading or updating the instance's `ssh-keys` metadata failed
+    #[error("Failed to update instance metadata: {0}")]
+    MetadataUpdate(String),
+
+    /// `--write-command` could not write its file
+    #[error("Could not write the SSH command to {0}: {1}")]
+    WriteCommand(String, String),
+
+    /// The IAP tunnel could not be opened or ended with an error
+    #[error("IAP tunnel failed: {0}")]
+    Tunnel(String),
+
+    /// SSH was asked for on a VM whose operating system isn't reached over SSH
+    #[error("{0} runs {1}, which isn't reached over SSH; use --print-only for RDP instructions")]
+    UnsupportedOs(String, String),
+
+    /// The VM has no external address to connect to
+    #[error("VM does not have an external IP address (of the requested family)")]
+    NoExternalIp,
+
+    /// `--internal-dns` was given but the DNS name can't be built
+    #[error("Cannot build the VM's internal DNS name: {0}; connect by IP without --internal-dns")]
+    InternalDns(String),
+
+    /// `--nic` named an interface the VM doesn't have
+    #[error("VM {0} has no network interface nic{1} (it has {2})")]
+    NoSuchNic(String, usize, usize),
+
+    /// The gcloud binary from `--gcloud-bin`, `GCLOUD_SSH_GCLOUD_BIN` or PATH doesn't exist
+    #[error(
+        "gcloud was not found at '{0}': install the Google Cloud SDK or pass --gcloud-bin <PATH>"
+    )]
+    GcloudNotFound(String),
+
+    /// Neither `--ssh-dir` nor a home directory is available
+    #[error(
+        "Could not find the SSH directory: set HOME (or USERPROFILE) or pass --ssh-dir <PATH>"
+    )]
+    NoSshDir,
+
+    /// `--multi` was combined with an action that can't run in bulk
+    #[error("--multi supports copying keys, --start, --stop and --delete, not '{0}'")]
+    UnsupportedBulkAction(String),
+
+    /// A large bulk selection needs explicit confirmation
+    #[error(
+        "{0} VMs selected, above the bulk threshold of {1}; pass --force with --yes to proceed"
+    )]
+    BulkNotConfirmed(usize, usize),
+
+    /// Some VMs in a bulk action failed
+    #[error("{0} of {1} VMs failed")]
+    BulkFailed(usize, usize),
+
+    /// gcloud's credentials expired and re-authenticating was declined or impossible
+    #[error("gcloud needs to re-authenticate: {0}")]
+    AuthExpired(String),
+
+    /// An operation that changes something was attempted under `--read-only`
+    #[error("Not allowed in read-only mode: {0}")]
+    ReadOnly(String),
+
+    /// A local file or process operation failed
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    /// JSON from gcloud or a local file could not be read or written
+    #[error("Invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+impl AppError {
+    /// Returns a stable snake_case name for the error, for machine-readable output
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AppError::SshKeyGeneration(_) => "ssh_key_generation",
+            AppError::KeyGenerationDisabled(_) => "key_generation_disabled",
+            AppError::VmListing(_) => "vm_listing",
+            AppError::NoVmsFound => "no_vms_found",
+            AppError::VmSelection(_) => "vm_selection",
+            AppError::SelectionCancelled => "selection_cancelled",
+            AppError::KeyCopy(_) => "key_copy",
+            AppError::KeyNotVerified(_) => "key_not_verified",
+            AppError::NoVmsInZone(_) => "no_vms_in_zone",
+            AppError::NoVmsMatchFilter(_) => "no_vms_match_filter",
+            AppError::ExpiryNeedsMetadata(_) => "expiry_needs_metadata",
+            AppError::NoIdentityMatch(_) => "no_identity_match",
+            AppError::VmNotFound(_) => "vm_not_found",
+            AppError::AmbiguousVm(_, _) => "ambiguous_vm",
+            AppError::NoFavorites => "no_favorites",
+            AppError::UnknownConfiguration(_, _) => "unknown_configuration",
+            AppError::VmStart(_) => "vm_start",
+            AppError::VmStop(_) => "vm_stop",
+            AppError::VmDelete(_) => "vm_delete",
+            AppError::MetadataUpdate(_) => "metadata_update",
+            AppError::WriteCommand(_, _) => "write_command",
+            AppError::Tunnel(_) => "tunnel",
+            AppError::UnsupportedOs(_, _) => "unsupported_os",
+            AppError::NoExternalIp => "no_external_ip",
+            AppError::InternalDns(_) => "internal_dns",
+            AppError::NoSuchNic(_, _, _) => "no_such_nic",
+            AppError::GcloudNotFound(_) => "gcloud_not_found",
+            AppError::NoSshDir => "no_ssh_dir",
+            AppError::UnsupportedBulkAction(_) => "unsupported_bulk_action",
+            AppError::BulkNotConfirmed(_, _) => "bulk_not_confirmed",
+            AppError::BulkFailed(_, _) => "bulk_failed",
+            AppError::AuthExpired(_) => "auth_expired",
+            AppError::ReadOnly(_) => "read_only",
+            AppError::Io(_) => "io",
+            AppError::Json(_) => "json",
+        }
+    }
+
+    /// Returns the process exit code for the error (see the `EXIT_*` constants)
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            AppError::KeyGenerationDisabled(_)
+            | AppError::UnknownConfiguration(_, _)
+            | AppError::NoSshDir
+            | AppError::GcloudNotFound(_)
+            | AppError::UnsupportedBulkAction(_)
+            | AppError::BulkNotConfirmed(_, _)
+            | AppError::ReadOnly(_)
+            | AppError::AuthExpired(_)
+            | AppError::ExpiryNeedsMetadata(_)
+            | AppError::UnsupportedOs(_, _) => EXIT_USAGE,
+
+            AppError::NoVmsFound
+            | AppError::NoVmsInZone(_)
+            | AppError::NoVmsMatchFilter(_)
+            | AppError::NoIdentityMatch(_)
+            | AppError::VmNotFound(_)
+            | AppError::AmbiguousVm(_, _)
+            | AppError::NoFavorites
+            | AppError::NoExternalIp
+            | AppError::InternalDns(_)
+            | AppError::NoSuchNic(_, _, _) => EXIT_NOT_FOUND,
+
+            AppError::SshKeyGeneration(_)
+            | AppError::VmListing(_)
+            | AppError::KeyCopy(_)
+            | AppError::KeyNotVerified(_)
+            | AppError::VmStart(_)
+            | AppError::VmStop(_)
+            | AppError::VmDelete(_)
+            | AppError::MetadataUpdate(_)
+            | AppError::Tunnel(_)
+            | AppError::BulkFailed(_, _) => EXIT_OPERATION_FAILED,
+
+            AppError::SelectionCancelled => EXIT_CANCELLED,
+
+            AppError::VmSelection(_)
+            | AppError::WriteCommand(_, _)
+            | AppError::Io(_)
+            | AppError::Json(_) => EXIT_FAILURE,
+        }
+    }
+}
+
+/// Returns the exit code for any error, using the first [`AppError`] in its chain
+///
+/// An [`AppError`] carried inside an `io::Error` (as the read-only guard on
+/// process helpers does) counts too.
+///
+/// # Arguments
+/// * `error` - The error that ended the run
+///
+/// # Returns
+/// * `i32` - The matching exit code, or [`EXIT_FAILURE`] for other errors
+pub fn exit_code(error: &anyhow::Error) -> i32 {
+    error
+        .chain()
+        .find_map(|cause| {
+            cause.downcast_ref::<AppError>().or_else(|| {
+                cause
+                    .downcast_ref::<io::Error>()
+                    .and_then(io::Error::get_ref)
+                    .and_then(|inner| inner.downcast_ref::<AppError>())
+            })
+        })
+        .map_or(EXIT_FAILURE, AppError::exit_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    /// Test that kinds are stable names and exit codes survive added context
+    #[test]
+    fn test_kind_and_exit_code() {
+        let error = AppError::VmNotFound("web".to_string());
+        assert_eq!(error.kind(), "vm_not_found");
+        assert_eq!(error.exit_code(), EXIT_NOT_FOUND);
+
+        let wrapped = Err::<(), _>(AppError::SelectionCancelled)
+            .context("Failed to select VM")
+            .unwrap_err();
+        assert_eq!(exit_code(&wrapped), EXIT_CANCELLED);
+        assert_eq!(exit_code(&anyhow::anyhow!("other")), EXIT_FAILURE);
+
+        let json: AppError = serde_json::from_str::<u32>("x").unwrap_err().into();
+        assert_eq!(json.kind(), "json");
+
+        let wrapped = io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            AppError::ReadOnly("gcloud compute instances".to_string()),
+        );
+        assert_eq!(exit_code(&wrapped.into()), EXIT_USAGE);
+    }
+}
@@ -0,0 +1,91 @@
+/// This module manages the list of starred VMs that are shown at the top of
+/// the selection menu, stored per project in `favorites.json`.
+use crate::storage;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// File name of the favorites store inside the configuration directory
+pub const FAVORITES_FILE: &str = "favorites.json";
+
+/// Starred VM names, keyed by project
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Favorites {
+    #[serde(flatten)]
+    projects: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl Favorites {
+    /// Loads favorites from the configuration directory
+    ///
+    /// # Returns
+    /// * `Result<Favorites>` - The stored favorites (empty if none) or error
+    pub fn load() -> Result<Self> {
+        storage::load_json(&storage::config_file(FAVORITES_FILE)?)
+    }
+
+    /// Saves favorites to the configuration directory
+    ///
+    /// # Returns
+    /// * `Result<()>` - Success or error information
+    pub fn save(&self) -> Result<()> {
+        storage::save_json(&storage::config_file(FAVORITES_FILE)?, self)
+    }
+
+    /// Checks whether a VM is starred in a project
+    ///
+    /// # Arguments
+    /// * `project` - The project the VM belongs to
+    /// * `name` - The VM name
+    pub fn contains(&self, project: &str, name: &str) -> bool {
+        self.projects
+            .get(project)
+            .is_some_and(|names| names.contains(name))
+    }
+
+    /// Returns the favorite VM names for a project
+    ///
+    /// # Arguments
+    /// * `project` - The project to look up
+    pub fn names(&self, project: &str) -> BTreeSet<String> {
+        self.projects.get(project).cloned().unwrap_or_default()
+    }
+
+    /// Stars a VM, or removes the star if it already has one
+    ///
+    /// # Arguments
+    /// * `project` - The project the VM belongs to
+    /// * `name` - The VM name
+    ///
+    /// # Returns
+    /// * `bool` - True if the VM is now a favorite
+    pub fn toggle(&mut self, project: &str, name: &str) -> bool {
+        let names = self.projects.entry(project.to_string()).or_default();
+        let added = names.insert(name.to_string());
+        if !added {
+            names.remove(name);
+            if names.is_empty() {
+                self.projects.remove(project);
+            }
+        }
+        added
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that toggling adds and removes a favorite within its project only
+    #[test]
+    fn test_toggle_is_scoped_by_project() {
+        let mut favorites = Favorites::default();
+        assert!(favorites.toggle("prod", "web-1"));
+        assert!(favorites.contains("prod", "web-1"));
+        assert!(!favorites.contains("staging", "web-1"));
+
+        assert!(!favorites.toggle("prod", "web-1"));
+        assert!(!favorites.contains("prod", "web-1"));
+        assert_eq!(favorites, Favorites::default());
+    }
+}
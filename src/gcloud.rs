@@ -0,0 +1,1055 @@
+/// This module builds the `gcloud` invocations used by the application, so
+/// flags that apply to every call (such as the named configuration) are added
+/// in one place.
+use crate::{cli, error::AppError, logging, settings, terminal_fx::LiveSpinner};
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    ffi::OsStr,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Output, Stdio},
+    sync::RwLock,
+    thread,
+};
+
+/// Environment variable naming the gcloud binary, as `--gcloud-bin` does
+pub const GCLOUD_BIN_ENV: &str = "GCLOUD_SSH_GCLOUD_BIN";
+
+/// The binary run when none is configured, looked up on PATH
+pub const DEFAULT_PROGRAM: &str = "gcloud";
+
+/// Flags applied to every gcloud invocation
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GlobalFlags {
+    /// Named gcloud configuration (`--configuration`)
+    pub configuration: Option<String>,
+
+    /// Project to operate on (`--project`)
+    pub project: Option<String>,
+
+    /// Service account to impersonate (`--impersonate-service-account`)
+    pub impersonate: Option<String>,
+}
+
+impl GlobalFlags {
+    /// Collects the global flags given on the command line
+    ///
+    /// # Arguments
+    /// * `options` - Parsed command-line options
+    pub fn from_options(options: &cli::Options) -> Self {
+        GlobalFlags {
+            configuration: options.configuration.clone(),
+            project: options.project.clone(),
+            impersonate: options.impersonate.clone(),
+        }
+    }
+
+    /// Returns the flags as gcloud arguments
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(configuration) = &self.configuration {
+            args.push(format!("--configuration={}", configuration));
+        }
+        if let Some(project) = &self.project {
+            args.push(format!("--project={}", project));
+        }
+        if let Some(service_account) = &self.impersonate {
+            args.push(format!("--impersonate-service-account={}", service_account));
+        }
+        args
+    }
+}
+
+/// Flags applied to every gcloud invocation, installed once at startup
+static GLOBAL_FLAGS: RwLock<GlobalFlags> = RwLock::new(GlobalFlags {
+    configuration: None,
+    project: None,
+    impersonate: None,
+});
+
+/// Sets the flags applied to every gcloud invocation
+///
+/// # Arguments
+/// * `flags` - The global flags
+pub fn set_global_flags(flags: GlobalFlags) {
+    if let Ok(mut current) = GLOBAL_FLAGS.write() {
+        *current = flags;
+    }
+}
+
+/// Scopes every later gcloud invocation to a project, replacing any `--project` flag
+///
+/// # Arguments
+/// * `project` - The project ID
+pub fn set_project(project: &str) {
+    if let Ok(mut current) = GLOBAL_FLAGS.write() {
+        current.project = Some(project.to_string());
+    }
+}
+
+/// The gcloud binary every invocation runs, installed once at startup
+static PROGRAM: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Sets the gcloud binary every invocation runs
+///
+/// # Arguments
+/// * `program` - A path, or a bare name looked up on PATH
+pub fn set_program(program: PathBuf) {
+    if let Ok(mut current) = PROGRAM.write() {
+        *current = Some(program);
+    }
+}
+
+/// Returns the gcloud binary every invocation runs
+///
+/// # Returns
+/// * `PathBuf` - The installed binary, or `gcloud` from PATH
+pub fn program() -> PathBuf {
+    PROGRAM
+        .read()
+        .ok()
+        .and_then(|program| program.clone())
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_PROGRAM))
+}
+
+/// Chooses the gcloud binary: `--gcloud-bin`, then `GCLOUD_SSH_GCLOUD_BIN`, then `gcloud`
+///
+/// # Arguments
+/// * `flag` - The path given with `--gcloud-bin`, if any
+/// * `env_value` - The value of `GCLOUD_SSH_GCLOUD_BIN`, if set
+///
+/// # Returns
+/// * `PathBuf` - The binary to run
+pub fn resolve_program(flag: Option<&Path>, env_value: Option<&OsStr>) -> PathBuf {
+    flag.map(Path::to_path_buf)
+        .or_else(|| {
+            env_value
+                .filter(|value| !value.is_empty())
+                .map(PathBuf::from)
+        })
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_PROGRAM))
+}
+
+/// Finds the file a gcloud binary refers to
+///
+/// A path with a directory in it must name an existing file; a bare name is
+/// searched for in each PATH directory, as the shell would.
+///
+/// # Arguments
+/// * `program` - The configured binary
+/// * `path` - The value of `PATH`, if set
+///
+/// # Returns
+/// * `Option<PathBuf>` - The file that would run, or None if there is none
+pub fn locate_program(program: &Path, path: Option<&OsStr>) -> Option<PathBuf> {
+    // Windows runs gcloud through its .cmd wrapper
+    let suffixes: &[&str] = if cfg!(windows) {
+        &["", ".cmd", ".exe", ".bat"]
+    } else {
+        &[""]
+    };
+    let candidates = |base: PathBuf| {
+        suffixes
+            .iter()
+            .map(|suffix| {
+                let mut candidate = base.clone().into_os_string();
+                candidate.push(suffix);
+                PathBuf::from(candidate)
+            })
+            .collect::<Vec<_>>()
+    };
+
+    if program.components().count() > 1 {
+        return candidates(program.to_path_buf())
+            .into_iter()
+            .find(|candidate| candidate.is_file());
+    }
+    env::split_paths(path?)
+        .flat_map(|dir| candidates(dir.join(program)))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Builds a gcloud invocation on top of the global flags
+///
+/// The arguments are kept as strings so the exact command line can be checked
+/// before anything is run. A command that changes something is marked with
+/// [`GcloudCommand::mutating`], and [`GcloudCommand::build`] refuses it under
+/// `--read-only`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GcloudCommand {
+    /// Flags shared by every invocation
+    globals: GlobalFlags,
+    /// Subcommand and its arguments
+    args: Vec<String>,
+    /// Whether running the command changes something
+    mutating: bool,
+}
+
+impl GcloudCommand {
+    /// Starts a command with the installed global flags
+    pub fn new() -> Self {
+        let globals = GLOBAL_FLAGS
+            .read()
+            .map(|flags| flags.clone())
+            .unwrap_or_default();
+        Self::with_globals(globals)
+    }
+
+    /// Starts a command with explicit global flags
+    ///
+    /// # Arguments
+    /// * `globals` - The flags shared by every invocation
+    pub fn with_globals(globals: GlobalFlags) -> Self {
+        GcloudCommand {
+            globals,
+            args: Vec::new(),
+            mutating: false,
+        }
+    }
+
+    /// Marks the command as one that changes something, so `--read-only` refuses it
+    pub fn mutating(mut self) -> Self {
+        self.mutating = true;
+        self
+    }
+
+    /// Appends one argument
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Appends several arguments
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Adds `--zone <ZONE>`
+    pub fn zone(self, zone: &str) -> Self {
+        self.args(["--zone", zone])
+    }
+
+    /// Runs this command in a project, replacing the global `--project`
+    pub fn project(mut self, project: &str) -> Self {
+        self.globals.project = Some(project.to_string());
+        self
+    }
+
+    /// Returns the full argument vector: global flags, then the subcommand
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = self.globals.args();
+        args.extend(self.args.iter().cloned());
+        args
+    }
+
+    /// Returns the program followed by the full argument vector, for showing to the user
+    pub fn command_line(&self) -> Vec<String> {
+        let mut line = vec![program().to_string_lossy().into_owned()];
+        line.extend(self.to_args());
+        line
+    }
+
+    /// Creates the process to run
+    ///
+    /// This is the one place gcloud calls are checked against `--read-only`.
+    ///
+    /// # Returns
+    /// * `Result<Command, AppError>` - The command, or [`AppError::ReadOnly`] for a
+    ///   mutating command in read-only mode
+    pub fn build(&self) -> Result<Command, AppError> {
+        if self.mutating {
+            let subcommand: Vec<&str> = self
+                .args
+                .iter()
+                .map(String::as_str)
+                .filter(|arg| !arg.starts_with("--"))
+                .take(3)
+                .collect();
+            settings::ensure_writable(&format!("gcloud {}", subcommand.join(" ")))?;
+        }
+        let mut command = Command::new(program());
+        command.args(self.to_args());
+        Ok(command)
+    }
+}
+
+/// Lists the IDs of every project the account can see
+///
+/// # Returns
+/// * `Result<Vec<String>>` - The project IDs or error
+pub fn projects() -> Result<Vec<String>> {
+    let output = capture(command().args(["projects", "list", "--format=value(projectId)"]))
+        .context("Failed to run gcloud")?;
+
+    if !output.status.success() {
+        bail!("Failed to list projects: {}", error_message(&output.stderr));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .filter(|project| !project.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Checks whether the installed gcloud accepts a flag on a subcommand
+///
+/// Older SDK releases lack some flags, so the subcommand's `--help` is searched
+/// for it rather than guessing from the version number.
+///
+/// # Arguments
+/// * `subcommand` - The subcommand, e.g. `["compute", "start-iap-tunnel"]`
+/// * `flag` - The flag, e.g. `--listen-on-stdin`
+///
+/// # Returns
+/// * `Result<bool>` - Whether the help lists the flag, or an error if it couldn't be read
+pub fn accepts_flag(subcommand: &[&str], flag: &str) -> Result<bool> {
+    let output =
+        capture(command().args(subcommand).arg("--help")).context("Failed to run gcloud")?;
+
+    if !output.status.success() {
+        bail!(
+            "Failed to read the help of gcloud {}: {}",
+            subcommand.join(" "),
+            error_message(&output.stderr)
+        );
+    }
+
+    Ok(help_lists_flag(
+        &String::from_utf8_lossy(&output.stdout),
+        flag,
+    ))
+}
+
+/// Returns true when help text documents a flag, not merely a longer one it prefixes
+fn help_lists_flag(help: &str, flag: &str) -> bool {
+    help.match_indices(flag).any(|(start, _)| {
+        let before = help[..start].chars().next_back();
+        let after = help[start + flag.len()..].chars().next();
+        !before.is_some_and(|c| c.is_alphanumeric() || c == '-')
+            && !after.is_some_and(|c| c.is_alphanumeric() || c == '-')
+    })
+}
+
+/// Reserved external addresses already looked up, keyed by project (None for the active one)
+static RESERVED_ADDRESSES: RwLock<BTreeMap<Option<String>, BTreeSet<String>>> =
+    RwLock::new(BTreeMap::new());
+
+/// Lists the reserved external addresses of a project
+///
+/// An external IP found here is static; any other is ephemeral. Each project
+/// is looked up once per run, so `--watch` refreshes reuse the first answer.
+///
+/// # Arguments
+/// * `project` - The project to look in, or None for the active one
+///
+/// # Returns
+/// * `Result<BTreeSet<String>>` - The reserved addresses or error
+pub fn reserved_addresses(project: Option<&str>) -> Result<BTreeSet<String>> {
+    let key = project.map(str::to_string);
+    if let Some(reserved) = RESERVED_ADDRESSES
+        .read()
+        .ok()
+        .and_then(|cache| cache.get(&key).cloned())
+    {
+        return Ok(reserved);
+    }
+
+    let mut gcloud = GcloudCommand::new().args([
+        "compute",
+        "addresses",
+        "list",
+        "--filter=addressType=EXTERNAL",
+        "--format=value(address)",
+    ]);
+    if let Some(project) = project {
+        gcloud = gcloud.project(project);
+    }
+    let output = capture(&mut gcloud.build()?).context("Failed to run gcloud")?;
+
+    if !output.status.success() {
+        bail!(
+            "Failed to list reserved addresses: {}",
+            error_message(&output.stderr)
+        );
+    }
+
+    let reserved: BTreeSet<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .filter(|address| !address.is_empty())
+        .map(str::to_string)
+        .collect();
+    if let Ok(mut cache) = RESERVED_ADDRESSES.write() {
+        cache.insert(key, reserved.clone());
+    }
+    Ok(reserved)
+}
+
+/// Creates a `gcloud` command with the global flags already applied
+///
+/// The command is never refused under `--read-only`, so it is only for calls
+/// that read; build a [`GcloudCommand`] marked as mutating for anything else.
+///
+/// # Returns
+/// * `Command` - The command, ready for subcommand arguments
+pub fn command() -> Command {
+    let mut command = Command::new(program());
+    command.args(GcloudCommand::new().to_args());
+    command
+}
+
+// gcloud is run in one of two ways. Calls whose stdout is parsed (listings,
+// `describe`, `--format=json`) use `capture`. Calls that change something, or
+// that may ask a question first (enabling an API, creating OS Login keys), use
+// `attach` or `attach_reading_stdout` so gcloud's prompts reach the user.
+// `--read-only` is enforced when a mutating command is built (see
+// `GcloudCommand::build`), whichever of these runs it.
+
+/// Runs a command whose output is parsed, capturing stdout and stderr
+///
+/// stdin is closed, so a prompt fails straight away instead of hanging on an
+/// answer nobody can see to give.
+///
+/// # Arguments
+/// * `command` - The command to run
+///
+/// # Returns
+/// * `io::Result<Output>` - The captured output, or an error if it couldn't start
+pub fn capture(command: &mut Command) -> io::Result<Output> {
+    logging::command(command);
+    command.stdin(Stdio::null()).output()
+}
+
+/// Runs a command attached to the terminal, so its prompts and output reach the user
+///
+/// # Arguments
+/// * `command` - The command to run
+///
+/// # Returns
+/// * `io::Result<ExitStatus>` - The exit status; gcloud has already printed any error
+pub fn attach(command: &mut Command) -> io::Result<ExitStatus> {
+    logging::command(command);
+    command
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+}
+
+/// Runs a command that may prompt but whose stdout is still needed
+///
+/// stdin and stderr stay attached to the terminal (gcloud prompts on stderr),
+/// and only stdout is captured.
+///
+/// # Arguments
+/// * `command` - The command to run
+///
+/// # Returns
+/// * `io::Result<Output>` - The exit status and stdout; stderr is always empty
+pub fn attach_reading_stdout(command: &mut Command) -> io::Result<Output> {
+    logging::command(command);
+    command
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+}
+
+/// Runs a command like [`attach_reading_stdout`], turning its progress output into spinner messages
+///
+/// stderr is read as it arrives: lines naming a known stage (see [`stage`])
+/// update the spinner, other lines are printed above it, and an unfinished line
+/// that looks like a prompt is shown so it can be answered. The spinner keeps
+/// its initial message when nothing is recognized. The printed lines are kept
+/// so a failure can be classified afterwards.
+///
+/// # Arguments
+/// * `command` - The command to run
+/// * `spinner` - The spinner showing the current stage
+///
+/// # Returns
+/// * `io::Result<Output>` - The exit status, stdout and the stderr lines that were printed
+pub fn attach_showing_stages(command: &mut Command, spinner: &LiveSpinner) -> io::Result<Output> {
+    logging::command(command);
+    let mut child = command
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Drain stdout on its own thread so neither pipe can fill up and block gcloud
+    let mut stdout = child.stdout.take();
+    let reader = thread::spawn(move || {
+        let mut buffer = Vec::new();
+        if let Some(stdout) = stdout.as_mut() {
+            stdout.read_to_end(&mut buffer)?;
+        }
+        Ok::<_, io::Error>(buffer)
+    });
+
+    let mut shown = Vec::new();
+    if let Some(mut stderr) = child.stderr.take() {
+        let mut pending = Vec::new();
+        let mut chunk = [0u8; 1024];
+        loop {
+            let read = stderr.read(&mut chunk)?;
+            if read == 0 {
+                break;
+            }
+            pending.extend_from_slice(&chunk[..read]);
+
+            while let Some(end) = pending.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<u8> = pending.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line);
+                match stage(&line) {
+                    Some(stage) => spinner.set_message(stage),
+                    None => {
+                        spinner.print_line(line.trim_end());
+                        shown.extend_from_slice(line.as_bytes());
+                    }
+                }
+            }
+
+            // gcloud ends progress messages with "done." later, so only show a
+            // partial line now when it is waiting for an answer
+            let partial = String::from_utf8_lossy(&pending).into_owned();
+            if let Some(stage) = stage(&partial) {
+                spinner.set_message(stage);
+            } else if is_prompt(&partial) {
+                spinner.prompt(&partial);
+                pending.clear();
+            }
+        }
+        if !pending.is_empty() {
+            spinner.print_line(String::from_utf8_lossy(&pending).trim_end());
+            shown.extend_from_slice(&pending);
+        }
+    }
+
+    let stdout = reader
+        .join()
+        .map_err(|_| io::Error::other("stdout reader panicked"))??;
+    Ok(Output {
+        status: child.wait()?,
+        stdout,
+        stderr: shown,
+    })
+}
+
+/// Names the stage a line of `gcloud compute ssh` progress output reports
+///
+/// # Arguments
+/// * `line` - A line (or the start of one) from gcloud's stderr
+///
+/// # Returns
+/// * `Option<&'static str>` - A short description of the stage, if recognized
+pub fn stage(line: &str) -> Option<&'static str> {
+    let line = line.trim().to_lowercase();
+    let stages = [
+        ("generating public/private", "Generating the gcloud SSH key"),
+        (
+            "updating project ssh metadata",
+            "Propagating SSH keys to the project",
+        ),
+        (
+            "updating instance ssh metadata",
+            "Propagating SSH keys to the VM",
+        ),
+        (
+            "waiting for ssh key to propagate",
+            "Waiting for the SSH key to propagate",
+        ),
+        (
+            "defaulting to using iap tunneling",
+            "Connecting through an IAP tunnel",
+        ),
+        (
+            "testing if instance is reachable",
+            "Waiting for SSH on the VM",
+        ),
+        ("permanently added", "Establishing the SSH connection"),
+    ];
+    stages
+        .into_iter()
+        .find(|(marker, _)| line.contains(marker))
+        .map(|(_, stage)| stage)
+}
+
+/// Checks whether an unfinished line of output is a question waiting for input
+fn is_prompt(partial: &str) -> bool {
+    let trimmed = partial.trim_end();
+    !trimmed.is_empty() && (trimmed.ends_with('?') || trimmed.ends_with(':'))
+}
+
+/// A well-known cause of gcloud failures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// The service account passed to `--impersonate` can't be impersonated
+    Impersonation,
+    /// No credentials, or expired ones
+    Auth,
+    /// The Compute Engine API is disabled for the project
+    ApiDisabled,
+    /// A quota or rate limit was exceeded
+    Quota,
+    /// The account lacks an IAM permission
+    Permission,
+}
+
+impl ErrorCategory {
+    /// Returns what the user can do about the failure
+    pub fn hint(self) -> &'static str {
+        match self {
+            ErrorCategory::Impersonation => {
+                "Impersonation requires the Service Account Token Creator role \
+                 (roles/iam.serviceAccountTokenCreator) on the service account passed to --impersonate"
+            }
+            ErrorCategory::Auth => {
+                "gcloud has no valid credentials; run `gcloud auth login` and try again"
+            }
+            ErrorCategory::ApiDisabled => {
+                "The Compute Engine API is not enabled for this project; \
+                 run `gcloud services enable compute.googleapis.com`"
+            }
+            ErrorCategory::Quota => {
+                "A quota or rate limit was reached; wait a moment and retry, \
+                 or request more quota under IAM & Admin > Quotas"
+            }
+            ErrorCategory::Permission => {
+                "Your account lacks a permission this needs; ask for a role such as \
+                 Compute Instance Admin (roles/compute.instanceAdmin.v1) on the project"
+            }
+        }
+    }
+}
+
+/// Recognizes the cause of a gcloud failure from its error text
+///
+/// # Arguments
+/// * `error` - The error message, including gcloud's stderr
+///
+/// # Returns
+/// * `Option<ErrorCategory>` - The cause, if the failure is recognized
+pub fn classify_error(error: &str) -> Option<ErrorCategory> {
+    let error = error.to_lowercase();
+    let has = |markers: &[&str]| markers.iter().any(|marker| error.contains(marker));
+
+    // Disabled APIs and impersonation are also reported as permission errors,
+    // so they are checked first
+    if error.contains("impersonate") && has(&["permission", "failed to impersonate"]) {
+        Some(ErrorCategory::Impersonation)
+    } else if has(&[
+        "service_disabled",
+        "has not been used in project",
+        "api has not been enabled",
+    ]) {
+        Some(ErrorCategory::ApiDisabled)
+    } else if has(&[
+        "gcloud auth login",
+        "do not currently have an active account",
+        "reauthentication",
+        "invalid_grant",
+        "credentials were not found",
+    ]) {
+        Some(ErrorCategory::Auth)
+    } else if has(&["quota", "rate limit", "ratelimitexceeded"]) {
+        Some(ErrorCategory::Quota)
+    } else if has(&["permission_denied", "permission", "forbidden", "403"]) {
+        Some(ErrorCategory::Permission)
+    } else {
+        None
+    }
+}
+
+/// Returns the gcloud command that renews expired credentials
+///
+/// Only real expiry counts (`invalid_grant`, "Reauthentication required"); a
+/// machine with no account at all is left to the usual auth hint. Failures
+/// naming Application Default Credentials need
+/// `gcloud auth application-default login`; others need `gcloud auth login`.
+///
+/// # Arguments
+/// * `error` - The error message, including gcloud's stderr
+///
+/// # Returns
+/// * `Option<&'static [&'static str]>` - The login arguments, or `None` when the credentials haven't expired
+pub fn reauth_command(error: &str) -> Option<&'static [&'static str]> {
+    let error = error.to_lowercase();
+    if !error.contains("invalid_grant") && !error.contains("reauthentication") {
+        return None;
+    }
+    if error.contains("application-default") || error.contains("application default credentials") {
+        Some(&["auth", "application-default", "login"])
+    } else {
+        Some(&["auth", "login"])
+    }
+}
+
+/// Suggests a fix for a gcloud failure that has a well-known cause
+///
+/// # Arguments
+/// * `error` - The error message, including gcloud's stderr
+///
+/// # Returns
+/// * `Option<&'static str>` - A hint for the user, if the failure is recognized
+pub fn error_hint(error: &str) -> Option<&'static str> {
+    classify_error(error).map(ErrorCategory::hint)
+}
+
+/// Turns a failed gcloud call's stderr into a short message for an error
+///
+/// The full text is logged under `--verbose`; the message is what
+/// [`summarize_error`] extracts from it.
+///
+/// # Arguments
+/// * `stderr` - The captured stderr
+///
+/// # Returns
+/// * `String` - The salient part of the error
+pub fn error_message(stderr: &[u8]) -> String {
+    let text = String::from_utf8_lossy(stderr);
+    logging::verbose(&format!("gcloud stderr:\n{}", text.trim_end()));
+    summarize_error(&text)
+}
+
+/// Extracts the salient message from gcloud's error output
+///
+/// gcloud reports failures as `ERROR: (gcloud.<command>) <message>`, sometimes
+/// followed by indented ` - ` detail lines; the command prefix is dropped and
+/// the details joined on. Without such a line a Python traceback is reduced to
+/// its last line, and any other text is kept as it is.
+///
+/// # Arguments
+/// * `stderr` - The captured stderr
+///
+/// # Returns
+/// * `String` - The message to show
+pub fn summarize_error(stderr: &str) -> String {
+    let lines: Vec<&str> = stderr.lines().collect();
+    let Some(start) = lines
+        .iter()
+        .rposition(|line| line.trim_start().starts_with("ERROR:"))
+    else {
+        let last = lines
+            .iter()
+            .rev()
+            .map(|line| line.trim())
+            .find(|line| !line.is_empty());
+        return match last {
+            Some(last) if stderr.contains("Traceback (most recent call last)") => last.to_string(),
+            _ => stderr.trim().to_string(),
+        };
+    };
+
+    let first = lines[start]
+        .trim_start()
+        .trim_start_matches("ERROR:")
+        .trim();
+    let first = match first.strip_prefix("(gcloud") {
+        Some(rest) => rest
+            .split_once(')')
+            .map_or(first, |(_, message)| message.trim()),
+        None => first,
+    };
+
+    let mut parts = vec![first.to_string()];
+    for line in &lines[start + 1..] {
+        let detail = line.trim().trim_start_matches("- ").trim();
+        if detail.is_empty() {
+            break;
+        }
+        parts.push(detail.to_string());
+    }
+    parts
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A named gcloud configuration
+#[derive(Debug, Clone, Deserialize)]
+pub struct Configuration {
+    /// Configuration name
+    pub name: String,
+
+    /// Whether this is the currently active configuration
+    #[serde(default)]
+    pub is_active: bool,
+}
+
+/// The project, account and configuration gcloud commands will run with
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GcloudContext {
+    /// Project used by gcloud calls
+    pub project: Option<String>,
+
+    /// Account gcloud calls authenticate as
+    pub account: Option<String>,
+
+    /// Named configuration in effect
+    pub configuration: Option<String>,
+}
+
+/// Resolves the effective project, account and configuration
+///
+/// One `gcloud info` call reports all three with the global flags applied, so
+/// `--project` and `--configuration` are reflected. An impersonated service
+/// account replaces the authenticated one.
+///
+/// # Returns
+/// * `Result<GcloudContext>` - The context or error
+pub fn context() -> Result<GcloudContext> {
+    let output = capture(command().args([
+        "info",
+        "--format=json(config.active_config_name,config.account,config.project)",
+    ]))
+    .context("Failed to run gcloud")?;
+
+    if !output.status.success() {
+        bail!(
+            "Failed to read the gcloud configuration: {}",
+            error_message(&output.stderr)
+        );
+    }
+
+    let info: serde_json::Value =
+        serde_json::from_slice(&output.stdout).context("Failed to parse gcloud info")?;
+    let field = |name: &str| {
+        info["config"][name]
+            .as_str()
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+    };
+
+    let impersonate = GLOBAL_FLAGS
+        .read()
+        .ok()
+        .and_then(|flags| flags.impersonate.clone());
+    Ok(GcloudContext {
+        project: field("project"),
+        account: impersonate.or_else(|| field("account")),
+        configuration: field("active_config_name"),
+    })
+}
+
+/// Lists the named gcloud configurations
+///
+/// This deliberately bypasses the global flags, since they may reference the
+/// configuration being validated.
+///
+/// # Returns
+/// * `Result<Vec<Configuration>>` - The available configurations or error
+pub fn configurations() -> Result<Vec<Configuration>> {
+    let output = capture(Command::new(program()).args([
+        "config",
+        "configurations",
+        "list",
+        "--format=json",
+    ]))
+    .context("Failed to run gcloud")?;
+
+    if !output.status.success() {
+        bail!(
+            "Failed to list gcloud configurations: {}",
+            error_message(&output.stderr)
+        );
+    }
+
+    serde_json::from_slice(&output.stdout).context("Failed to parse gcloud configurations")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that gcloud error output is reduced to its message and mapped to a hint
+    #[test]
+    fn test_error_summary() {
+        let stderr = "WARNING: Some flag is deprecated\nERROR: (gcloud.compute.instances.list) Some requests did not succeed:\n - Required 'compute.instances.list' permission for 'projects/acme'\n\n";
+        let summary = summarize_error(stderr);
+        assert_eq!(
+            summary,
+            "Some requests did not succeed: Required 'compute.instances.list' permission for 'projects/acme'"
+        );
+        assert_eq!(classify_error(&summary), Some(ErrorCategory::Permission));
+
+        let traceback = "Traceback (most recent call last):\n  File \"gcloud.py\", line 1\nOSError: [Errno 28] No space left on device\n";
+        assert_eq!(
+            summarize_error(traceback),
+            "OSError: [Errno 28] No space left on device"
+        );
+        assert_eq!(summarize_error("  plain failure \n"), "plain failure");
+
+        let disabled = "ERROR: (gcloud.compute.zones.list) PERMISSION_DENIED: Compute Engine API has not been used in project 42 before or it is disabled.";
+        assert_eq!(classify_error(disabled), Some(ErrorCategory::ApiDisabled));
+        assert_eq!(
+            classify_error("ERROR: (gcloud.compute.instances.list) You do not currently have an active account selected."),
+            Some(ErrorCategory::Auth)
+        );
+        assert_eq!(
+            classify_error("Quota 'CPUS' exceeded. Limit: 24.0"),
+            Some(ErrorCategory::Quota)
+        );
+        assert_eq!(classify_error("instance not found"), None);
+    }
+
+    /// Test that expired credentials map to the matching login command
+    #[test]
+    fn test_reauth_command() {
+        assert_eq!(
+            reauth_command("ERROR: (gcloud.compute.instances.list) There was a problem refreshing your current auth tokens: Reauthentication failed. cannot prompt during non-interactive execution."),
+            Some(&["auth", "login"][..])
+        );
+        assert_eq!(
+            reauth_command("('invalid_grant: Bad Request', {'error': 'invalid_grant'}); run `gcloud auth application-default login`"),
+            Some(&["auth", "application-default", "login"][..])
+        );
+        assert_eq!(
+            reauth_command("PERMISSION_DENIED: compute.instances.list"),
+            None
+        );
+
+        // No account at all is a setup problem, not expired credentials
+        assert_eq!(
+            reauth_command("ERROR: (gcloud.compute.instances.list) You do not currently have an active account selected."),
+            None
+        );
+    }
+
+    /// Test that progress lines map to stages and prompts are told from partial lines
+    #[test]
+    fn test_stage_parsing() {
+        assert_eq!(
+            stage("Updating project ssh metadata...done."),
+            Some("Propagating SSH keys to the project")
+        );
+        assert_eq!(
+            stage("Waiting for SSH key to propagate."),
+            Some("Waiting for the SSH key to propagate")
+        );
+        assert_eq!(
+            stage("Warning: Permanently added 'compute.123' (ED25519) to the list of known hosts."),
+            Some("Establishing the SSH connection")
+        );
+        assert_eq!(stage("Welcome to Ubuntu 22.04"), None);
+
+        assert!(is_prompt("Do you want to continue (Y/n)?  "));
+        assert!(is_prompt("Enter passphrase: "));
+        assert!(!is_prompt("Updating instance"));
+
+        // stdout is still collected while stderr is read line by line
+        let spinner = LiveSpinner::start("Running");
+        let output = attach_showing_stages(
+            Command::new("sh").args([
+                "-c",
+                "echo 'Waiting for SSH key to propagate.' >&2; echo VERIFIED; echo 'ERROR: denied' >&2; exit 3",
+            ]),
+            &spinner,
+        )
+        .unwrap();
+        spinner.finish();
+        assert_eq!(output.stdout, b"VERIFIED\n");
+        assert_eq!(output.stderr, b"ERROR: denied\n");
+        assert_eq!(output.status.code(), Some(3));
+    }
+
+    /// Test the exact argument vector built from command-line options
+    #[test]
+    fn test_gcloud_command_args() {
+        let options = cli::Options {
+            configuration: Some("work".to_string()),
+            project: Some("acme-prod".to_string()),
+            impersonate: Some("ops@acme-prod.iam.gserviceaccount.com".to_string()),
+            ..Default::default()
+        };
+        let command = GcloudCommand::with_globals(GlobalFlags::from_options(&options))
+            .args(["compute", "ssh", "web-1"])
+            .zone("us-central1-a");
+        assert_eq!(
+            command.to_args(),
+            [
+                "--configuration=work",
+                "--project=acme-prod",
+                "--impersonate-service-account=ops@acme-prod.iam.gserviceaccount.com",
+                "compute",
+                "ssh",
+                "web-1",
+                "--zone",
+                "us-central1-a",
+            ]
+        );
+
+        // A per-command project replaces the global one instead of adding a second flag
+        let listing = GcloudCommand::with_globals(GlobalFlags::from_options(&options))
+            .args(["compute", "instances", "list"])
+            .project("acme-dev");
+        let projects: Vec<String> = listing
+            .to_args()
+            .into_iter()
+            .filter(|arg| arg.starts_with("--project"))
+            .collect();
+        assert_eq!(projects, ["--project=acme-dev"]);
+
+        assert_eq!(
+            GcloudCommand::with_globals(GlobalFlags::default())
+                .arg("version")
+                .to_args(),
+            ["version"]
+        );
+    }
+
+    /// Test that a flag only counts as documented when it appears on its own
+    #[test]
+    fn test_help_lists_flag() {
+        let help = "FLAGS\n     --listen-on-stdin\n        Whether to get/put local data on stdin/stdout.\n\n     --local-host-port=LOCAL_HOST_PORT\n";
+        assert!(help_lists_flag(help, "--listen-on-stdin"));
+        assert!(help_lists_flag(help, "--local-host-port"));
+        assert!(!help_lists_flag(help, "--listen-on"));
+        assert!(!help_lists_flag(help, "--zone"));
+    }
+
+    /// Test that the flag beats the environment and bare names are searched on PATH
+    #[test]
+    fn test_program_resolution() {
+        let flag = Path::new("/opt/sdk/bin/gcloud");
+        assert_eq!(
+            resolve_program(Some(flag), Some(OsStr::new("/env/gcloud"))),
+            flag
+        );
+        assert_eq!(
+            resolve_program(None, Some(OsStr::new("/env/gcloud"))),
+            Path::new("/env/gcloud")
+        );
+        assert_eq!(
+            resolve_program(None, Some(OsStr::new(""))),
+            Path::new(DEFAULT_PROGRAM)
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let binary = dir.path().join("gcloud-474");
+        std::fs::write(&binary, "#!/bin/sh\n").unwrap();
+        let path = env::join_paths([Path::new("/nonexistent"), dir.path()]).unwrap();
+
+        assert_eq!(
+            locate_program(Path::new("gcloud-474"), Some(&path)),
+            Some(binary.clone())
+        );
+        assert_eq!(locate_program(&binary, None), Some(binary.clone()));
+        assert_eq!(locate_program(Path::new("gcloud-474"), None), None);
+        assert_eq!(
+            locate_program(&dir.path().join("missing"), Some(&path)),
+            None
+        );
+    }
+}
@@ -0,0 +1,133 @@
+/// This module reads a static inventory of SSH hosts from a file so that
+/// servers outside Google Cloud can be selected through the same interface.
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::{fs, path::Path};
+
+/// A single host entry from a hosts file
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct HostEntry {
+    /// Display name of the host
+    pub name: String,
+
+    /// Address (IP or DNS name) to connect to
+    pub host: String,
+
+    /// Remote username, if different from the local one
+    #[serde(default)]
+    pub user: Option<String>,
+}
+
+/// Loads host entries from a CSV or JSON file
+///
+/// Files ending in `.json` (or whose content starts with `[`) are parsed as a
+/// JSON array of `{ "name", "host", "user" }` objects; anything else is read as
+/// `name,host,user` CSV lines.
+///
+/// # Arguments
+/// * `path` - Path to the hosts file
+///
+/// # Returns
+/// * `Result<Vec<HostEntry>>` - The parsed entries or error
+pub fn load_hosts_file(path: &Path) -> Result<Vec<HostEntry>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read hosts file {}", path.display()))?;
+
+    let is_json =
+        path.extension().is_some_and(|ext| ext == "json") || content.trim_start().starts_with('[');
+
+    let entries = if is_json {
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse hosts file {}", path.display()))?
+    } else {
+        parse_csv(&content)?
+    };
+
+    if entries.is_empty() {
+        bail!("Hosts file {} contains no hosts", path.display());
+    }
+
+    Ok(entries)
+}
+
+/// Parses `name,host,user` lines, skipping blanks, `#` comments and a header row
+///
+/// The header may only be the first line that is neither blank nor a comment.
+///
+/// # Arguments
+/// * `content` - The CSV text
+///
+/// # Returns
+/// * `Result<Vec<HostEntry>>` - The parsed entries or error
+pub fn parse_csv(content: &str) -> Result<Vec<HostEntry>> {
+    let mut entries = Vec::new();
+    let mut first_row = true;
+
+    for (line_no, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if std::mem::take(&mut first_row) && fields.first() == Some(&"name") {
+            continue;
+        }
+
+        let (name, host, user) = match fields.as_slice() {
+            [name, host] => (*name, *host, ""),
+            [name, host, user] => (*name, *host, *user),
+            _ => ("", "", ""),
+        };
+        if name.is_empty() || host.is_empty() {
+            bail!(
+                "Invalid hosts file line {}: expected name,host[,user]",
+                line_no + 1
+            );
+        }
+
+        entries.push(HostEntry {
+            name: name.to_string(),
+            host: host.to_string(),
+            user: (!user.is_empty()).then(|| user.to_string()),
+        });
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test CSV parsing with a header, comments and an optional user column
+    #[test]
+    fn test_parse_csv() {
+        let csv = "name,host,user\n# staging\nweb,10.0.0.1,deploy\ndb, db.example.com\n";
+        let entries = parse_csv(csv).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].user.as_deref(), Some("deploy"));
+        assert_eq!(entries[1].host, "db.example.com");
+        assert_eq!(entries[1].user, None);
+    }
+
+    /// Test that a header after leading comments and blank lines is still skipped
+    #[test]
+    fn test_parse_csv_header_after_comments() {
+        let csv = "# fleet\n\nname,host,user\nweb,10.0.0.1,deploy\n";
+        let entries = parse_csv(csv).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "web");
+
+        // Only the first row can be a header; a later "name" row is a host
+        let entries = parse_csv("web,10.0.0.1\nname,10.0.0.2\n").unwrap();
+        assert_eq!(entries[1].name, "name");
+    }
+
+    /// Test that malformed lines are rejected with their line number
+    #[test]
+    fn test_parse_csv_invalid_line() {
+        let err = parse_csv("web,10.0.0.1\nbroken\n").unwrap_err();
+        assert!(err.to_string().contains("line 2"));
+    }
+}
@@ -0,0 +1,237 @@
+/// This module is the single access point for the icons shown in the interface,
+/// so emoji can be swapped for plain ASCII with one runtime switch and replaced
+/// from the `[emojis]` table of the configuration file.
+use crate::{config::emojis, settings, term_utils};
+use std::{collections::BTreeMap, fmt::Display, sync::RwLock};
+
+/// Widest icon, in terminal columns, accepted from the configuration file
+pub const MAX_ICON_WIDTH: usize = 8;
+
+/// Emoji replacements loaded from the configuration file
+static OVERRIDES: RwLock<Vec<(Icon, &'static str)>> = RwLock::new(Vec::new());
+
+/// The role an icon plays in the interface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Icon {
+    /// Successful operations
+    Success,
+    /// Informational messages
+    Info,
+    /// Warnings
+    Warning,
+    /// Errors
+    Error,
+    /// IP addresses
+    IpAddress,
+    /// VM instances
+    Vm,
+    /// SSH keys
+    Key,
+    /// Zones and locations
+    Zone,
+    /// Favorite VMs
+    Favorite,
+    /// VMs the user cannot SSH into
+    Locked,
+    /// Static (reserved) external IPs
+    StaticIp,
+}
+
+impl Icon {
+    /// Every icon role
+    pub const ALL: [Icon; 11] = [
+        Icon::Success,
+        Icon::Info,
+        Icon::Warning,
+        Icon::Error,
+        Icon::IpAddress,
+        Icon::Vm,
+        Icon::Key,
+        Icon::Zone,
+        Icon::Favorite,
+        Icon::Locked,
+        Icon::StaticIp,
+    ];
+
+    /// The key naming this role in the `[emojis]` configuration table
+    pub fn key(self) -> &'static str {
+        match self {
+            Icon::Success => "success",
+            Icon::Info => "info",
+            Icon::Warning => "warning",
+            Icon::Error => "error",
+            Icon::IpAddress => "ip_address",
+            Icon::Vm => "vm",
+            Icon::Key => "key",
+            Icon::Zone => "zone",
+            Icon::Favorite => "favorite",
+            Icon::Locked => "locked",
+            Icon::StaticIp => "static_ip",
+        }
+    }
+
+    /// The built-in emoji used when the terminal can render it
+    pub fn emoji(self) -> &'static str {
+        match self {
+            Icon::Success => emojis::SUCCESS[0],
+            Icon::Info => emojis::INFO[0],
+            Icon::Warning => emojis::WARNING[0],
+            Icon::Error => emojis::ERROR[0],
+            Icon::IpAddress => emojis::IP_ADDRESS,
+            Icon::Vm => emojis::VM,
+            Icon::Key => emojis::KEY,
+            Icon::Zone => emojis::ZONE,
+            Icon::Favorite => emojis::FAVORITE,
+            Icon::Locked => emojis::LOCKED,
+            Icon::StaticIp => emojis::STATIC_IP,
+        }
+    }
+
+    /// The plain-text substitute used when emoji are disabled
+    pub fn ascii(self) -> &'static str {
+        match self {
+            Icon::Success => "[OK]",
+            Icon::Info => "[i]",
+            Icon::Warning => "[!]",
+            Icon::Error => "[x]",
+            Icon::IpAddress => "[IP]",
+            Icon::Favorite => "*",
+            Icon::Locked => "[locked]",
+            Icon::StaticIp => "[static]",
+            Icon::Vm | Icon::Key | Icon::Zone => "",
+        }
+    }
+}
+
+/// Returns the icon for a role according to the active settings
+///
+/// # Arguments
+/// * `kind` - The icon role
+///
+/// # Returns
+/// * `&'static str` - The emoji, or its ASCII substitute when emoji are disabled
+pub fn icon(kind: Icon) -> &'static str {
+    icon_for(kind, settings::current().emoji)
+}
+
+/// Returns the icon for a role, with emoji enabled or not
+fn icon_for(kind: Icon, emoji: bool) -> &'static str {
+    if !emoji {
+        return kind.ascii();
+    }
+
+    configured(kind).unwrap_or_else(|| kind.emoji())
+}
+
+/// Returns the emoji configured for a role in the `[emojis]` table, if any
+fn configured(kind: Icon) -> Option<&'static str> {
+    let overrides = OVERRIDES.read().ok()?;
+    overrides
+        .iter()
+        .find(|(role, _)| *role == kind)
+        .map(|(_, glyph)| *glyph)
+}
+
+/// Replaces the built-in emoji with the `[emojis]` table from the configuration file
+///
+/// Each entry is checked on its own: unknown roles and values that are empty,
+/// contain control characters or are wider than [`MAX_ICON_WIDTH`] are skipped,
+/// so those roles keep their built-in emoji.
+///
+/// # Arguments
+/// * `table` - Role keys (e.g. `vm`) mapped to the strings to show
+///
+/// # Returns
+/// * `Vec<String>` - A description of every entry that was ignored
+pub fn install_overrides(table: &BTreeMap<String, String>) -> Vec<String> {
+    let mut overrides = Vec::new();
+    let mut problems = Vec::new();
+
+    for (key, value) in table {
+        let Some(kind) = Icon::ALL.into_iter().find(|kind| kind.key() == key) else {
+            let known: Vec<&str> = Icon::ALL.iter().map(|kind| kind.key()).collect();
+            problems.push(format!(
+                "unknown emoji role '{}' (known: {})",
+                key,
+                known.join(", ")
+            ));
+            continue;
+        };
+
+        let value = value.trim();
+        if value.is_empty() || value.chars().any(char::is_control) {
+            problems.push(format!("emoji '{}' must be visible text", key));
+        } else if term_utils::display_width(value) > MAX_ICON_WIDTH {
+            problems.push(format!(
+                "emoji '{}' is wider than {} columns",
+                key, MAX_ICON_WIDTH
+            ));
+        } else {
+            // Loaded once at startup, so leaking keeps `icon()` allocation-free
+            overrides.push((kind, &*Box::leak(value.to_string().into_boxed_str())));
+        }
+    }
+
+    if let Ok(mut current) = OVERRIDES.write() {
+        *current = overrides;
+    }
+    problems
+}
+
+/// Prefixes a label with an icon, omitting the separator when the icon is empty
+///
+/// # Arguments
+/// * `kind` - The icon role
+/// * `label` - The text to prefix
+///
+/// # Returns
+/// * `String` - The prefixed label
+pub fn with_icon(kind: Icon, label: impl Display) -> String {
+    prefixed(icon(kind), label)
+}
+
+/// Joins an icon and a label with a space, or returns the label alone for an empty icon
+fn prefixed(glyph: &str, label: impl Display) -> String {
+    match glyph {
+        "" => label.to_string(),
+        glyph => format!("{} {}", glyph, label),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that disabling emoji switches to ASCII substitutes
+    #[test]
+    fn test_icons_without_emoji() {
+        assert_eq!(icon_for(Icon::Success, false), "[OK]");
+        assert_eq!(prefixed(icon_for(Icon::Vm, false), "VM Name:"), "VM Name:");
+        assert_eq!(
+            prefixed(icon_for(Icon::Success, false), "done"),
+            "[OK] done"
+        );
+    }
+
+    /// Test that configured emoji replace the defaults per key, skipping bad values
+    #[test]
+    fn test_install_overrides() {
+        let table: BTreeMap<String, String> = [
+            ("vm", "VM>"),
+            ("zone", "  "),
+            ("key", "\u{1b}[31m"),
+            ("sparkles", "✨"),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+        let problems = install_overrides(&table);
+
+        assert_eq!(configured(Icon::Vm), Some("VM>"));
+        assert_eq!(configured(Icon::Zone), None);
+        assert_eq!(configured(Icon::Key), None);
+        assert_eq!(problems.len(), 3);
+
+        install_overrides(&BTreeMap::new());
+    }
+}
@@ -0,0 +1,90 @@
+/// This module renders listed instances as an Ansible inventory so the
+/// filtered VM list can feed automation directly.
+use serde::Serialize;
+
+/// Inventory group the hosts are placed in
+pub const GROUP: &str = "gcloud";
+
+/// Ansible inventory formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InventoryFormat {
+    /// INI inventory (`--ansible`)
+    Ini,
+    /// YAML inventory (`--ansible-yaml`)
+    Yaml,
+}
+
+/// A host as it appears in the inventory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InventoryHost {
+    /// Inventory hostname (the VM name)
+    pub name: String,
+
+    /// Address Ansible connects to
+    pub ansible_host: String,
+
+    /// Remote login user
+    pub ansible_user: String,
+}
+
+/// Renders hosts as an inventory in the given format
+///
+/// # Arguments
+/// * `hosts` - The hosts to include
+/// * `format` - INI or YAML
+///
+/// # Returns
+/// * `String` - The inventory text, ending in a newline
+pub fn render(hosts: &[InventoryHost], format: InventoryFormat) -> String {
+    let mut out = String::new();
+    match format {
+        InventoryFormat::Ini => {
+            out.push_str(&format!("[{}]\n", GROUP));
+            for host in hosts {
+                out.push_str(&format!(
+                    "{} ansible_host={} ansible_user={}\n",
+                    host.name, host.ansible_host, host.ansible_user
+                ));
+            }
+        }
+        InventoryFormat::Yaml => {
+            // JSON strings are valid YAML scalars, which keeps IPv6 colons safe
+            let quote = |value: &str| serde_json::to_string(value).unwrap_or_default();
+            out.push_str(&format!(
+                "all:\n  children:\n    {}:\n      hosts:\n",
+                GROUP
+            ));
+            for host in hosts {
+                out.push_str(&format!(
+                    "        {}:\n          ansible_host: {}\n          ansible_user: {}\n",
+                    quote(&host.name),
+                    quote(&host.ansible_host),
+                    quote(&host.ansible_user)
+                ));
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test both inventory formats for a single host
+    #[test]
+    fn test_render() {
+        let hosts = vec![InventoryHost {
+            name: "web-1".to_string(),
+            ansible_host: "2600::1".to_string(),
+            ansible_user: "deploy".to_string(),
+        }];
+
+        assert_eq!(
+            render(&hosts, InventoryFormat::Ini),
+            "[gcloud]\nweb-1 ansible_host=2600::1 ansible_user=deploy\n"
+        );
+        assert!(render(&hosts, InventoryFormat::Yaml)
+            .contains("        \"web-1\":\n          ansible_host: \"2600::1\"\n"));
+    }
+}
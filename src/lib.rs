@@ -4,9 +4,28 @@
 /// without modifying the core functionality of the application.
 // Module declarations
 pub mod banner;
+pub mod cli;
 pub mod config;
+pub mod config_report;
+pub mod error;
+pub mod favorites;
+pub mod gcloud;
+pub mod hosts;
+pub mod icons;
+pub mod inventory;
+pub mod logging;
+pub mod messages;
+pub mod pager;
+pub mod parallel;
+pub mod render;
+pub mod settings;
+pub mod ssh_config;
+pub mod storage;
 pub mod term_utils;
 pub mod terminal_fx;
+pub mod update;
+pub mod user_config;
+pub mod zones;
 
 // Re-exports for easier access
 pub use banner::*;
@@ -36,7 +55,7 @@ pub fn display_welcome() -> Result<(), std::io::Error> {
     println!("{}", banner::main_banner());
 
     // Show welcome message with typing effect if animations are enabled
-    if config::animations::ENABLED {
+    if settings::current().animations {
         terminal_fx::type_text(
             &format!(
                 "Welcome to {}! Let's set up your SSH access.",
@@ -61,11 +80,7 @@ pub fn display_welcome() -> Result<(), std::io::Error> {
                          5. Adding your SSH key to the VM\n\
                          6. Generating the SSH command for connection";
 
-        let term_width = term_utils::get_terminal_size()
-            .map(|size| size.width)
-            .unwrap_or(config::layout::DEFAULT_FRAME_WIDTH);
-
-        terminal_fx::framed_message(help_text, term_width);
+        terminal_fx::framed_message(help_text, term_utils::layout_width());
     }
 
     // Display keyboard shortcuts if enabled
@@ -98,7 +113,7 @@ pub fn cleanup() -> Result<(), std::io::Error> {
 /// # Returns
 /// * `String` - Formatted VM list item
 pub fn format_vm_list_item(index: usize, name: &str, zone: &str, ip: Option<&str>) -> String {
-    banner::vm_list_item(index, name, zone, ip)
+    banner::vm_list_item(index, name, zone, ip, None, 1, false)
 }
 
 /// Formats and displays the SSH command in a visually appealing box
@@ -133,7 +148,7 @@ pub fn display_section_header(title: &str) -> Result<(), std::io::Error> {
 /// # Returns
 /// * `Result<(), std::io::Error>` - Success or error information
 pub fn display_success(message: &str) -> Result<(), std::io::Error> {
-    if config::animations::ENABLED {
+    if settings::current().animations {
         terminal_fx::fade_text(&banner::success_message(message), 1000);
     } else {
         println!("{}", banner::success_message(message));
@@ -150,7 +165,7 @@ pub fn display_success(message: &str) -> Result<(), std::io::Error> {
 /// # Returns
 /// * `Result<(), std::io::Error>` - Success or error information
 pub fn display_processing(message: &str, duration_ms: u64) -> Result<(), std::io::Error> {
-    if config::animations::ENABLED {
+    if settings::current().animations {
         terminal_fx::spinner(message, duration_ms);
     } else {
         println!("{}", message);
@@ -0,0 +1,154 @@
+/// This module writes verbose diagnostics to stderr when `--verbose` is set,
+/// redacting SSH public key material so it never lands in logs or history.
+use crate::settings;
+use base64::{engine::general_purpose, Engine};
+use sha2::{Digest, Sha256};
+use std::{
+    process::Command,
+    time::{Duration, Instant},
+};
+
+/// SSH public key type prefixes whose following token is a key body
+pub const KEY_TYPES: &[&str] = &[
+    "ssh-rsa",
+    "ssh-dss",
+    "ssh-ed25519",
+    "ecdsa-sha2-nistp256",
+    "ecdsa-sha2-nistp384",
+    "ecdsa-sha2-nistp521",
+    "sk-ssh-ed25519@openssh.com",
+    "sk-ecdsa-sha2-nistp256@openssh.com",
+];
+
+/// Prints a diagnostic message to stderr when verbose output is enabled
+///
+/// Key material in the message is replaced with its fingerprint.
+///
+/// # Arguments
+/// * `message` - The message to log
+pub fn verbose(message: &str) {
+    if settings::current().verbose {
+        eprintln!("[verbose] {}", redact_key(message));
+    }
+}
+
+/// Logs a command line about to be executed
+///
+/// # Arguments
+/// * `command` - The command to describe
+pub fn command(command: &Command) {
+    if !settings::current().verbose {
+        return;
+    }
+
+    let mut line = command.get_program().to_string_lossy().into_owned();
+    for arg in command.get_args() {
+        line.push(' ');
+        line.push_str(&arg.to_string_lossy());
+    }
+    verbose(&format!("Running: {}", line));
+}
+
+/// Runs a closure and measures how long it took
+///
+/// The duration is also logged when verbose output is enabled.
+///
+/// # Arguments
+/// * `label` - Name of the measured step
+/// * `f` - The work to time
+///
+/// # Returns
+/// * `(T, Duration)` - The closure's result and the elapsed time
+pub fn time_it<T>(label: &str, f: impl FnOnce() -> T) -> (T, Duration) {
+    let started = Instant::now();
+    let result = f();
+    let elapsed = started.elapsed();
+    verbose(&format!(
+        "{} took {:.1} ms",
+        label,
+        elapsed.as_secs_f64() * 1000.0
+    ));
+    (result, elapsed)
+}
+
+/// Computes the OpenSSH-style SHA256 fingerprint of a base64 key body
+///
+/// # Arguments
+/// * `body` - The base64-encoded key blob
+///
+/// # Returns
+/// * `Option<String>` - `SHA256:...` or None if the body isn't valid base64
+pub fn fingerprint(body: &str) -> Option<String> {
+    let blob = general_purpose::STANDARD.decode(body).ok()?;
+    let digest = Sha256::digest(&blob);
+    Some(format!(
+        "SHA256:{}",
+        general_purpose::STANDARD_NO_PAD.encode(digest)
+    ))
+}
+
+/// Replaces SSH public key bodies in a string with their fingerprints
+///
+/// Any token following a known key type (e.g. `ssh-ed25519`) is treated as a
+/// key body; characters after the base64 run, such as a closing quote, are kept.
+///
+/// # Arguments
+/// * `text` - Text that may contain public keys, such as a remote command
+///
+/// # Returns
+/// * `String` - The text with key bodies redacted
+pub fn redact_key(text: &str) -> String {
+    let tokens: Vec<&str> = text.split(' ').collect();
+    let mut redacted = Vec::with_capacity(tokens.len());
+
+    for (idx, token) in tokens.iter().enumerate() {
+        let follows_key_type = idx > 0
+            && KEY_TYPES
+                .iter()
+                .any(|key_type| tokens[idx - 1].ends_with(key_type));
+
+        if follows_key_type {
+            let body_len = token
+                .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '=')))
+                .unwrap_or(token.len());
+            let (body, rest) = token.split_at(body_len);
+            if let Some(fingerprint) = fingerprint(body) {
+                redacted.push(format!("{}{}", fingerprint, rest));
+                continue;
+            }
+        }
+        redacted.push(token.to_string());
+    }
+
+    redacted.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that a key inside a quoted remote command is replaced by its fingerprint
+    #[test]
+    fn test_redact_key_in_remote_command() {
+        let body = general_purpose::STANDARD.encode(b"\0\0\0\x0bssh-ed25519 test key blob");
+        let cmd = format!(
+            "mkdir -p ~/.ssh && echo 'ssh-ed25519 {} me@laptop' >> ~/.ssh/authorized_keys",
+            body
+        );
+
+        let redacted = redact_key(&cmd);
+        assert!(!redacted.contains(&body));
+        assert!(redacted.contains(&format!(
+            "'ssh-ed25519 {} me@laptop'",
+            fingerprint(&body).unwrap()
+        )));
+        assert!(redacted.ends_with(">> ~/.ssh/authorized_keys"));
+    }
+
+    /// Test that text without keys is left untouched
+    #[test]
+    fn test_redact_key_passthrough() {
+        let cmd = "gcloud compute ssh vm --zone us-central1-a";
+        assert_eq!(redact_key(cmd), cmd);
+    }
+}
@@ -1,43 +1,58 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use colored::Colorize;
-use dialoguer::{theme::ColorfulTheme, Select};
+use dialoguer::{theme::ColorfulTheme, Confirm, Input, MultiSelect, Select};
 use serde::Deserialize;
-use std::{env, fs, io, process::Command};
-use thiserror::Error;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    ffi::OsString,
+    fs,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::atomic::{AtomicBool, Ordering},
+    thread::JoinHandle,
+    time::Duration,
+};
 
 // Import our enhanced terminal interface library
-mod banner;
-mod config;
-mod term_utils;
-mod terminal_fx;
+use hcloud::{
+    banner::{self, ListField},
+    cli, config, config_report,
+    error::{self, AppError},
+    favorites::Favorites,
+    gcloud::{self, GcloudCommand},
+    hosts,
+    icons::{self, Icon},
+    inventory::{self, InventoryFormat, InventoryHost},
+    logging,
+    messages::{self, Message},
+    pager, parallel,
+    render::{self, InstanceSummary, IpKind, RdpInfo},
+    settings, ssh_config, storage, term_utils, terminal_fx,
+    update::{self, UpdateStatus},
+    user_config::{self, LocalConfig, OsUsers, UserConfig},
+    zones,
+};
+
+// Favorites scope used for hosts loaded from a hosts file
+const HOSTS_FILE_SCOPE: &str = "hosts-file";
 
 // Version of the application
 const VERSION: &str = "0.1.0";
 
-/// Custom error type for our application.
-/// This provides more descriptive errors than using anyhow alone.
-#[derive(Error, Debug)]
-enum AppError {
-    #[error("No SSH key found and failed to generate one: {0}")]
-    SshKeyGeneration(String),
-
-    #[error("Failed to list VM instances: {0}")]
-    VmListing(String),
-
-    #[error("No VM instances found in the active project")]
-    NoVmsFound,
-
-    #[error("Failed to select VM: {0}")]
-    VmSelection(String),
+// `--write-command` targets that name the standard output
+const STDOUT_PATHS: [&str; 2] = ["/dev/stdout", "/dev/fd/1"];
 
-    #[error("Failed to copy SSH key to VM: {0}")]
-    KeyCopy(String),
+/// Where an instance was loaded from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum InstanceSource {
+    /// Listed by gcloud, with a real zone and project
+    #[default]
+    Gcloud,
 
-    #[error("VM does not have an external IP address")]
-    NoExternalIp,
-
-    #[error("IO error: {0}")]
-    Io(#[from] io::Error),
+    /// Read from a static hosts file (`--hosts-file`), with no zone
+    HostsFile,
 }
 
 /// Represents a Google Cloud VM instance
@@ -53,39 +68,376 @@ struct Instance {
     /// Network interfaces attached to the VM
     #[serde(rename = "networkInterfaces")]
     network_interfaces: Vec<NetworkInterface>,
+
+    /// Lifecycle status reported by gcloud (e.g. "RUNNING", "TERMINATED")
+    #[serde(default)]
+    status: String,
+
+    /// Labels attached to the VM
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+
+    /// Machine type URL (e.g. ".../zones/us-central1-a/machineTypes/e2-medium")
+    #[serde(rename = "machineType", default)]
+    machine_type_url: String,
+
+    /// Disks attached to the VM, whose licenses reveal the operating system
+    #[serde(default)]
+    disks: Vec<AttachedDisk>,
+
+    /// Remote username, when known (hosts-file entries, `--user` or a user label)
+    #[serde(skip)]
+    user: Option<String>,
+
+    /// Where the instance was loaded from
+    #[serde(skip)]
+    source: InstanceSource,
+
+    /// Set when `--check-access` found that the user cannot SSH into the VM
+    #[serde(skip)]
+    access_denied: bool,
+
+    /// Network interface chosen with `--nic`; `None` searches every interface
+    #[serde(skip)]
+    nic: Option<usize>,
+
+    /// Pick a static external IP over an ephemeral one (`--prefer-static-ip`)
+    #[serde(skip)]
+    prefer_static_ip: bool,
+
+    /// Project the VM was listed from with `--projects`/`--all-projects`
+    #[serde(skip)]
+    project: Option<String>,
 }
 
 impl Instance {
+    /// Builds an instance from a static hosts-file entry
+    fn from_host_entry(entry: hosts::HostEntry) -> Self {
+        Instance {
+            name: entry.name,
+            zone_url: String::new(),
+            network_interfaces: vec![NetworkInterface {
+                access_configs: vec![AccessConfig {
+                    nat_ip: Some(entry.host),
+                    reserved: None,
+                }],
+                ipv6_access_configs: Vec::new(),
+                network_ip: None,
+            }],
+            status: String::new(),
+            labels: BTreeMap::new(),
+            machine_type_url: String::new(),
+            disks: Vec::new(),
+            user: entry.user,
+            source: InstanceSource::HostsFile,
+            access_denied: false,
+            nic: None,
+            prefer_static_ip: false,
+            project: None,
+        }
+    }
+
+    /// Label that marks a VM's operating system, e.g. `os=windows-2022`
+    const OS_LABEL: &'static str = "os";
+
+    /// Checks whether the VM runs Windows
+    ///
+    /// Windows images carry a license from the `windows-cloud` project and the
+    /// `WINDOWS` guest OS feature; an `os` label starting with "windows" also counts.
+    fn is_windows(&self) -> bool {
+        let labeled = self
+            .labels
+            .get(Self::OS_LABEL)
+            .is_some_and(|os| os.to_lowercase().starts_with("windows"));
+        labeled
+            || self.disks.iter().any(|disk| {
+                disk.licenses
+                    .iter()
+                    .any(|license| license.contains("/projects/windows-cloud/"))
+                    || disk
+                        .guest_os_features
+                        .iter()
+                        .any(|feature| feature.kind == "WINDOWS")
+            })
+    }
+
+    /// Resolves the remote username for this instance
+    ///
+    /// An explicit `--user` wins; otherwise the user from the hosts file, the
+    /// `user_label` instance label or the default user of the VM's image is
+    /// used, leaving `None` for the local username.
+    ///
+    /// # Arguments
+    /// * `explicit` - The username given with `--user`, if any
+    /// * `user_label` - Name of the label holding the login user
+    /// * `os_users` - Image families mapped to their login users
+    fn with_login_user(
+        mut self,
+        explicit: Option<&str>,
+        user_label: &str,
+        os_users: &OsUsers,
+    ) -> Self {
+        if let Some(user) = explicit {
+            self.user = Some(user.to_string());
+        } else if self.user.is_none() {
+            self.user = self
+                .labels
+                .get(user_label)
+                .filter(|user| !user.is_empty())
+                .cloned()
+                .or_else(|| os_users.user_for(&self.licenses()).map(str::to_string));
+        }
+        self
+    }
+
+    /// Returns the license URLs of every attached disk
+    fn licenses(&self) -> Vec<String> {
+        self.disks
+            .iter()
+            .flat_map(|disk| disk.licenses.iter().cloned())
+            .collect()
+    }
+
+    /// Returns the `[USER@]NAME` target for `gcloud compute ssh` and `scp`
+    fn ssh_target(&self) -> String {
+        match &self.user {
+            Some(user) => format!("{}@{}", user, self.name),
+            None => self.name.clone(),
+        }
+    }
+
+    /// Whether this instance came from a static hosts file rather than gcloud
+    fn is_static(&self) -> bool {
+        self.source == InstanceSource::HostsFile
+    }
+
     /// Extracts just the zone name from the full zone URL
     fn zone(&self) -> String {
         // The zone URL is formatted like: "https://www.googleapis.com/compute/v1/projects/PROJECT_ID/zones/ZONE_NAME"
         // We only want the ZONE_NAME part
-        self.zone_url
-            .split('/')
-            .last()
-            .unwrap_or(&self.zone_url)
-            .to_string()
+        last_url_segment(&self.zone_url)
+    }
+
+    /// Gets the internal IP address of the VM on the searched interfaces
+    fn internal_ip(&self) -> Option<String> {
+        self.interfaces()
+            .find_map(|interface| interface.network_ip.clone())
+    }
+
+    /// Returns the normalized details every listing and output format shows
+    ///
+    /// This is the one place that decides how zones, addresses and machine
+    /// types are read from the gcloud description, so all formats agree.
+    fn summary(&self) -> InstanceSummary {
+        InstanceSummary {
+            name: self.name.clone(),
+            zone: (!self.is_static()).then(|| self.zone()),
+            external_ip: self.external_ip(),
+            external_ip_kind: self.external_ip_kind(),
+            external_ipv6: self.ipv6(),
+            internal_ip: self.internal_ip(),
+            status: self.status.clone(),
+            machine_type: last_url_segment(&self.machine_type_url),
+            labels: self.labels.clone(),
+        }
+    }
+
+    /// Returns the zone for use in a gcloud `--zone` flag, refusing an empty one
+    ///
+    /// # Returns
+    /// * `Result<String>` - The zone name or an error if it couldn't be determined
+    fn gcloud_zone(&self) -> Result<String> {
+        let zone = self.zone();
+        if zone.is_empty() {
+            return Err(AppError::VmSelection(format!(
+                "could not determine the zone of VM {} from '{}'",
+                self.name, self.zone_url
+            ))
+            .into());
+        }
+        Ok(zone)
+    }
+
+    /// Restricts address lookups to one network interface
+    ///
+    /// # Arguments
+    /// * `nic` - The interface index given with `--nic` (0 is `nic0`), if any
+    ///
+    /// # Returns
+    /// * `Result<Self>` - The instance, or an error if it has no such interface
+    fn with_nic(mut self, nic: Option<usize>) -> Result<Self> {
+        if let Some(nic) = nic {
+            if nic >= self.network_interfaces.len() {
+                return Err(AppError::NoSuchNic(
+                    self.name.clone(),
+                    nic,
+                    self.network_interfaces.len(),
+                )
+                .into());
+            }
+        }
+        self.nic = nic;
+        Ok(self)
+    }
+
+    /// The network interfaces searched for addresses, in order (nic0 first)
+    fn interfaces(&self) -> impl Iterator<Item = &NetworkInterface> {
+        self.network_interfaces
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| self.nic.is_none_or(|nic| nic == *idx))
+            .map(|(_, interface)| interface)
     }
 
     /// Gets the external IP address of the VM, if available
+    ///
+    /// The primary interface is preferred; later interfaces are searched when it
+    /// has no external address, unless `--nic` picked one.
     fn external_ip(&self) -> Option<String> {
-        // Get the first network interface
-        self.network_interfaces.first().and_then(|interface| {
-            // Get the first access config with a natIP
+        self.external_access_config()
+            .and_then(|config| config.nat_ip.clone())
+    }
+
+    /// Tells whether the external IP address is static or ephemeral
+    ///
+    /// # Returns
+    /// * `Option<IpKind>` - The kind, or None without an external IP or when the
+    ///   project's reserved addresses couldn't be read
+    fn external_ip_kind(&self) -> Option<IpKind> {
+        self.external_access_config()
+            .and_then(|config| config.reserved)
+            .map(|reserved| {
+                if reserved {
+                    IpKind::Static
+                } else {
+                    IpKind::Ephemeral
+                }
+            })
+    }
+
+    /// Returns the access config holding the external IP address to use
+    ///
+    /// The first one with an address wins, unless `--prefer-static-ip` asks for
+    /// the first static one when there is one.
+    fn external_access_config(&self) -> Option<&AccessConfig> {
+        let mut configs = self
+            .interfaces()
+            .flat_map(|interface| &interface.access_configs)
+            .filter(|config| config.nat_ip.is_some());
+        if self.prefer_static_ip {
+            let configs: Vec<&AccessConfig> = configs.collect();
+            return configs
+                .iter()
+                .find(|config| config.reserved == Some(true))
+                .or(configs.first())
+                .copied();
+        }
+        configs.next()
+    }
+
+    /// Records which external addresses are reserved, from the project's address list
+    ///
+    /// # Arguments
+    /// * `reserved` - Every reserved external address in the VM's project
+    fn mark_reserved(&mut self, reserved: &BTreeSet<String>) {
+        for interface in &mut self.network_interfaces {
+            for config in &mut interface.access_configs {
+                config.reserved = config
+                    .nat_ip
+                    .as_ref()
+                    .map(|address| reserved.contains(address));
+            }
+        }
+    }
+
+    /// Gets the external IPv6 address of the VM, if available
+    fn ipv6(&self) -> Option<String> {
+        self.interfaces().find_map(|interface| {
             interface
-                .access_configs
+                .ipv6_access_configs
                 .iter()
-                .find_map(|config| config.nat_ip.clone())
+                .find_map(|config| config.external_ipv6.clone())
+        })
+    }
+
+    /// Returns the address to put in an SSH destination
+    ///
+    /// IPv4 is preferred unless `ipv6` is set; IPv6 addresses are bracketed.
+    ///
+    /// # Arguments
+    /// * `ipv6` - Whether to use the IPv6 address
+    fn ssh_address(&self, ipv6: bool) -> Option<String> {
+        self.address(ipv6).map(|address| {
+            if address.contains(':') {
+                format!("[{}]", address)
+            } else {
+                address
+            }
         })
     }
+
+    /// Returns the external address to connect to, preferring IPv4 unless `ipv6` is set
+    ///
+    /// # Arguments
+    /// * `ipv6` - Whether to use the IPv6 address
+    fn address(&self, ipv6: bool) -> Option<String> {
+        if ipv6 {
+            self.ipv6()
+        } else {
+            self.external_ip().or_else(|| self.ipv6())
+        }
+    }
+}
+
+/// Returns the last non-empty segment of a gcloud resource URL
+///
+/// Trailing slashes and empty segments are ignored, and a bare name is
+/// returned unchanged.
+///
+/// # Arguments
+/// * `url` - The resource URL, e.g. ".../projects/p/zones/us-central1-a"
+fn last_url_segment(url: &str) -> String {
+    url.split('/')
+        .map(str::trim)
+        .rfind(|segment| !segment.is_empty())
+        .unwrap_or_default()
+        .to_string()
 }
 
 /// Represents a network interface attached to a VM
 #[derive(Debug, Deserialize, Clone)]
 struct NetworkInterface {
     /// Configuration for external access
-    #[serde(rename = "accessConfigs")]
+    #[serde(rename = "accessConfigs", default)]
     access_configs: Vec<AccessConfig>,
+
+    /// Configuration for external IPv6 access
+    #[serde(rename = "ipv6AccessConfigs", default)]
+    ipv6_access_configs: Vec<Ipv6AccessConfig>,
+
+    /// Internal IPv4 address on the VPC network
+    #[serde(rename = "networkIP", default)]
+    network_ip: Option<String>,
+}
+
+/// A disk attached to a VM
+#[derive(Debug, Deserialize, Clone)]
+struct AttachedDisk {
+    /// License URLs of the disk's image
+    #[serde(default)]
+    licenses: Vec<String>,
+
+    /// Features of the guest operating system
+    #[serde(rename = "guestOsFeatures", default)]
+    guest_os_features: Vec<GuestOsFeature>,
+}
+
+/// A guest operating system feature, such as `WINDOWS` or `UEFI_COMPATIBLE`
+#[derive(Debug, Deserialize, Clone)]
+struct GuestOsFeature {
+    /// The feature name
+    #[serde(rename = "type")]
+    kind: String,
 }
 
 /// Configuration for external network access
@@ -94,25 +446,65 @@ struct AccessConfig {
     /// External IP address, if assigned
     #[serde(rename = "natIP")]
     nat_ip: Option<String>,
+
+    /// Whether the address is reserved (static), once the project's addresses were checked
+    #[serde(skip)]
+    reserved: Option<bool>,
+}
+
+/// Configuration for external IPv6 access
+#[derive(Debug, Deserialize, Clone)]
+struct Ipv6AccessConfig {
+    /// External IPv6 address, if assigned
+    #[serde(rename = "externalIpv6")]
+    external_ipv6: Option<String>,
+}
+
+/// Prints the application title line shared by `--version` and `--help`
+fn print_title() {
+    println!(
+        "{} {}",
+        "Google Cloud SSH Manager".bright_cyan().bold(),
+        format!("v{}", VERSION).bright_white()
+    );
+    println!(
+        "{}",
+        "A tool to automate SSH access to Google Cloud VMs".italic()
+    );
 }
 
 /// Prints the version information and exits
-fn print_version() {
-    println!("Google Cloud SSH Manager v{}", VERSION);
-    println!("A tool to automate SSH access to Google Cloud VMs");
+fn print_version() -> ! {
+    print_title();
     std::process::exit(0);
 }
 
 /// Prints the help message and exits
-fn print_help() {
-    println!("Google Cloud SSH Manager v{}", VERSION);
-    println!("A tool to automate SSH access to Google Cloud VMs\n");
-    println!("USAGE:");
-    println!("  gcloud-ssh [OPTIONS]\n");
-    println!("OPTIONS:");
-    println!("  -h, --help     Print this help message");
-    println!("  -v, --version  Print version information");
-    println!("  --update       Check for updates and install them");
+fn print_help() -> ! {
+    // Descriptions start in this column; longer flags put them on the next line
+    const DESCRIPTION_COLUMN: usize = 26;
+
+    print_title();
+    println!("\n{}", "USAGE:".yellow().bold());
+    println!(
+        "  {} {}",
+        "gcloud-ssh".bright_white().bold(),
+        "[OPTIONS]".bright_green()
+    );
+
+    for section in cli::HELP_SECTIONS {
+        println!("\n{}", format!("{}:", section.title).yellow().bold());
+        for (usage, description) in section.entries {
+            let flag = format!("  {}", usage);
+            if flag.len() < DESCRIPTION_COLUMN {
+                let padding = " ".repeat(DESCRIPTION_COLUMN - flag.len());
+                println!("{}{}{}", flag.bright_green(), padding, description);
+            } else {
+                println!("{}", flag.bright_green());
+                println!("{}{}", " ".repeat(DESCRIPTION_COLUMN), description);
+            }
+        }
+    }
     std::process::exit(0);
 }
 
@@ -120,10 +512,23 @@ fn print_help() {
 fn check_for_updates() -> Result<()> {
     println!("{}", "Checking for updates...".blue());
 
-    // In a real application, this would connect to a server to check for updates
-    // For this example, we'll just display a message
+    match update::check()? {
+        UpdateStatus::UpToDate => {
+            println!("{}", "You're running the latest version!".green());
+            return Ok(());
+        }
+        UpdateStatus::Available(version) => println!(
+            "{}",
+            format!("Version {} is available (you have {}).", version, VERSION).green()
+        ),
+        UpdateStatus::Failed(reason) => {
+            println!(
+                "{}",
+                format!("Could not check for updates: {}", reason).yellow()
+            )
+        }
+    }
 
-    println!("{}", "You're running the latest version!".green());
     println!("If you want to update manually, run the following commands:");
     println!("  1. git pull");
     println!("  2. cargo build --release");
@@ -132,95 +537,446 @@ fn check_for_updates() -> Result<()> {
     Ok(())
 }
 
+/// Prints a one-line notice if the background update check found a newer release
+///
+/// A check that hasn't finished yet is abandoned rather than waited for, and
+/// failures stay silent.
+///
+/// # Arguments
+/// * `check` - The background check started at startup, if any
+fn report_update_check(check: Option<JoinHandle<Result<UpdateStatus>>>) {
+    let Some(check) = check.filter(|check| check.is_finished()) else {
+        return;
+    };
+    if let Ok(Ok(UpdateStatus::Available(version))) = check.join() {
+        render::renderer().info(&format!(
+            "Version {} is available (you have {}); run gcloud-ssh --update",
+            version, VERSION
+        ));
+    }
+}
+
 /// Parses command-line arguments
-fn parse_args() {
-    let args: Vec<String> = env::args().collect();
-
-    if args.len() > 1 {
-        match args[1].as_str() {
-            "-h" | "--help" => print_help(),
-            "-v" | "--version" => print_version(),
-            "--update" => {
-                if let Err(e) = check_for_updates() {
-                    eprintln!("Error checking for updates: {}", e);
-                    std::process::exit(1);
-                }
-                std::process::exit(0);
-            }
-            _ => {
-                eprintln!("Unknown option: {}", args[1]);
-                eprintln!("Run with --help for usage information");
+///
+/// # Returns
+/// * `cli::Options` - The options for the interactive flow
+fn parse_args() -> cli::Options {
+    // Help and version are printed before options are resolved, so decide color for them too
+    let early = cli::Options {
+        no_color: env::args().any(|arg| arg == "--no-color"),
+        ..Default::default()
+    };
+    settings::install(settings::Settings::resolve(&early));
+
+    match cli::parse(env::args().skip(1)) {
+        Ok(cli::Invocation::Help) => print_help(),
+        Ok(cli::Invocation::Version) => print_version(),
+        Ok(cli::Invocation::Update) => {
+            if let Err(e) = check_for_updates() {
+                eprintln!("Error checking for updates: {}", e);
                 std::process::exit(1);
             }
+            std::process::exit(0);
+        }
+        Ok(cli::Invocation::Run(options)) => *options,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("Run with --help for usage information");
+            std::process::exit(1);
         }
     }
 }
 
-/// Main function that orchestrates the application flow
-fn main() -> Result<()> {
-    // Parse command-line arguments
-    parse_args();
+/// Set once the interactive flow starts when `--bell` or `ui.bell_on_complete` asks
+/// for a bell at the end, whether the flow succeeds or fails
+static BELL_ON_COMPLETE: AtomicBool = AtomicBool::new(false);
 
-    // Initialize terminal interface
-    term_utils::clear_screen();
+/// Entry point: runs the application, adds hints to well-known gcloud failures
+/// and exits with the code matching the error
+fn main() {
+    // Leave a usable terminal behind however the run is stopped; without the
+    // handlers the default actions still end the process
+    #[cfg(unix)]
+    let _ = term_utils::install_signal_cleanup();
+
+    let result = run();
+    if BELL_ON_COMPLETE.load(Ordering::Relaxed) {
+        term_utils::ring_bell();
+    }
+    if let Err(e) = result {
+        eprintln!("{}: {:?}", messages::text(Message::ErrorPrefix), e);
+        if let Some(hint) = gcloud::error_hint(&format!("{:#}", e)) {
+            eprintln!("{}", banner::info_message(hint));
+        }
+        std::process::exit(error::exit_code(&e));
+    }
+}
+
+/// Prints the effective settings with the source of each value
+///
+/// # Arguments
+/// * `layers` - The configuration at each stage of resolution
+/// * `json` - Whether to print a JSON array instead of aligned lines
+///
+/// # Returns
+/// * `Result<()>` - Success or error information
+fn print_config(layers: &config_report::Layers, json: bool) -> Result<()> {
+    let entries = config_report::entries(layers);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+    } else {
+        let file = storage::config_dir().map(|dir| dir.join(user_config::CONFIG_FILE));
+        if let Some(file) = file.filter(|file| file.is_file()) {
+            println!("# file: {}", file.display());
+        }
+        if let Some(local) = layers.local {
+            println!("# local file: {}", local.path.display());
+        }
+        print!("{}", config_report::render(&entries));
+    }
+    Ok(())
+}
 
-    // Display welcome banner
+/// Clears the screen and shows the banner with the typed welcome line
+fn display_welcome() {
+    term_utils::clear_screen();
     println!("{}", banner::main_banner());
 
     // Add a slight delay for visual effect
-    if config::animations::ENABLED {
-        terminal_fx::type_text(
-            &format!(
-                "Welcome to {}! Let's set up your SSH access.",
-                config::APP_TITLE
-            ),
-            config::animations::TYPING_SPEED_MS,
-        );
+    let welcome = messages::text(Message::Welcome).replace("{app}", config::APP_TITLE);
+    if settings::current().animations {
+        terminal_fx::type_text(&welcome, config::animations::TYPING_SPEED_MS);
     } else {
-        println!(
-            "Welcome to {}! Let's set up your SSH access.",
-            config::APP_TITLE
-        );
+        println!("{}", welcome);
+    }
+}
+
+/// Prints the active project, account and configuration, flagging production projects
+///
+/// Failing to read the context is not fatal; the listing reports gcloud errors.
+///
+/// # Arguments
+/// * `user_config` - Settings from the configuration file
+fn print_gcloud_context(user_config: &UserConfig) {
+    let context = match gcloud::context() {
+        Ok(context) => context,
+        Err(e) => {
+            logging::verbose(&format!("Could not read the gcloud context: {:#}", e));
+            return;
+        }
+    };
+
+    let production = match &context.project {
+        Some(project) => is_production(project, &user_config.ui.prod_pattern).unwrap_or_else(|e| {
+            render::renderer().warning(&format!("Ignoring ui.prod_pattern: {}", e));
+            false
+        }),
+        None => false,
+    };
+    println!(
+        "{}",
+        banner::context_line(
+            context.project.as_deref(),
+            context.account.as_deref(),
+            context.configuration.as_deref(),
+            production,
+        )
+    );
+}
+
+/// Checks whether a project ID matches the production pattern as a whole
+///
+/// # Arguments
+/// * `project` - The project ID
+/// * `pattern` - Regular expression from `ui.prod_pattern`; empty matches nothing
+///
+/// # Returns
+/// * `Result<bool>` - Whether the project is production, or an error for an invalid pattern
+fn is_production(project: &str, pattern: &str) -> Result<bool> {
+    if pattern.is_empty() {
+        return Ok(false);
+    }
+    let regex = regex::Regex::new(&format!("^(?:{})$", pattern))?;
+    Ok(regex.is_match(project))
+}
+
+/// Orchestrates the application flow
+fn run() -> Result<()> {
+    // Parse command-line arguments
+    let mut options = parse_args();
+    let flags = options.clone();
+
+    // Project-local and profile values act as defaults for the flags, so apply
+    // them before anything else
+    let mut user_config = UserConfig::load()?;
+    let local_config = match env::current_dir() {
+        Ok(cwd) => LocalConfig::discover(&cwd)?,
+        Err(_) => None,
+    };
+    let file_config = user_config.clone();
+    user_config.resolve(local_config.as_ref(), &mut options)?;
+    let options = options;
+    // A command written to a file or stdout is for scripts, so the UI goes to stderr
+    if options.write_command.is_some() {
+        term_utils::move_ui_to_stderr()?;
+    }
+    settings::install(settings::Settings::resolve(&options));
+    render::install(render_style(&options));
+    gcloud::set_program(gcloud::resolve_program(
+        options.gcloud_bin.as_deref(),
+        env::var_os(gcloud::GCLOUD_BIN_ENV).as_deref(),
+    ));
+    if let Some(local) = &local_config {
+        logging::verbose(&format!("Using defaults from {}", local.path.display()));
+    }
+    let problems = icons::install_overrides(&user_config.emojis)
+        .into_iter()
+        .chain(messages::install_overrides(&user_config.messages));
+    for problem in problems {
+        render::renderer().warning(&format!("Ignoring configured {}", problem));
+    }
+
+    // Showing the resolved settings needs neither gcloud nor SSH keys
+    if options.print_config {
+        let layers = config_report::Layers {
+            flags: &flags,
+            options: &options,
+            file: &file_config,
+            resolved: &user_config,
+            local: local_config.as_ref(),
+            settings: &settings::current(),
+        };
+        return print_config(&layers, options.json);
+    }
+
+    // Timing the startup phases replaces the interactive flow
+    if options.profile_startup {
+        return profile_startup(&options, &user_config);
+    }
+
+    // Previewing the display settings needs neither gcloud nor SSH keys
+    if options.preview {
+        println!("{}", banner::preview());
+        return Ok(());
+    }
+
+    // Check the gcloud binary before the first call; hosts files can do without it
+    if options.hosts_file.is_none() {
+        let program = gcloud::program();
+        if gcloud::locate_program(&program, env::var_os("PATH").as_deref()).is_none() {
+            return Err(AppError::GcloudNotFound(program.display().to_string()).into());
+        }
+    }
+
+    // Select the named gcloud configuration before any other gcloud call
+    let interactive = options.favorite.is_none()
+        && options.hosts_file.is_none()
+        && options.inventory.is_none()
+        && !options.json;
+    let mut global_flags = gcloud::GlobalFlags::from_options(&options);
+    global_flags.configuration = resolve_configuration(&options, interactive)?;
+    gcloud::set_global_flags(global_flags);
+
+    // Starring a VM is a standalone action
+    if let Some(name) = &options.favorite {
+        return toggle_favorite(&options, name);
+    }
+
+    // Only metadata entries carry an expiry, so refuse one for other copy methods
+    // before anything is listed or copied
+    if options.expire_after.is_some() && options.copy_method != cli::CopyMethod::Metadata {
+        return Err(AppError::ExpiryNeedsMetadata(options.copy_method.name().to_string()).into());
+    }
+
+    // Fail early, before any output, when there is nowhere to keep SSH keys
+    let ssh_dir = resolve_ssh_dir(
+        options.ssh_dir.as_deref(),
+        env::var_os("HOME"),
+        env::var_os("USERPROFILE"),
+    )
+    .ok_or(AppError::NoSshDir)?;
+
+    let favorites = Favorites::load().unwrap_or_else(|e| {
+        render::renderer().info(&format!("Ignoring unreadable favorites: {}", e));
+        Favorites::default()
+    });
+
+    // Watching the fleet is read-only and replaces the interactive flow
+    if options.watch {
+        return watch_instances(&options, &user_config, &favorites);
+    }
+
+    // Inventory output replaces the interactive flow
+    if let Some(format) = options.inventory {
+        return print_inventory(&options, &user_config, &favorites, format);
+    }
+
+    // JSON and shell exports must be the only thing on stdout, so skip the
+    // interactive flow
+    if options.json {
+        return print_json_listing(&options, &user_config, &favorites);
+    }
+    if options.info_format == cli::InfoFormat::Env {
+        return print_connection_env(&options, &user_config, &favorites);
+    }
+
+    // Data modes have returned by now, so the bell only ends interactive runs
+    BELL_ON_COMPLETE.store(
+        options.bell || user_config.ui.bell_on_complete,
+        Ordering::Relaxed,
+    );
+
+    // Look for a newer release while the intro runs; only interactive runs are told
+    let update_check =
+        (!options.no_update_check && term_utils::caps().is_tty).then(update::check_in_background);
+
+    // Narrow terminals mangle the banner and boxes, so say so and simplify the markers
+    let narrow_width = narrow_terminal_width(&user_config);
+    if narrow_width.is_some() && user_config.display.auto_downgrade {
+        let mut plain = settings::current();
+        plain.emoji = false;
+        settings::install(plain);
+    }
+
+    // Frequent users can skip the intro and go straight to the first section
+    if !options.no_welcome && user_config.ui.show_welcome {
+        display_welcome();
+    }
+    if let Some(width) = narrow_width {
+        render::renderer().info(&format!(
+            "This terminal is {} columns wide; {} or more gives the best layout",
+            width, user_config.display.min_width
+        ));
+    }
+
+    // Say where gcloud calls will land before anything is selected
+    if options.hosts_file.is_none() && user_config.ui.show_context {
+        print_gcloud_context(&user_config);
+    }
+    report_update_check(update_check);
+    if options.read_only {
+        render::renderer().warning(messages::text(Message::ReadOnlyNotice));
+    }
+
+    // Hosts from a static file bypass gcloud entirely
+    if let Some(hosts_file) = &options.hosts_file {
+        render::renderer().section(messages::text(Message::HostsFileSection));
+        let (instances, message) = load_static_hosts(hosts_file)?;
+        render::renderer().success(&message);
+        let starred = favorites.names(HOSTS_FILE_SCOPE);
+        let instances = apply_favorites(instances, &starred, options.favorites_only)?;
+
+        render::renderer().section(messages::text(Message::HostSelectionSection));
+        let selected_host = choose_instance(&instances, &starred, &options)
+            .context("Failed to select host")?
+            .with_login_user(
+                options.user.as_deref(),
+                &user_config.listing.user_label,
+                &user_config.os_users,
+            )
+            .with_nic(options.nic)?;
+
+        render::renderer().section(messages::text(Message::ConnectionSection));
+        print_ssh_command(&selected_host, &options)?;
+        if options.show_reconnect {
+            print_reconnect(&selected_host, &cli::Action::PrintCommand, &options);
+        }
+
+        term_utils::reset_terminal();
+        term_utils::show_cursor();
+        return Ok(());
     }
 
     // Step 1: Ensure SSH key exists
-    println!("{}", banner::section_header("SSH KEY MANAGEMENT"));
-    ensure_ssh_key().context("Failed to ensure SSH key exists")?;
+    render::renderer().section(messages::text(Message::KeyManagementSection));
+    // Keys picked with --identity-glob must already exist, so never generate one
+    if options.identity_glob.is_none() {
+        let allow_generate = !options.no_key_gen && user_config.keys.generate;
+        ensure_ssh_key(&ssh_dir, options.key_type, allow_generate)
+            .context("Failed to ensure SSH key exists")?;
+    }
 
-    // Step 2: List VM instances
-    println!("{}", banner::section_header("VM INSTANCES"));
+    // Step 2: List VM instances, naming the project when one was chosen
+    let instances_title = messages::text(Message::InstancesSection);
+    match &options.project {
+        Some(project) => {
+            render::renderer().section(&format!("{} · project {}", instances_title, project))
+        }
+        None => render::renderer().section(instances_title),
+    }
 
     // Display loading animation
-    if config::animations::ENABLED {
+    if settings::current().animations {
         terminal_fx::spinner(
             "Fetching VM instances...",
             config::animations::SPINNER_DURATION_MS,
         );
     }
 
-    let instances = list_vms().context("Failed to list VM instances")?;
-
-    // Step 3: Let user select a VM
-    println!("{}", banner::section_header("VM SELECTION"));
-    let selected_vm = select_vm(&instances).context("Failed to select VM")?;
+    let query = ListingQuery::resolve(&options, &user_config).marking_static_ips(
+        options.vm.is_none() && options.list_columns().contains(&cli::Column::Ip),
+    );
+    let instances = list_instances(&query, &options).context("Failed to list VM instances")?;
+    render::renderer().success(&listing_message(&query, instances.len()));
+    let instances = filter_instances(instances, zone_filter(&options)?.as_deref(), &options)?;
+    render::renderer().info(&fleet_summary(&instances));
+    let starred = starred_names(&favorites, &instances, &favorites_scope(&options));
+    let mut instances = apply_favorites(instances, &starred, options.favorites_only)?;
 
-    // Step 4: Copy SSH key to selected VM
-    println!("{}", banner::section_header("SSH KEY DEPLOYMENT"));
+    // With --vm only the named VM is checked, since no list is shown
+    if options.check_access && options.vm.is_none() {
+        check_access(
+            &mut instances,
+            &options,
+            &user_config.listing.user_label,
+            &user_config.os_users,
+        )?;
+    }
 
-    // Display progress animation
-    if config::animations::ENABLED {
-        terminal_fx::progress_bar(
-            "Copying SSH key to VM...",
-            config::animations::PROGRESS_BAR_STEPS,
-            config::animations::PROGRESS_BAR_DURATION_MS,
-        );
+    if options.multi {
+        return run_bulk(&instances, &starred, &options, &user_config, &ssh_dir);
     }
 
-    copy_ssh_key_to_vm(&selected_vm).context("Failed to copy SSH key to VM")?;
+    // Step 3: Let user select a VM
+    render::renderer().section(messages::text(Message::SelectionSection));
+    let mut selected_vm = choose_instance(&instances, &starred, &options)
+        .context("Failed to select VM")?
+        .with_login_user(
+            options.user.as_deref(),
+            &user_config.listing.user_label,
+            &user_config.os_users,
+        )
+        .with_nic(options.nic)?;
+    // VMs merged from several projects are managed in their own project
+    if let Some(project) = &selected_vm.project {
+        gcloud::set_project(project);
+    }
+    if options.check_access && options.vm.is_some() {
+        check_access(
+            std::slice::from_mut(&mut selected_vm),
+            &options,
+            &user_config.listing.user_label,
+            &user_config.os_users,
+        )?;
+    }
+    if selected_vm.access_denied {
+        render::renderer().warning(&format!(
+            "The access check for {} failed; connecting may be refused",
+            selected_vm.name
+        ));
+    }
 
-    // Step 5: Print SSH command
-    println!("{}", banner::section_header("CONNECTION INFORMATION"));
-    print_ssh_command(&selected_vm)?;
+    // Step 4: Act on the selected VM
+    let action = match &options.action {
+        Some(action) => action.clone(),
+        None if options.read_only => cli::Action::PrintCommand,
+        None if options.no_menu || !io::stdin().is_terminal() => cli::Action::CopyKey,
+        None => select_action(&selected_vm)?,
+    };
+    run_action(&action, &selected_vm, &options, &user_config, &ssh_dir)?;
+    if options.show_reconnect {
+        print_reconnect(&selected_vm, &action, &options);
+    }
 
     // Clean up terminal state
     term_utils::reset_terminal();
@@ -229,231 +985,4164 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-/// Ensures that an SSH key pair exists, generating one if it doesn't
+/// Returns the terminal width when it is below `display.min_width`
+///
+/// A width forced with `--width` is the user's choice and never warned about.
+///
+/// # Arguments
+/// * `user_config` - Settings from the configuration file
+///
+/// # Returns
+/// * `Option<usize>` - The detected width, if the terminal is too narrow
+fn narrow_terminal_width(user_config: &UserConfig) -> Option<usize> {
+    if settings::current().width.is_some() {
+        return None;
+    }
+    let width = term_utils::caps().width;
+    (width < user_config.display.min_width).then_some(width)
+}
+
+/// Determines the directory holding the SSH key pair
+///
+/// `--ssh-dir` wins; otherwise `.ssh` under `HOME`, then `USERPROFILE`, then the
+/// platform home directory.
+///
+/// # Arguments
+/// * `explicit` - The directory given with `--ssh-dir`, if any
+/// * `home` - The value of `HOME`
+/// * `userprofile` - The value of `USERPROFILE`
+///
+/// # Returns
+/// * `Option<PathBuf>` - The SSH directory, or `None` if no home could be found
+fn resolve_ssh_dir(
+    explicit: Option<&Path>,
+    home: Option<OsString>,
+    userprofile: Option<OsString>,
+) -> Option<PathBuf> {
+    if let Some(dir) = explicit {
+        return Some(dir.to_path_buf());
+    }
+
+    home.into_iter()
+        .chain(userprofile)
+        .find(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(dirs::home_dir)
+        .map(|home| home.join(".ssh"))
+}
+
+/// Times each startup phase and prints a plain breakdown (`--profile-startup`)
+///
+/// # Arguments
+/// * `options` - Parsed command-line options
+/// * `user_config` - Settings from the configuration file
 ///
 /// # Returns
 /// * `Result<()>` - Success or error information
-fn ensure_ssh_key() -> Result<()> {
-    // Get the path to the user's .ssh directory
-    let ssh_dir = dirs::home_dir()
-        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Could not find home directory"))?
-        .join(".ssh");
+fn profile_startup(options: &cli::Options, user_config: &UserConfig) -> Result<()> {
+    let mut phases = Vec::new();
 
+    let (_, elapsed) = logging::time_it("terminal detection", term_utils::TerminalCaps::detect);
+    phases.push(("terminal detection", elapsed, None));
+
+    let (configurations, elapsed) =
+        logging::time_it("gcloud configurations", gcloud::configurations);
+    phases.push(("gcloud configurations", elapsed, configurations.err()));
+
+    let (_, elapsed) = logging::time_it("gcloud project", active_project);
+    phases.push(("gcloud project", elapsed, None));
+
+    let query = ListingQuery::resolve(options, user_config);
+    let (instances, elapsed) = logging::time_it("vm listing", || list_vms(&query, None));
+    phases.push(("vm listing", elapsed, instances.err()));
+
+    let total: Duration = phases.iter().map(|(_, elapsed, _)| *elapsed).sum();
+    for (label, elapsed, error) in &phases {
+        let note = error
+            .as_ref()
+            .map(|e| {
+                let message = e.to_string();
+                format!("  (failed: {})", message.lines().next().unwrap_or_default())
+            })
+            .unwrap_or_default();
+        println!(
+            "{:<24}{:>10.1} ms{}",
+            label,
+            elapsed.as_secs_f64() * 1000.0,
+            note
+        );
+    }
+    println!("{:<24}{:>10.1} ms", "total", total.as_secs_f64() * 1000.0);
+    Ok(())
+}
+
+/// Ensures that an SSH key pair of the requested type exists, generating one if it doesn't
+///
+/// RSA keys are generated with `gcloud compute ssh-keys create`; other types with
+/// `ssh-keygen`, without a passphrase.
+///
+/// # Arguments
+/// * `ssh_dir` - Directory holding the key pair
+/// * `key_type` - The kind of key pair to look for and generate
+/// * `allow_generate` - Whether a missing key may be generated
+///
+/// # Returns
+/// * `Result<()>` - Success or error information
+fn ensure_ssh_key(ssh_dir: &Path, key_type: cli::KeyType, allow_generate: bool) -> Result<()> {
     // Create the .ssh directory if it doesn't exist
     if !ssh_dir.exists() {
-        println!("{}", banner::info_message("Creating ~/.ssh directory..."));
-        fs::create_dir_all(&ssh_dir).context("Failed to create ~/.ssh directory")?;
+        render::renderer().info(&format!("Creating {} directory...", ssh_dir.display()));
+        fs::create_dir_all(ssh_dir)
+            .with_context(|| format!("Failed to create {}", ssh_dir.display()))?;
 
         // Set appropriate permissions for .ssh directory (700)
         #[cfg(unix)]
         {
             use std::os::unix::fs::PermissionsExt;
-            fs::set_permissions(&ssh_dir, fs::Permissions::from_mode(0o700))
-                .context("Failed to set permissions on ~/.ssh directory")?;
+            fs::set_permissions(ssh_dir, fs::Permissions::from_mode(0o700))
+                .with_context(|| format!("Failed to set permissions on {}", ssh_dir.display()))?;
         }
     }
 
     // Check if public key exists
-    let pub_key_path = ssh_dir.join("id_rsa.pub");
-    let priv_key_path = ssh_dir.join("id_rsa");
+    let priv_key_path = ssh_dir.join(key_type.file_name());
+    let pub_key_path = ssh_dir.join(format!("{}.pub", key_type.file_name()));
 
     if pub_key_path.exists() && priv_key_path.exists() {
-        println!(
-            "{}",
-            banner::success_message("SSH key pair already exists.")
-        );
+        render::renderer().success("SSH key pair already exists.");
         return Ok(());
     }
 
+    if !allow_generate {
+        return Err(AppError::KeyGenerationDisabled(ssh_dir.display().to_string()).into());
+    }
+
     // Generate new SSH key pair using gcloud
-    println!(
-        "{}",
-        banner::info_message("No SSH key found. Generating new key pair...")
-    );
+    render::renderer().info("No SSH key found. Generating new key pair...");
 
     // Display spinner animation for key generation
-    if config::animations::ENABLED {
+    if settings::current().animations {
         terminal_fx::spinner("Generating SSH key pair...", 3000);
     }
 
-    // Use gcloud to generate the key
-    let output = Command::new("gcloud")
-        .args(["compute", "ssh-keys", "create"])
-        .output()?;
+    if key_type == cli::KeyType::Rsa {
+        // Use gcloud to generate the key
+        let mut command = GcloudCommand::new()
+            .args(["compute", "ssh-keys", "create"])
+            .mutating()
+            .build()?;
+        let status = gcloud::attach(&mut command)?;
 
-    if !output.status.success() {
-        let error_msg = String::from_utf8_lossy(&output.stderr);
-        return Err(AppError::SshKeyGeneration(error_msg.to_string()).into());
+        if !status.success() {
+            return Err(
+                AppError::SshKeyGeneration(format!("gcloud exited with {}", status)).into(),
+            );
+        }
+    } else {
+        generate_with_ssh_keygen(&priv_key_path, key_type)?;
     }
 
-    println!(
-        "{}",
-        banner::success_message("SSH key generated successfully.")
-    );
+    render::renderer().success("SSH key generated successfully.");
+    Ok(())
+}
+
+/// Generates a key pair with `ssh-keygen`, leaving the private key readable only by its owner
+///
+/// # Arguments
+/// * `priv_key_path` - Where the private key goes; the public key gets `.pub` appended
+/// * `key_type` - The kind of key pair to generate
+///
+/// # Returns
+/// * `Result<()>` - Success or error information
+fn generate_with_ssh_keygen(priv_key_path: &Path, key_type: cli::KeyType) -> Result<()> {
+    let mut command = local_command(
+        "ssh-keygen",
+        &format!("generating {}", priv_key_path.display()),
+    )?;
+    command
+        .args(["-q", "-t", key_type.name(), "-N", "", "-f"])
+        .arg(priv_key_path);
+    logging::command(&command);
+    let status = command
+        .status()
+        .map_err(|e| AppError::SshKeyGeneration(format!("could not run ssh-keygen: {}", e)))?;
+
+    if !status.success() {
+        return Err(
+            AppError::SshKeyGeneration(format!("ssh-keygen exited with {}", status)).into(),
+        );
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(priv_key_path, fs::Permissions::from_mode(0o600))
+            .with_context(|| format!("Failed to set permissions on {}", priv_key_path.display()))?;
+    }
     Ok(())
 }
 
-/// Lists all VM instances in the active Google Cloud project
+/// Narrowing applied by gcloud when listing instances
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct ListingQuery {
+    /// gcloud `--filter` expression
+    filter: Option<String>,
+
+    /// Maximum number of instances to return
+    limit: Option<u32>,
+
+    /// Whether reserved addresses are looked up to tell static IPs from ephemeral ones
+    static_ips: bool,
+}
+
+impl ListingQuery {
+    /// Resolves the listing query from flags and the configuration file
+    ///
+    /// A VM named with `--vm` bypasses the default filter and limit so it can
+    /// always be found; `--all` drops the configured defaults.
+    ///
+    /// # Arguments
+    /// * `options` - Parsed command-line options
+    /// * `config` - The user configuration
+    fn resolve(options: &cli::Options, config: &UserConfig) -> Self {
+        let static_ips = options.prefer_static_ip;
+        if options.vm.is_some() {
+            return ListingQuery {
+                static_ips,
+                ..Default::default()
+            };
+        }
+
+        let use_defaults = !options.all;
+        let filter = Some(config.listing.default_filter.clone())
+            .filter(|filter| use_defaults && !filter.is_empty());
+        let limit = options
+            .limit
+            .or(config.listing.limit.filter(|_| use_defaults));
+
+        ListingQuery {
+            filter,
+            limit,
+            static_ips,
+        }
+    }
+
+    /// Also looks up reserved addresses when the static IP marker will be shown
+    ///
+    /// # Arguments
+    /// * `shown` - Whether the output marks static external IPs
+    fn marking_static_ips(mut self, shown: bool) -> Self {
+        self.static_ips |= shown;
+        self
+    }
+
+    /// Returns the gcloud arguments for this query
+    fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(filter) = &self.filter {
+            args.push(format!("--filter={}", filter));
+        }
+        if let Some(limit) = self.limit {
+            args.push(format!("--limit={}", limit));
+        }
+        args
+    }
+}
+
+/// Lists VM instances in the active Google Cloud project
+///
+/// # Arguments
+/// * `query` - Filter and limit applied by gcloud
+/// * `project` - Project to list instead of the active one, tagged on each instance
 ///
 /// # Returns
 /// * `Result<Vec<Instance>>` - List of VM instances or error
-fn list_vms() -> Result<Vec<Instance>> {
+fn list_vms(query: &ListingQuery, project: Option<&str>) -> Result<Vec<Instance>> {
     // Execute gcloud command to list instances in JSON format
-    let output = Command::new("gcloud")
+    let mut gcloud = GcloudCommand::new()
         .args(["compute", "instances", "list", "--format=json"])
-        .output()?;
+        .args(query.args());
+    if let Some(project) = project {
+        gcloud = gcloud.project(project);
+    }
+    let output = gcloud::capture(&mut gcloud.build()?)?;
 
     if !output.status.success() {
-        let error_msg = String::from_utf8_lossy(&output.stderr);
-        return Err(AppError::VmListing(error_msg.to_string()).into());
+        return Err(AppError::VmListing(gcloud::error_message(&output.stderr)).into());
     }
 
-    // Parse JSON output into our Instance struct
-    let instances: Vec<Instance> =
-        serde_json::from_slice(&output.stdout).context("Failed to parse VM instance JSON data")?;
+    // Parse JSON output into our Instance struct, skipping any noise around it
+    let raw = String::from_utf8_lossy(&output.stdout);
+    let parsed = match extract_json(&raw) {
+        Some(json) => parse_instances(json),
+        None => Err("no complete JSON value".to_string()),
+    };
+    let mut instances: Vec<Instance> = parsed.map_err(|reason| {
+        let excerpt: String = raw.chars().take(200).collect();
+        AppError::VmListing(format!(
+            "could not parse gcloud output ({}); output began with: {:?}",
+            reason, excerpt
+        ))
+    })?;
 
     // Check if we found any instances
     if instances.is_empty() {
         return Err(AppError::NoVmsFound.into());
     }
 
-    println!(
-        "{}",
-        banner::success_message(&format!("Found {} VM instances.", instances.len()))
-    );
+    for instance in &mut instances {
+        instance.project = project.map(str::to_string);
+    }
     Ok(instances)
 }
 
-/// Allows the user to select a VM from the list
+/// A gcloud call made while listing instances
+#[derive(Debug, Clone, Copy)]
+enum ListingCall<'a> {
+    /// List a project's instances
+    Instances(Option<&'a str>),
+
+    /// Look up a project's reserved addresses
+    ReservedAddresses(Option<&'a str>),
+}
+
+/// The answer to a [`ListingCall`]
+enum ListingReply {
+    /// The project's instances
+    Instances(Result<Vec<Instance>>),
+
+    /// The project's reserved external addresses
+    ReservedAddresses(Result<BTreeSet<String>>),
+}
+
+impl ListingCall<'_> {
+    /// Runs the gcloud call
+    ///
+    /// # Arguments
+    /// * `query` - Filter and limit applied by gcloud
+    fn run(self, query: &ListingQuery) -> ListingReply {
+        match self {
+            ListingCall::Instances(project) => ListingReply::Instances(list_vms(query, project)),
+            ListingCall::ReservedAddresses(project) => {
+                ListingReply::ReservedAddresses(gcloud::reserved_addresses(project))
+            }
+        }
+    }
+}
+
+/// Lists VM instances in the active project, or in every project chosen with
+/// `--projects`/`--all-projects`
+///
+/// Expired gcloud credentials can be renewed on the spot, after which the
+/// listing is retried once (see [`with_reauth`]).
 ///
 /// # Arguments
-/// * `instances` - List of available VM instances
+/// * `query` - Filter and limit applied by gcloud to each project
+/// * `options` - Parsed command-line options
 ///
 /// # Returns
-/// * `Result<Instance>` - The selected VM instance or error
-fn select_vm(instances: &[Instance]) -> Result<Instance> {
-    // Map instances to display strings for selection menu
-    let vm_display: Vec<String> = instances
-        .iter()
-        .enumerate()
-        .map(|(idx, instance)| {
-            // Create a longer-lived value for the IP
-            let ip_option = instance.external_ip();
-            let ip_str = ip_option.as_deref();
+/// * `Result<Vec<Instance>>` - The merged instances or error
+fn list_instances(query: &ListingQuery, options: &cli::Options) -> Result<Vec<Instance>> {
+    with_reauth(options, || fetch_instances(query, options))
+}
+
+/// Lists VM instances for [`list_instances`]
+///
+/// Projects are listed concurrently; a project that fails is reported as a
+/// warning on stderr instead of aborting the whole list. When the query asks
+/// for static IPs, each project's reserved addresses are looked up as a call of
+/// their own, counted against `--max-concurrency` like the listings.
+///
+/// # Arguments
+/// * `query` - Filter and limit applied by gcloud to each project
+/// * `options` - Parsed command-line options
+///
+/// # Returns
+/// * `Result<Vec<Instance>>` - The merged instances or error
+fn fetch_instances(query: &ListingQuery, options: &cli::Options) -> Result<Vec<Instance>> {
+    let projects = if options.all_projects {
+        gcloud::projects()?
+    } else {
+        options.projects.clone()
+    };
+    let targets: Vec<Option<&str>> = if projects.is_empty() {
+        vec![None]
+    } else {
+        projects
+            .iter()
+            .map(|project| Some(project.as_str()))
+            .collect()
+    };
 
-            banner::vm_list_item(idx, &instance.name, &instance.zone(), ip_str)
+    let calls: Vec<ListingCall> = targets
+        .iter()
+        .flat_map(|&project| {
+            let reserved = query
+                .static_ips
+                .then_some(ListingCall::ReservedAddresses(project));
+            std::iter::once(ListingCall::Instances(project)).chain(reserved)
         })
         .collect();
+    let limit = options
+        .max_concurrency
+        .unwrap_or(parallel::DEFAULT_MAX_CONCURRENCY);
+    let mut replies = parallel::bounded_map(&calls, limit, |call| call.run(query)).into_iter();
 
-    // Create an interactive selection menu
-    println!(
-        "{}",
-        banner::info_message("Please select a VM to connect to:")
-    );
+    // Replies come back in call order: each project's instances, then its reserved addresses
+    let mut listings: Vec<(String, Result<Vec<Instance>>)> = Vec::new();
+    for project in &targets {
+        let mut listing = match replies.next().flatten() {
+            Some(ListingReply::Instances(listing)) => listing,
+            _ => Err(anyhow::anyhow!("listing thread panicked")),
+        };
+        if query.static_ips {
+            match replies.next().flatten() {
+                Some(ListingReply::ReservedAddresses(Ok(reserved))) => {
+                    if let Ok(instances) = &mut listing {
+                        for instance in instances {
+                            instance.mark_reserved(&reserved);
+                        }
+                    }
+                }
+                Some(ListingReply::ReservedAddresses(Err(e))) => {
+                    logging::verbose(&format!("Not marking static IPs: {:#}", e));
+                }
+                _ => {}
+            }
+        }
+        listings.push((project.unwrap_or_default().to_string(), listing));
+    }
+    if projects.is_empty() {
+        let (_, listing) = listings.remove(0);
+        return listing.map(|instances| prefer_static_ips(instances, options));
+    }
 
-    let selection = Select::with_theme(&ColorfulTheme::default())
-        .items(&vm_display)
-        .default(0)
-        .interact()
-        .context("Failed to display VM selection menu")?;
+    let (instances, failures) = merge_project_listings(listings);
+    for failure in &failures {
+        render::renderer().warning(failure);
+    }
+    if instances.is_empty() {
+        return Err(if failures.is_empty() {
+            AppError::NoVmsFound
+        } else {
+            AppError::VmListing(failures.join("; "))
+        }
+        .into());
+    }
+    Ok(prefer_static_ips(instances, options))
+}
+
+/// Runs a gcloud operation, offering to renew expired credentials and retry once
+///
+/// When gcloud's credentials have expired, the matching login command
+/// (`gcloud auth login` or `gcloud auth application-default login`) is run once
+/// the user confirms it, or straight away with `--yes`, also under
+/// `--read-only`. Without a terminal, or when the user declines, the failure
+/// becomes [`AppError::AuthExpired`].
+///
+/// # Arguments
+/// * `options` - Parsed command-line options
+/// * `operation` - The operation to run, and to retry after logging in
+///
+/// # Returns
+/// * `Result<T>` - The operation's result or error
+fn with_reauth<T>(options: &cli::Options, mut operation: impl FnMut() -> Result<T>) -> Result<T> {
+    let error = match operation() {
+        Ok(value) => return Ok(value),
+        Err(e) => e,
+    };
+    let message = format!("{:#}", error);
+    let Some(login) = gcloud::reauth_command(&message) else {
+        return Err(error);
+    };
+    // Renewing credentials changes nothing in the project, and without it not
+    // even a listing works, so it is allowed under --read-only
+    let gcloud = GcloudCommand::new().args(login.iter().copied());
+    let login_line = shell_command_line(&gcloud);
+
+    render::renderer().warning(&format!(
+        "gcloud's credentials have expired; `{}` renews them",
+        login_line
+    ));
+    let confirmed = io::stdin().is_terminal()
+        && (options.yes
+            || Confirm::with_theme(&ColorfulTheme::default())
+                .with_prompt(format!("Run `{}` now and retry?", login_line))
+                .default(true)
+                .interact()
+                .context("Failed to display confirmation prompt")?);
+    if !confirmed {
+        return Err(AppError::AuthExpired(message).into());
+    }
 
-    // Return a clone of the selected instance
-    Ok(instances[selection].clone())
+    let status = gcloud::attach(&mut gcloud.build()?)?;
+    if !status.success() {
+        return Err(AppError::AuthExpired(format!("{} exited with {}", login_line, status)).into());
+    }
+    render::renderer().success("Re-authenticated; retrying...");
+    operation()
 }
 
-/// Copies the local SSH key to the authorized_keys file on the selected VM
+/// Applies `--prefer-static-ip` to listed instances
 ///
 /// # Arguments
-/// * `instance` - The selected VM instance
+/// * `instances` - The listed instances
+/// * `options` - Parsed command-line options
 ///
 /// # Returns
-/// * `Result<()>` - Success or error information
-fn copy_ssh_key_to_vm(instance: &Instance) -> Result<()> {
-    println!(
-        "{}",
-        banner::info_message(&format!("Copying SSH key to VM: {}", instance.name.bold()))
-    );
+/// * `Vec<Instance>` - The instances, picking static addresses when asked to
+fn prefer_static_ips(mut instances: Vec<Instance>, options: &cli::Options) -> Vec<Instance> {
+    for instance in &mut instances {
+        instance.prefer_static_ip = options.prefer_static_ip;
+    }
+    instances
+}
 
-    // Get the path to the public key
-    let pub_key_path = dirs::home_dir()
-        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Could not find home directory"))?
-        .join(".ssh")
-        .join("id_rsa.pub");
+/// Merges per-project listings, collecting failures instead of stopping at the first
+///
+/// Projects without VMs are skipped quietly.
+///
+/// # Arguments
+/// * `listings` - Each project with the result of listing it
+///
+/// # Returns
+/// * `(Vec<Instance>, Vec<String>)` - The merged instances and one message per failed project
+fn merge_project_listings(
+    listings: Vec<(String, Result<Vec<Instance>>)>,
+) -> (Vec<Instance>, Vec<String>) {
+    let mut instances = Vec::new();
+    let mut failures = Vec::new();
+    for (project, listing) in listings {
+        match listing {
+            Ok(listed) => instances.extend(listed),
+            Err(e) if matches!(e.downcast_ref(), Some(AppError::NoVmsFound)) => {}
+            Err(e) => failures.push(format!(
+                "Skipping project {}: {}",
+                project,
+                e.to_string().lines().next().unwrap_or_default()
+            )),
+        }
+    }
+    (instances, failures)
+}
 
-    // Read public key content
-    let pub_key_content =
-        fs::read_to_string(&pub_key_path).context("Failed to read SSH public key")?;
+/// Finds the JSON array or object in command output that may carry warnings around it
+///
+/// The value must start a line; its end is the matching `]` or `}`, honoring
+/// brackets inside strings.
+///
+/// # Arguments
+/// * `raw` - The raw command output
+///
+/// # Returns
+/// * `Option<&str>` - The JSON text, or None if there is no complete value
+fn extract_json(raw: &str) -> Option<&str> {
+    let start = raw
+        .match_indices(['[', '{'])
+        .map(|(idx, _)| idx)
+        .find(|&idx| {
+            raw[..idx]
+                .rsplit('\n')
+                .next()
+                .is_some_and(|prefix| prefix.trim().is_empty())
+        })?;
 
-    // Prepare the command to be executed on the VM
-    // This command will:
-    // 1. Create ~/.ssh directory if it doesn't exist
-    // 2. Set proper permissions on ~/.ssh directory
-    // 3. Append the public key to authorized_keys
-    // 4. Set proper permissions on authorized_keys
-    let remote_cmd = format!(
-        "mkdir -p ~/.ssh && chmod 700 ~/.ssh && echo '{}' >> ~/.ssh/authorized_keys && chmod 600 ~/.ssh/authorized_keys",
-        pub_key_content.trim()
-    );
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (offset, c) in raw[start..].char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '[' | '{' if !in_string => depth += 1,
+            ']' | '}' if !in_string => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&raw[start..=start + offset]);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
 
-    // Execute gcloud command to run the remote command
-    let output = Command::new("gcloud")
-        .args([
-            "compute",
-            "ssh",
-            &instance.name,
-            "--zone",
-            &instance.zone(),
-            "--command",
-            &remote_cmd,
-        ])
-        .output()?;
+/// Reads instances from a listing, whatever shape gcloud gave it
+///
+/// Most gcloud versions print a bare array, but some print a single object or
+/// wrap the array as `{ "items": [...] }`, so the top-level value is probed first.
+///
+/// # Arguments
+/// * `json` - The JSON text
+///
+/// # Returns
+/// * `Result<Vec<Instance>, String>` - The instances, or why they couldn't be read
+fn parse_instances(json: &str) -> Result<Vec<Instance>, String> {
+    let value: serde_json::Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let instances = match value {
+        serde_json::Value::Object(mut object) if object.contains_key("items") => {
+            object.remove("items").unwrap_or_default()
+        }
+        serde_json::Value::Object(_) => serde_json::Value::Array(vec![value]),
+        value => value,
+    };
+    serde_json::from_value(instances).map_err(|e| e.to_string())
+}
 
-    if !output.status.success() {
-        let error_msg = String::from_utf8_lossy(&output.stderr);
-        return Err(AppError::KeyCopy(error_msg.to_string()).into());
+/// Describes how many instances a listing found
+///
+/// # Arguments
+/// * `query` - The query that was run
+/// * `count` - Number of instances found
+///
+/// # Returns
+/// * `String` - The message
+fn listing_message(query: &ListingQuery, count: usize) -> String {
+    match &query.filter {
+        Some(filter) => format!(
+            "Found {} VM instances matching {} (use --all to list everything).",
+            count, filter
+        ),
+        None => format!("Found {} VM instances.", count),
+    }
+}
+
+/// Expands `--zone` into the full zone name, when it was given
+///
+/// # Arguments
+/// * `options` - Parsed command-line options
+///
+/// # Returns
+/// * `Result<Option<String>>` - The full zone name, or an error if it matches no single zone
+fn zone_filter(options: &cli::Options) -> Result<Option<String>> {
+    options
+        .zone
+        .as_deref()
+        .map(|zone| zones::expand(zone, &zones::known_zones()?))
+        .transpose()
+}
+
+/// Keeps only the instances in the `--zone` zone and with a name containing
+/// the `--filter` text, when those were given
+///
+/// # Arguments
+/// * `instances` - The listed instances
+/// * `zone` - The expanded `--zone` (see [`zone_filter`])
+/// * `options` - Parsed command-line options
+///
+/// # Returns
+/// * `Result<Vec<Instance>>` - The remaining instances or error if none are left
+fn filter_instances(
+    mut instances: Vec<Instance>,
+    zone: Option<&str>,
+    options: &cli::Options,
+) -> Result<Vec<Instance>> {
+    if let Some(zone) = zone {
+        instances.retain(|instance| instance.zone() == zone);
+        if instances.is_empty() {
+            return Err(AppError::NoVmsInZone(zone.to_string()).into());
+        }
+    }
+    if let Some(filter) = &options.name_filter {
+        let needle = filter.to_lowercase();
+        instances.retain(|instance| instance.name.to_lowercase().contains(&needle));
+        if instances.is_empty() {
+            return Err(AppError::NoVmsMatchFilter(filter.clone()).into());
+        }
+    }
+    Ok(instances)
+}
+
+/// Redraws the VM list with statuses every few seconds until interrupted
+///
+/// Nothing is selected or changed: this is a dashboard for a fleet that is
+/// scaling. A resize redraws the last listing at once, and Ctrl-C ends the
+/// loop through the signal handler installed in `main`.
+///
+/// # Arguments
+/// * `options` - Parsed command-line options
+/// * `user_config` - Settings from the configuration file
+/// * `favorites` - Starred VMs, marked in the list
+///
+/// # Returns
+/// * `Result<()>` - Only returns on error, as the loop runs until interrupted
+fn watch_instances(
+    options: &cli::Options,
+    user_config: &UserConfig,
+    favorites: &Favorites,
+) -> Result<()> {
+    const WATCH_COLUMNS: [cli::Column; 4] = [
+        cli::Column::Name,
+        cli::Column::Zone,
+        cli::Column::Status,
+        cli::Column::Ip,
+    ];
+    let columns = options.columns.as_deref().unwrap_or(&WATCH_COLUMNS);
+    let query = ListingQuery::resolve(options, user_config)
+        .marking_static_ips(columns.contains(&cli::Column::Ip));
+    let interval = options.watch_every();
+    let resized = term_utils::resize_flag()?;
+    let scope = favorites_scope(options);
+    // Expanding --zone may ask gcloud for the zone list, so do it once
+    let zone = zone_filter(options)?;
+
+    loop {
+        let listing = list_instances(&query, options)
+            .and_then(|instances| filter_instances(instances, zone.as_deref(), options))
+            .map_err(|e| format!("{:#}", e));
+        let starred = listing
+            .as_ref()
+            .map(|instances| starred_names(favorites, instances, &scope))
+            .unwrap_or_default();
+        let updated = utc_timestamp(unix_now());
+
+        // Redraw on every refresh, and straight away when the terminal changes size
+        let mut waited = Duration::ZERO;
+        loop {
+            resized.store(false, Ordering::Relaxed);
+            draw_watch_frame(&listing, &starred, columns, &updated, interval);
+            while waited < interval && !resized.load(Ordering::Relaxed) {
+                let step = WATCH_POLL.min(interval - waited);
+                std::thread::sleep(step);
+                waited += step;
+            }
+            if waited >= interval {
+                break;
+            }
+        }
     }
+}
+
+/// How often `--watch` checks for a resize while waiting for the next refresh
+const WATCH_POLL: Duration = Duration::from_millis(200);
 
+/// Clears the screen and draws one `--watch` frame, cut to the terminal width
+///
+/// # Arguments
+/// * `listing` - The listed instances, or why listing failed
+/// * `starred` - Names of favorite VMs
+/// * `columns` - The fields to show for each VM
+/// * `updated` - When the listing was taken
+/// * `interval` - Time between refreshes
+fn draw_watch_frame(
+    listing: &std::result::Result<Vec<Instance>, String>,
+    starred: &BTreeSet<String>,
+    columns: &[cli::Column],
+    updated: &str,
+    interval: Duration,
+) {
+    let width = term_utils::get_terminal_size()
+        .map(|size| size.width)
+        .unwrap_or_else(term_utils::layout_width);
+    let fit = |line: String| term_utils::truncate_to_width(&line, width);
+
+    term_utils::clear_screen();
     println!(
         "{}",
-        banner::success_message(&format!(
-            "SSH key successfully copied to VM: {}",
-            instance.name.bold()
-        ))
+        fit(format!(
+            "Every {}s, updated {} (Ctrl-C to quit)",
+            interval.as_secs(),
+            updated
+        )
+        .bright_black()
+        .to_string())
     );
-    Ok(())
+    match listing {
+        Ok(instances) => {
+            println!("{}", fit(banner::info_message(&fleet_summary(instances))));
+            for (_, row) in menu_rows(instances, starred, columns) {
+                println!("{}", fit(row));
+            }
+        }
+        Err(e) => println!("{}", fit(banner::warning_message(e))),
+    }
 }
 
-/// Generates and prints the SSH command to connect to the VM
+/// Prints the filtered instance list as an Ansible inventory
+///
+/// Hosts come from the hosts file or gcloud, like the other data modes. The
+/// inventory goes to stdout; progress messages go to stderr so the output can
+/// be redirected straight into a file.
 ///
 /// # Arguments
-/// * `instance` - The selected VM instance
+/// * `options` - Parsed command-line options
+/// * `user_config` - Settings from the configuration file
+/// * `favorites` - Starred VMs, listed first
+/// * `format` - INI or YAML
 ///
 /// # Returns
 /// * `Result<()>` - Success or error information
-fn print_ssh_command(instance: &Instance) -> Result<()> {
-    // Get the external IP of the VM
-    let external_ip = instance.external_ip().ok_or(AppError::NoExternalIp)?;
+fn print_inventory(
+    options: &cli::Options,
+    user_config: &UserConfig,
+    favorites: &Favorites,
+    format: InventoryFormat,
+) -> Result<()> {
+    let (instances, _) = data_mode_instances(options, user_config, favorites, false)?;
 
-    // Get the local username
-    let username = whoami::username();
+    let mut hosts = Vec::new();
+    for instance in instances {
+        let instance = match instance.with_nic(options.nic) {
+            Ok(instance) => instance.with_login_user(
+                options.user.as_deref(),
+                &user_config.listing.user_label,
+                &user_config.os_users,
+            ),
+            Err(e) => {
+                render::renderer().warning(&format!("Skipping: {}", e));
+                continue;
+            }
+        };
+        match instance.address(options.ipv6) {
+            Some(address) => hosts.push(InventoryHost {
+                ansible_user: remote_username(&instance),
+                ansible_host: address,
+                name: instance.summary().name,
+            }),
+            None => render::renderer().warning(&format!(
+                "Skipping {}: no external IP address",
+                instance.name
+            )),
+        }
+    }
 
-    // Construct the SSH command
-    let ssh_cmd = format!("ssh {}@{}", username, external_ip);
+    print!("{}", inventory::render(&hosts, format));
+    Ok(())
+}
+
+/// Prints the selected VM's connection details as shell exports
+///
+/// Only the `export` lines go to stdout, so the output can be passed to `eval`;
+/// the menu and messages go to stderr. No key is copied.
+///
+/// # Arguments
+/// * `options` - Parsed command-line options
+/// * `user_config` - Settings from the configuration file
+/// * `favorites` - Starred VMs, listed first
+///
+/// # Returns
+/// * `Result<()>` - Success or error information
+fn print_connection_env(
+    options: &cli::Options,
+    user_config: &UserConfig,
+    favorites: &Favorites,
+) -> Result<()> {
+    let (instances, starred) = data_mode_instances(options, user_config, favorites, false)?;
+    let instance = choose_instance(&instances, &starred, options)
+        .context("Failed to select VM")?
+        .with_login_user(
+            options.user.as_deref(),
+            &user_config.listing.user_label,
+            &user_config.os_users,
+        )
+        .with_nic(options.nic)?;
 
-    // Display connection information
-    println!("{} {}", config::emojis::VM, "VM Name:".yellow());
-    println!("   {}", instance.name.bright_cyan().bold());
+    let route = connection_route(&instance, options)?;
+    let address = instance.address(options.ipv6).unwrap_or(route.address);
+    print!(
+        "{}",
+        connection_env(&instance, &address, &route.command.join(" "))
+    );
+    Ok(())
+}
+
+/// Prints the listed VMs as a JSON array of their summaries
+///
+/// Only the JSON goes to stdout; messages go to stderr.
+///
+/// # Arguments
+/// * `options` - Parsed command-line options
+/// * `user_config` - Settings from the configuration file
+/// * `favorites` - Starred VMs, listed first
+///
+/// # Returns
+/// * `Result<()>` - Success or error information
+fn print_json_listing(
+    options: &cli::Options,
+    user_config: &UserConfig,
+    favorites: &Favorites,
+) -> Result<()> {
+    let (instances, _) = data_mode_instances(options, user_config, favorites, true)?;
+    let summaries: Vec<InstanceSummary> = instances
+        .iter()
+        .map(|instance| instance.clone().with_nic(options.nic).map(|i| i.summary()))
+        .collect::<Result<_>>()?;
+    render::renderer().vm_list(&summaries);
+    Ok(())
+}
 
-    println!("{} {}", config::emojis::ZONE, "Zone:".yellow());
-    println!("   {}", instance.zone().bright_cyan());
+/// Loads the instances for the data-only output modes (`--json`, env exports,
+/// inventories)
+///
+/// Instances come from the hosts file or gcloud, narrowed by zone and
+/// favorites; progress messages go to stderr so stdout stays pure data.
+///
+/// # Arguments
+/// * `options` - Parsed command-line options
+/// * `user_config` - Settings from the configuration file
+/// * `favorites` - Starred VMs, listed first
+/// * `static_marker` - Whether the output tells static external IPs from ephemeral ones
+///
+/// # Returns
+/// * `Result<(Vec<Instance>, BTreeSet<String>)>` - The instances and the starred names
+fn data_mode_instances(
+    options: &cli::Options,
+    user_config: &UserConfig,
+    favorites: &Favorites,
+    static_marker: bool,
+) -> Result<(Vec<Instance>, BTreeSet<String>)> {
+    let (instances, starred) = match &options.hosts_file {
+        Some(path) => {
+            let (instances, message) = load_static_hosts(path)?;
+            render::renderer().success(&message);
+            (instances, favorites.names(HOSTS_FILE_SCOPE))
+        }
+        None => {
+            let query =
+                ListingQuery::resolve(options, user_config).marking_static_ips(static_marker);
+            let instances =
+                list_instances(&query, options).context("Failed to list VM instances")?;
+            let instances = filter_instances(instances, zone_filter(options)?.as_deref(), options)?;
+            let starred = starred_names(favorites, &instances, &favorites_scope(options));
+            (instances, starred)
+        }
+    };
+    let instances = apply_favorites(instances, &starred, options.favorites_only)?;
+    Ok((instances, starred))
+}
 
-    println!("{} {}", config::emojis::IP_ADDRESS, "External IP:".yellow());
-    println!("   {}", external_ip.bright_cyan());
+/// Renders connection details as `export NAME='value'` lines
+///
+/// # Arguments
+/// * `instance` - The selected VM instance
+/// * `address` - The address to connect to
+/// * `ssh_cmd` - The full SSH command line
+///
+/// # Returns
+/// * `String` - One export per line, each value single-quoted for the shell
+fn connection_env(instance: &Instance, address: &str, ssh_cmd: &str) -> String {
+    let summary = instance.summary();
+    let mut vars = vec![("VM_NAME", summary.name)];
+    if let Some(zone) = summary.zone {
+        vars.push(("VM_ZONE", zone));
+    }
+    vars.push(("VM_IP", address.to_string()));
+    vars.push(("VM_USER", remote_username(instance)));
+    vars.push(("SSH_CMD", ssh_cmd.to_string()));
 
-    println!("\n{}", "To connect to your VM, run:".green().bold());
+    vars.iter()
+        .map(|(name, value)| format!("export {}={}\n", name, shell_quote(value)))
+        .collect()
+}
 
-    // Display SSH command in a box
-    println!("{}", banner::ssh_command_box(&ssh_cmd));
+/// Prints a `gcloud-ssh` command that selects the same VM and action without menus
+///
+/// # Arguments
+/// * `instance` - The selected VM instance
+/// * `action` - The action that was run
+/// * `options` - Parsed command-line options
+fn print_reconnect(instance: &Instance, action: &cli::Action, options: &cli::Options) {
+    let command: Vec<String> = reconnect_args(instance, action, options)
+        .iter()
+        .map(|arg| shell_word(arg))
+        .collect();
+    println!("\n{}", "To do this again, run:".green().bold());
+    println!("   {}", command.join(" ").bright_cyan());
+}
 
-    Ok(())
+/// Builds the arguments that reproduce a selection non-interactively
+///
+/// Every resolved value that changes what is selected or how the connection is
+/// made is spelled out, so the command doesn't depend on the current directory's
+/// `.gcloud-ssh.toml` or on a profile staying the same.
+///
+/// # Arguments
+/// * `instance` - The selected VM instance
+/// * `action` - The action that was run
+/// * `options` - Parsed command-line options
+///
+/// # Returns
+/// * `Vec<String>` - The program name followed by its arguments, unquoted
+fn reconnect_args(
+    instance: &Instance,
+    action: &cli::Action,
+    options: &cli::Options,
+) -> Vec<String> {
+    let mut args = vec!["gcloud-ssh".to_string()];
+    let mut push = |flag: &str, value: Option<String>| {
+        if let Some(value) = value {
+            args.extend([flag.to_string(), value]);
+        }
+    };
+
+    if let Some(hosts_file) = &options.hosts_file {
+        push("--hosts-file", Some(hosts_file.display().to_string()));
+    }
+    push("--vm", Some(instance.name.clone()));
+    if !instance.is_static() {
+        push("--zone", Some(instance.zone()));
+        // Only a VM from a merged listing is found again through --projects
+        match &instance.project {
+            Some(project) => push("--projects", Some(project.clone())),
+            None => push("--project", options.project.clone()),
+        }
+        push(
+            "--gcloud-bin",
+            options
+                .gcloud_bin
+                .as_ref()
+                .map(|bin| bin.display().to_string()),
+        );
+        push("--configuration", options.configuration.clone());
+        push("--impersonate", options.impersonate.clone());
+    }
+    push("--user", options.user.clone());
+    push(
+        "--ssh-dir",
+        options
+            .ssh_dir
+            .as_ref()
+            .map(|dir| dir.display().to_string()),
+    );
+    push("--identity-glob", options.identity_glob.clone());
+    push("--nic", options.nic.map(|nic| nic.to_string()));
+    push("--bind", options.bind.map(|bind| bind.to_string()));
+    if options.key_type != cli::KeyType::Rsa {
+        push("--key-type", Some(options.key_type.name().to_string()));
+    }
+    if options.copy_method != cli::CopyMethod::Auto {
+        push(
+            "--copy-method",
+            Some(options.copy_method.name().to_string()),
+        );
+    }
+    push(
+        "--no-external-ip-fallback",
+        options
+            .no_external_ip_fallback
+            .filter(|fallback| *fallback != cli::NoExternalIpFallback::Error)
+            .map(|fallback| fallback.name().to_string()),
+    );
+
+    let switches = [
+        (options.ipv6, "--ipv6"),
+        (options.internal_dns, "--internal-dns"),
+        (options.prefer_static_ip, "--prefer-static-ip"),
+        (options.use_ssh_config, "--use-ssh-config"),
+        (options.windows, "--windows"),
+        (options.no_key_gen, "--no-key-gen"),
+        (options.read_only, "--read-only"),
+    ];
+    args.extend(
+        switches
+            .into_iter()
+            .filter(|(on, _)| *on)
+            .map(|(_, flag)| flag.to_string()),
+    );
+    args.extend(action.flag_args());
+    args
+}
+
+/// Quotes a value for POSIX shells only when it contains characters the shell treats specially
+///
+/// # Arguments
+/// * `value` - The value to quote
+///
+/// # Returns
+/// * `String` - The value as-is, or single-quoted
+fn shell_word(value: &str) -> String {
+    let plain = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "@%+=:,./_-".contains(c));
+    if plain {
+        value.to_string()
+    } else {
+        shell_quote(value)
+    }
+}
+
+/// Single-quotes a value for POSIX shells, escaping embedded single quotes
+///
+/// # Arguments
+/// * `value` - The value to quote
+///
+/// # Returns
+/// * `String` - The quoted value, safe to pass through `eval`
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Summarizes instances by status and zone, e.g. "12 running, 3 stopped across 4 zones"
+///
+/// Hosts-file entries have no zone or status and are counted on their own,
+/// e.g. "5 from the hosts file".
+///
+/// # Arguments
+/// * `instances` - The listed instances
+///
+/// # Returns
+/// * `String` - The summary line
+fn fleet_summary(instances: &[Instance]) -> String {
+    let summaries: Vec<InstanceSummary> = instances
+        .iter()
+        .filter(|instance| !instance.is_static())
+        .map(Instance::summary)
+        .collect();
+    let mut by_status: BTreeMap<String, usize> = BTreeMap::new();
+    for summary in &summaries {
+        let status = match summary.status.as_str() {
+            "TERMINATED" | "STOPPED" => "stopped".to_string(),
+            "" => "unknown".to_string(),
+            other => other.to_lowercase(),
+        };
+        *by_status.entry(status).or_default() += 1;
+    }
+
+    // Running and stopped come first, the rarer states follow alphabetically
+    let mut parts = Vec::new();
+    for status in ["running", "stopped"] {
+        if let Some(count) = by_status.remove(status) {
+            parts.push(format!("{} {}", count, status));
+        }
+    }
+    parts.extend(
+        by_status
+            .iter()
+            .map(|(status, count)| format!("{} {}", count, status)),
+    );
+
+    let zones: BTreeSet<&str> = summaries
+        .iter()
+        .filter_map(|summary| summary.zone.as_deref())
+        .collect();
+    let mut summary = Vec::new();
+    if !summaries.is_empty() {
+        summary.push(format!(
+            "{} across {} zone{}",
+            parts.join(", "),
+            zones.len(),
+            if zones.len() == 1 { "" } else { "s" }
+        ));
+    }
+    let hosts = instances.len() - summaries.len();
+    if hosts > 0 {
+        summary.push(format!("{} from the hosts file", hosts));
+    }
+    summary.join("; ")
+}
+
+/// Loads instances from a static hosts file
+///
+/// # Arguments
+/// * `path` - Path to the CSV or JSON hosts file
+///
+/// # Returns
+/// * `Result<(Vec<Instance>, String)>` - The hosts as selectable instances and a
+///   message reporting how many were loaded, or error
+fn load_static_hosts(path: &Path) -> Result<(Vec<Instance>, String)> {
+    let instances: Vec<Instance> = hosts::load_hosts_file(path)?
+        .into_iter()
+        .map(Instance::from_host_entry)
+        .collect();
+
+    let message = format!("Loaded {} hosts from {}.", instances.len(), path.display());
+    Ok((instances, message))
+}
+
+/// Determines which named gcloud configuration to use
+///
+/// An explicit `--configuration` is validated against the available ones. Without
+/// it, and when several configurations exist, the user may pick one interactively.
+///
+/// # Arguments
+/// * `options` - Parsed command-line options
+/// * `interactive` - Whether prompting for a configuration is allowed
+///
+/// # Returns
+/// * `Result<Option<String>>` - The configuration to pass to gcloud, if any
+fn resolve_configuration(options: &cli::Options, interactive: bool) -> Result<Option<String>> {
+    if let Some(name) = &options.configuration {
+        let available = gcloud::configurations()?;
+        if !available.iter().any(|config| &config.name == name) {
+            let names: Vec<&str> = available
+                .iter()
+                .map(|config| config.name.as_str())
+                .collect();
+            return Err(AppError::UnknownConfiguration(name.clone(), names.join(", ")).into());
+        }
+        return Ok(Some(name.clone()));
+    }
+
+    if !interactive || !io::stdin().is_terminal() {
+        return Ok(None);
+    }
+
+    // Without gcloud configurations to choose from, keep the active one
+    let available = gcloud::configurations().unwrap_or_default();
+    if available.len() < 2 {
+        return Ok(None);
+    }
+
+    let active = available
+        .iter()
+        .position(|config| config.is_active)
+        .unwrap_or(0);
+    let names: Vec<&str> = available
+        .iter()
+        .map(|config| config.name.as_str())
+        .collect();
+
+    render::renderer().info("Select the gcloud configuration to use:");
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .items(&names)
+        .default(active)
+        .interact()
+        .context("Failed to display configuration menu")?;
+
+    Ok((selection != active).then(|| names[selection].to_string()))
+}
+
+/// Returns the active gcloud project, if one is configured
+fn active_project() -> Option<String> {
+    let output =
+        gcloud::capture(gcloud::command().args(["config", "get-value", "project"])).ok()?;
+
+    let project = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !project.is_empty()).then_some(project)
+}
+
+/// Returns the key favorites are stored under for the current inventory
+///
+/// This is `--project` when given, so gcloud is only asked for the active
+/// project when there is none; call it once per run.
+///
+/// # Arguments
+/// * `options` - Parsed command-line options
+fn favorites_scope(options: &cli::Options) -> String {
+    if options.hosts_file.is_some() {
+        HOSTS_FILE_SCOPE.to_string()
+    } else {
+        options
+            .project
+            .clone()
+            .or_else(active_project)
+            .unwrap_or_else(|| "default".to_string())
+    }
+}
+
+/// Returns the names of the listed VMs that are starred in their own project
+///
+/// VMs from a merged `--projects` listing are looked up under their project,
+/// the others under `scope`.
+///
+/// # Arguments
+/// * `favorites` - The stored favorites
+/// * `instances` - The listed instances
+/// * `scope` - The key for VMs without a project of their own (see [`favorites_scope`])
+///
+/// # Returns
+/// * `BTreeSet<String>` - Names of the starred VMs
+fn starred_names(favorites: &Favorites, instances: &[Instance], scope: &str) -> BTreeSet<String> {
+    instances
+        .iter()
+        .filter(|instance| {
+            favorites.contains(instance.project.as_deref().unwrap_or(scope), &instance.name)
+        })
+        .map(|instance| instance.name.clone())
+        .collect()
+}
+
+/// Stars or unstars a VM and reports the result
+///
+/// # Arguments
+/// * `options` - Parsed command-line options
+/// * `name` - The VM name to toggle
+///
+/// # Returns
+/// * `Result<()>` - Success or error information
+fn toggle_favorite(options: &cli::Options, name: &str) -> Result<()> {
+    let scope = favorites_scope(options);
+    let mut favorites = Favorites::load()?;
+    let added = favorites.toggle(&scope, name);
+    favorites.save().context("Failed to save favorites")?;
+
+    let message = if added {
+        format!("Added {} to favorites for {}.", name, scope)
+    } else {
+        format!("Removed {} from favorites for {}.", name, scope)
+    };
+    render::renderer().success(&message);
+    Ok(())
+}
+
+/// Moves favorite VMs to the top of the list, optionally dropping all others
+///
+/// # Arguments
+/// * `instances` - The listed instances
+/// * `starred` - Names of favorite VMs
+/// * `favorites_only` - Whether to keep only favorites
+///
+/// # Returns
+/// * `Result<Vec<Instance>>` - The reordered instances or error if none remain
+fn apply_favorites(
+    instances: Vec<Instance>,
+    starred: &BTreeSet<String>,
+    favorites_only: bool,
+) -> Result<Vec<Instance>> {
+    let (mut ordered, others): (Vec<Instance>, Vec<Instance>) = instances
+        .into_iter()
+        .partition(|instance| starred.contains(&instance.name));
+
+    if favorites_only {
+        if ordered.is_empty() {
+            return Err(AppError::NoFavorites.into());
+        }
+    } else {
+        ordered.extend(others);
+    }
+    Ok(ordered)
+}
+
+/// Picks the instance named with `--vm`, or lets the user choose from the menu
+///
+/// # Arguments
+/// * `instances` - List of available VM instances
+/// * `starred` - Names of favorite VMs, marked in the menu
+/// * `options` - Parsed command-line options
+///
+/// # Returns
+/// * `Result<Instance>` - The chosen instance or error
+fn choose_instance(
+    instances: &[Instance],
+    starred: &BTreeSet<String>,
+    options: &cli::Options,
+) -> Result<Instance> {
+    let Some(pattern) = &options.vm else {
+        return select_vm(instances, starred, options);
+    };
+
+    let matches = matching_instances(instances, pattern);
+    match matches.as_slice() {
+        [] => Err(AppError::VmNotFound(pattern.clone()).into()),
+        [instance] => Ok((*instance).clone()),
+        _ if !io::stdin().is_terminal() => {
+            Err(AppError::AmbiguousVm(pattern.clone(), describe_matches(&matches)).into())
+        }
+        [first, ..] if matches.iter().all(|i| i.name == first.name) => pick_zone(&matches),
+        _ => {
+            let matches: Vec<Instance> = matches.into_iter().cloned().collect();
+            select_vm(&matches, starred, options)
+        }
+    }
+}
+
+/// Lists matched instances as `name (zone)` pairs, for telling same-named VMs apart
+///
+/// # Arguments
+/// * `matches` - The instances a `--vm` pattern matched
+///
+/// # Returns
+/// * `String` - The comma-separated pairs
+fn describe_matches(matches: &[&Instance]) -> String {
+    matches
+        .iter()
+        .map(|instance| format!("{} ({})", instance.name, instance.zone()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Asks which zone is meant when one VM name exists in several zones
+///
+/// # Arguments
+/// * `matches` - The same-named instances
+///
+/// # Returns
+/// * `Result<Instance>` - The chosen instance, or an error if the menu was dismissed
+fn pick_zone(matches: &[&Instance]) -> Result<Instance> {
+    let labels: Vec<String> = matches
+        .iter()
+        .map(|instance| format!("{} ({})", instance.name, instance.zone()))
+        .collect();
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!(
+            "{} exists in several zones; pick one (or pass --zone)",
+            matches[0].name
+        ))
+        .items(&labels)
+        .default(0)
+        .interact_opt()
+        .map_err(|e| AppError::VmSelection(e.to_string()))?
+        .ok_or(AppError::SelectionCancelled)?;
+    Ok(matches[selection].clone())
+}
+
+/// Finds the instances a `--vm` pattern refers to
+///
+/// An exact name wins; otherwise every name containing the pattern, ignoring
+/// case, matches.
+///
+/// # Arguments
+/// * `instances` - List of available VM instances
+/// * `pattern` - The name or part of a name given with `--vm`
+///
+/// # Returns
+/// * `Vec<&Instance>` - The matching instances, in list order
+fn matching_instances<'a>(instances: &'a [Instance], pattern: &str) -> Vec<&'a Instance> {
+    let exact: Vec<&Instance> = instances.iter().filter(|i| i.name == pattern).collect();
+    if !exact.is_empty() {
+        return exact;
+    }
+
+    let pattern = pattern.to_lowercase();
+    instances
+        .iter()
+        .filter(|instance| instance.name.to_lowercase().contains(&pattern))
+        .collect()
+}
+
+/// Allows the user to select a VM from the list
+///
+/// Cancelling the menu (Esc or q) asks whether to re-open it or quit, so a stray
+/// keypress doesn't throw away the fetched list. With `--yes` or without a
+/// terminal, cancelling is an error straight away.
+///
+/// # Arguments
+/// * `instances` - List of available VM instances
+/// * `starred` - Names of favorite VMs, marked in the menu
+/// * `options` - Parsed command-line options
+///
+/// # Returns
+/// * `Result<Instance>` - The selected VM instance or error
+fn select_vm(
+    instances: &[Instance],
+    starred: &BTreeSet<String>,
+    options: &cli::Options,
+) -> Result<Instance> {
+    // Rows pair each label with its instance so the selection maps to what was shown
+    let rows = menu_rows(instances, starred, options.list_columns());
+    let labels: Vec<&str> = rows.iter().map(|(_, label)| label.as_str()).collect();
+
+    // Create an interactive selection menu
+    // The menu itself is drawn on stderr, so keep its heading there too
+    eprintln!(
+        "{}",
+        banner::info_message("Please select a VM to connect to:")
+    );
+
+    loop {
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .items(&labels)
+            .default(0)
+            .interact_opt()
+            .map_err(|e| AppError::VmSelection(e.to_string()))?;
+
+        if let Some(selection) = selection {
+            // Return a clone of the selected instance
+            return Ok(rows[selection].0.clone());
+        }
+
+        if options.yes || !io::stdin().is_terminal() {
+            return Err(AppError::SelectionCancelled.into());
+        }
+
+        // Only an explicit "Quit" exits; cancelling this prompt re-opens the list
+        let choice = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Selection cancelled")
+            .items(&["Back to the VM list", "Quit"])
+            .default(0)
+            .interact_opt()
+            .map_err(|e| AppError::VmSelection(e.to_string()))?;
+        if choice == Some(1) {
+            return Err(AppError::SelectionCancelled.into());
+        }
+    }
+}
+
+/// Builds the VM selection menu rows, each carrying the instance it displays
+///
+/// # Arguments
+/// * `instances` - The instances, in display order
+/// * `starred` - Names of favorite VMs, marked in the menu
+/// * `columns` - The fields to show for each VM, in order
+///
+/// # Returns
+/// * `Vec<(&Instance, String)>` - Each instance with its menu label
+fn menu_rows<'a>(
+    instances: &'a [Instance],
+    starred: &BTreeSet<String>,
+    columns: &[cli::Column],
+) -> Vec<(&'a Instance, String)> {
+    instances
+        .iter()
+        .enumerate()
+        .map(|(idx, instance)| {
+            let summary = instance.summary();
+            // Hosts-file entries have no zone; merged listings show each VM's project
+            let zone = summary.zone.as_deref().unwrap_or_default();
+            let location = match &instance.project {
+                _ if instance.is_static() => "hosts file".to_string(),
+                Some(project) => format!("{}/{}", project, zone),
+                None => zone.to_string(),
+            };
+            let labels = summary
+                .labels
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value))
+                .collect::<Vec<_>>()
+                .join(",");
+            let fields: Vec<ListField> = columns
+                .iter()
+                .map(|column| match column {
+                    cli::Column::Name => ListField::Name(&summary.name),
+                    cli::Column::Zone => ListField::Zone(&location),
+                    cli::Column::Status => ListField::Status(&summary.status),
+                    cli::Column::Ip => ListField::Address {
+                        ip: summary.external_ip.as_deref(),
+                        ipv6: summary.external_ipv6.as_deref(),
+                        nics: instance.network_interfaces.len(),
+                        pinned: summary.external_ip_kind == Some(IpKind::Static),
+                    },
+                    cli::Column::Machine => ListField::Machine(&summary.machine_type),
+                    cli::Column::Labels => ListField::Labels(labels.clone()),
+                })
+                .collect();
+            let mut label = banner::vm_list_fields(idx, &fields);
+            if starred.contains(&instance.name) {
+                label = format!("{} {}", label, icons::icon(Icon::Favorite));
+            }
+            if instance.access_denied {
+                label = format!("{} {}", label, icons::icon(Icon::Locked));
+            }
+            (instance, label)
+        })
+        .collect()
+}
+
+/// Checks whether the user may SSH into each instance and marks the ones they can't
+///
+/// `gcloud compute ssh --dry-run` resolves the instance, its project and OS Login
+/// settings without connecting, so permission problems show up cheaply. It can
+/// still create gcloud's own key pair, so it is refused under `--read-only`.
+///
+/// # Arguments
+/// * `instances` - The instances to check; inaccessible ones get `access_denied`
+/// * `options` - Parsed command-line options
+/// * `user_label` - Name of the label holding the login user
+/// * `os_users` - Image families mapped to their login users
+///
+/// # Returns
+/// * `Result<()>` - Success, or [`AppError::ReadOnly`] in read-only mode
+fn check_access(
+    instances: &mut [Instance],
+    options: &cli::Options,
+    user_label: &str,
+    os_users: &OsUsers,
+) -> Result<()> {
+    render::renderer().info(&format!(
+        "Checking SSH access to {} VM(s)...",
+        instances.len()
+    ));
+
+    for instance in instances.iter_mut() {
+        let Ok(zone) = instance.gcloud_zone() else {
+            continue;
+        };
+        let target = instance
+            .clone()
+            .with_login_user(options.user.as_deref(), user_label, os_users)
+            .ssh_target();
+
+        let mut gcloud = GcloudCommand::new()
+            .args(["compute", "ssh", &target])
+            .zone(&zone)
+            .args(["--dry-run", "--quiet"])
+            .mutating();
+        if let Some(project) = &instance.project {
+            gcloud = gcloud.project(project);
+        }
+        let mut command = gcloud.build()?;
+        instance.access_denied = match gcloud::capture(&mut command) {
+            Ok(output) => {
+                if !output.status.success() {
+                    logging::verbose(&format!(
+                        "Access check for {} failed: {}",
+                        instance.name,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    ));
+                }
+                !output.status.success()
+            }
+            // Without gcloud nothing can be concluded, so leave the VM unmarked
+            Err(_) => false,
+        };
+    }
+
+    let denied = instances.iter().filter(|i| i.access_denied).count();
+    if denied > 0 {
+        render::renderer().warning(&format!(
+            "{} VM(s) failed the access check and are marked {}",
+            denied,
+            icons::icon(Icon::Locked)
+        ));
+    }
+    Ok(())
+}
+
+/// Copies the local SSH key to the authorized_keys file on the selected VM
+///
+/// # Arguments
+/// * `instance` - The selected VM instance
+/// * `options` - Parsed command-line options
+/// * `user_config` - Settings from the configuration file
+/// * `ssh_dir` - Directory holding the key pair
+///
+/// # Returns
+/// * `Result<()>` - Success or error information
+fn copy_ssh_key_to_vm(
+    instance: &Instance,
+    options: &cli::Options,
+    user_config: &UserConfig,
+    ssh_dir: &Path,
+) -> Result<()> {
+    render::renderer().info(&format!("Copying SSH key to VM: {}", instance.name.bold()));
+
+    // Get the path to the public key
+    let pub_key_path = match &options.identity_glob {
+        Some(pattern) => choose_identity(pattern)?,
+        None => ssh_dir.join(format!("{}.pub", options.key_type.file_name())),
+    };
+
+    // Read public key content
+    let pub_key_content =
+        fs::read_to_string(&pub_key_path).context("Failed to read SSH public key")?;
+
+    // Label keys without a comment so they can be told apart on the VM later
+    let comment = expand_key_comment(&user_config.ssh.pushed_key_comment);
+    let pub_key = &with_key_comment(pub_key_content.trim(), &comment);
+
+    // Show exactly what will change on the VM and let the user back out
+    let (destination, line) = match options.copy_method {
+        cli::CopyMethod::Metadata => {
+            let expire_on = options
+                .expire_after
+                .map(|after| utc_timestamp(unix_now() + after.as_secs()));
+            (
+                match &expire_on {
+                    Some(expire_on) => {
+                        format!("Instance metadata: ssh-keys (expires {})", expire_on)
+                    }
+                    None => "Instance metadata: ssh-keys".to_string(),
+                },
+                metadata_key_entry(&remote_username(instance), pub_key, expire_on.as_deref()),
+            )
+        }
+        _ => (
+            "File: ~/.ssh/authorized_keys".to_string(),
+            pub_key.to_string(),
+        ),
+    };
+    terminal_fx::framed_message(
+        &format!(
+            "Target: {} ({})\n{}\nWill append line:\n+ {}",
+            instance.name,
+            instance.zone(),
+            destination,
+            line
+        ),
+        term_utils::layout_width().min(config::layout::DEFAULT_FRAME_WIDTH),
+    );
+
+    if !options.yes && io::stdin().is_terminal() {
+        let confirmed = Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!("Append this key on {}?", instance.name))
+            .default(true)
+            .interact()
+            .context("Failed to display confirmation prompt")?;
+        if !confirmed {
+            render::renderer().info("Key deployment skipped.");
+            return Ok(());
+        }
+    }
+
+    // Display progress animation
+    if settings::current().animations {
+        terminal_fx::progress_bar(
+            "Copying SSH key to VM...",
+            config::animations::PROGRESS_BAR_STEPS,
+            config::animations::PROGRESS_BAR_DURATION_MS,
+        );
+    }
+
+    let outcome = with_reauth(options, || match options.copy_method {
+        cli::CopyMethod::Echo => copy_key_via_echo(instance, pub_key),
+        cli::CopyMethod::Scp => copy_key_via_scp(instance, pub_key),
+        cli::CopyMethod::Metadata => copy_key_via_metadata(instance, &line),
+        cli::CopyMethod::Auto => match copy_key_via_scp(instance, pub_key) {
+            Ok(outcome) => Ok(outcome),
+            Err(e) => {
+                render::renderer().info(&format!(
+                    "scp copy failed ({}), falling back to remote echo...",
+                    e.to_string().trim()
+                ));
+                copy_key_via_echo(instance, pub_key)
+            }
+        },
+    })?;
+
+    match outcome {
+        KeyCopyOutcome::Added => render::renderer().success(&format!(
+            "SSH key successfully copied to VM: {}",
+            instance.name.bold()
+        )),
+        KeyCopyOutcome::AlreadyPresent => render::renderer().info(&format!(
+            "SSH key already authorized on VM: {}",
+            instance.name.bold()
+        )),
+    }
+
+    if options.verify {
+        verify_key(instance, options, pub_key)?;
+    }
+    Ok(())
+}
+
+/// Checks that the copied key is now authorized on the VM
+///
+/// With the metadata method the instance's `ssh-keys` value is read back;
+/// otherwise authorized_keys is searched over SSH.
+///
+/// # Arguments
+/// * `instance` - The selected VM instance
+/// * `options` - Parsed command-line options
+/// * `pub_key` - The public key line that was copied
+///
+/// # Returns
+/// * `Result<()>` - Success, or `KeyNotVerified` when the key was not found
+fn verify_key(instance: &Instance, options: &cli::Options, pub_key: &str) -> Result<()> {
+    render::renderer().info(&format!(
+        "Verifying the key on VM: {}",
+        instance.name.bold()
+    ));
+
+    let body = key_body(pub_key);
+    let (found, place) = match options.copy_method {
+        cli::CopyMethod::Metadata => (
+            read_metadata_ssh_keys(instance)?
+                .lines()
+                .any(|line| line.contains(&body)),
+            "the instance's ssh-keys metadata",
+        ),
+        _ => {
+            let remote_cmd = format!(
+                "if grep -qF -- {} ~/.ssh/authorized_keys; then echo VERIFIED; else echo MISSING; fi",
+                shell_quote(&body)
+            );
+            let output = run_remote_command(instance, &remote_cmd)?;
+            (
+                output.lines().any(|line| line.trim() == "VERIFIED"),
+                "~/.ssh/authorized_keys",
+            )
+        }
+    };
+
+    if !found {
+        render::renderer().error(&format!("Verification failed: the key is not in {}", place));
+        return Err(AppError::KeyNotVerified(instance.name.clone()).into());
+    }
+
+    render::renderer().success(&format!("Verified: the key is in {}", place));
+    Ok(())
+}
+
+/// Returns the type and base64 fields of a public key line, without its comment
+///
+/// # Arguments
+/// * `pub_key` - The public key line (`TYPE BASE64 [COMMENT]`)
+fn key_body(pub_key: &str) -> String {
+    pub_key
+        .split_whitespace()
+        .take(2)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// What happened when the key was copied to the VM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyCopyOutcome {
+    /// The key was appended
+    Added,
+    /// The key was already authorized, so nothing changed
+    AlreadyPresent,
+}
+
+impl KeyCopyOutcome {
+    /// Reads the `ADDED`/`PRESENT` marker echoed by the remote append command
+    ///
+    /// Login banners may surround the marker, so any line may carry it. Output
+    /// without a marker is treated as an append, matching older behavior.
+    fn from_remote_output(output: &str) -> Self {
+        if output.lines().any(|line| line.trim() == "PRESENT") {
+            KeyCopyOutcome::AlreadyPresent
+        } else {
+            KeyCopyOutcome::Added
+        }
+    }
+}
+
+/// Expands `--identity-glob` into candidate public keys and picks one
+///
+/// Matches may name either half of a key pair; private keys are mapped to their
+/// `.pub` sibling. When several keys match, the user chooses from a menu.
+///
+/// # Arguments
+/// * `pattern` - The glob pattern, where a leading `~` means the home directory
+///
+/// # Returns
+/// * `Result<PathBuf>` - The chosen public key or error if none matched
+fn choose_identity(pattern: &str) -> Result<PathBuf> {
+    let expanded = match (pattern.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest).to_string_lossy().into_owned(),
+        _ => pattern.to_string(),
+    };
+
+    let candidates: BTreeSet<PathBuf> = glob::glob(&expanded)
+        .with_context(|| format!("Invalid identity pattern '{}'", pattern))?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|path| {
+            if path.extension().is_some_and(|ext| ext == "pub") {
+                Some(path)
+            } else {
+                let public = PathBuf::from(format!("{}.pub", path.display()));
+                public.exists().then_some(public)
+            }
+        })
+        .collect();
+    let candidates: Vec<PathBuf> = candidates.into_iter().collect();
+
+    match candidates.as_slice() {
+        [] => Err(AppError::NoIdentityMatch(pattern.to_string()).into()),
+        [only] => Ok(only.clone()),
+        _ if !io::stdin().is_terminal() => bail!(
+            "Identity pattern '{}' matches {} keys; narrow it down to one",
+            pattern,
+            candidates.len()
+        ),
+        _ => {
+            let labels: Vec<String> = candidates
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect();
+            let selection = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("Select the public key to copy")
+                .items(&labels)
+                .default(0)
+                .interact()
+                .context("Failed to display key menu")?;
+            Ok(candidates[selection].clone())
+        }
+    }
+}
+
+/// Appends the public key to authorized_keys by echoing it in a remote command
+///
+/// # Arguments
+/// * `instance` - The selected VM instance
+/// * `pub_key` - The trimmed public key line
+///
+/// # Returns
+/// * `Result<KeyCopyOutcome>` - Whether the key was added or already present
+fn copy_key_via_echo(instance: &Instance, pub_key: &str) -> Result<KeyCopyOutcome> {
+    let output = run_remote_command(instance, &echo_append_command(pub_key))?;
+    Ok(KeyCopyOutcome::from_remote_output(&output))
+}
+
+/// Builds the remote shell command that appends a key to authorized_keys
+///
+/// The command will:
+/// 1. Create ~/.ssh and authorized_keys with proper permissions
+/// 2. Append the public key unless the exact line is already there
+/// 3. Report ADDED or PRESENT so the outcome can be shown honestly
+///
+/// The key is quoted with [`shell_quote`], so a comment containing `'` can
+/// neither break the command nor inject into it.
+///
+/// # Arguments
+/// * `pub_key` - The trimmed public key line
+///
+/// # Returns
+/// * `String` - The command to run on the VM
+fn echo_append_command(pub_key: &str) -> String {
+    format!(
+        "mkdir -p ~/.ssh && chmod 700 ~/.ssh && touch ~/.ssh/authorized_keys && chmod 600 ~/.ssh/authorized_keys && if grep -qxF -- {0} ~/.ssh/authorized_keys; then echo PRESENT; else echo {0} >> ~/.ssh/authorized_keys && echo ADDED; fi",
+        shell_quote(pub_key)
+    )
+}
+
+/// Appends a comment to a public key line that doesn't already have one
+///
+/// # Arguments
+/// * `pub_key` - The trimmed public key line (`TYPE BASE64 [COMMENT]`)
+/// * `comment` - The comment to add; empty leaves the key unchanged
+///
+/// # Returns
+/// * `String` - The key line, with the comment added when it had none
+fn with_key_comment(pub_key: &str, comment: &str) -> String {
+    let comment = comment.split_whitespace().collect::<Vec<_>>().join("-");
+    if comment.is_empty() || pub_key.split_whitespace().count() != 2 {
+        return pub_key.to_string();
+    }
+    format!("{} {}", pub_key, comment)
+}
+
+/// Expands the `{host}` and `{date}` placeholders in `ssh.pushed_key_comment`
+///
+/// # Arguments
+/// * `template` - The configured comment
+///
+/// # Returns
+/// * `String` - The comment with the local hostname and today's UTC date filled in
+fn expand_key_comment(template: &str) -> String {
+    let host = whoami::fallible::hostname().unwrap_or_else(|_| "localhost".to_string());
+    template
+        .replace("{host}", &host)
+        .replace("{date}", &utc_date(unix_now()))
+}
+
+/// Returns the current time as seconds since the Unix epoch
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+/// Formats a Unix timestamp as the `YYYY-MM-DDTHH:MM:SS+0000` form used by `expireOn`
+///
+/// The guest agent parses the offset without a colon, so this is RFC 3339 apart
+/// from the offset spelling.
+///
+/// # Arguments
+/// * `timestamp` - Seconds since the Unix epoch
+fn utc_timestamp(timestamp: u64) -> String {
+    let seconds = timestamp % 86_400;
+    format!(
+        "{}T{:02}:{:02}:{:02}+0000",
+        utc_date(timestamp),
+        seconds / 3_600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Builds the `ssh-keys` metadata entry for a key, optionally with an expiry
+///
+/// Expiring entries use the `google-ssh` form, which replaces the key comment
+/// with the user name and expiry so the guest agent revokes the key on time.
+///
+/// # Arguments
+/// * `user` - The remote username
+/// * `pub_key` - The public key line (`TYPE BASE64 [COMMENT]`)
+/// * `expire_on` - When the key stops working, from [`utc_timestamp`]
+///
+/// # Returns
+/// * `String` - The `user:key` entry
+fn metadata_key_entry(user: &str, pub_key: &str, expire_on: Option<&str>) -> String {
+    match expire_on {
+        Some(expire_on) => format!(
+            "{}:{} google-ssh {}",
+            user,
+            key_body(pub_key),
+            serde_json::json!({ "userName": user, "expireOn": expire_on })
+        ),
+        None => format!("{}:{}", user, pub_key),
+    }
+}
+
+/// Formats a Unix timestamp as a `YYYY-MM-DD` UTC date
+///
+/// # Arguments
+/// * `timestamp` - Seconds since the Unix epoch
+fn utc_date(timestamp: u64) -> String {
+    // Civil-from-days conversion on the proleptic Gregorian calendar, with
+    // eras of 400 years starting on March 1st
+    let days = (timestamp / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Uploads the public key with `gcloud compute scp` and appends it remotely
+///
+/// The key travels as a file rather than inside a shell command, so long keys
+/// and unusual comment characters survive intact.
+///
+/// # Arguments
+/// * `instance` - The selected VM instance
+/// * `pub_key` - The trimmed public key line
+///
+/// # Returns
+/// * `Result<KeyCopyOutcome>` - Whether the key was added or already present
+fn copy_key_via_scp(instance: &Instance, pub_key: &str) -> Result<KeyCopyOutcome> {
+    let zone = instance.gcloud_zone()?;
+
+    // The local copy is deleted when `key_file` drops, whatever the outcome
+    let mut key_file = tempfile::Builder::new()
+        .prefix("gcloud-ssh-")
+        .suffix(".pub")
+        .tempfile()
+        .context("Failed to create temporary key file")?;
+    writeln!(key_file, "{}", pub_key).context("Failed to write temporary key file")?;
+    let file_name = key_file
+        .path()
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .context("Temporary key file has no name")?;
+
+    let mut command = GcloudCommand::new()
+        .args(["compute", "scp"])
+        .zone(&zone)
+        .arg(key_file.path().to_string_lossy())
+        .arg(format!("{}:/tmp/{}", instance.ssh_target(), file_name))
+        .mutating()
+        .build()?;
+    let status = gcloud::attach(&mut command)?;
+    drop(key_file);
+
+    if !status.success() {
+        return Err(AppError::KeyCopy(format!("gcloud compute scp exited with {}", status)).into());
+    }
+
+    let remote_cmd = format!(
+        "mkdir -p ~/.ssh && chmod 700 ~/.ssh && touch ~/.ssh/authorized_keys && chmod 600 ~/.ssh/authorized_keys && {{ if grep -qxFf /tmp/{0} ~/.ssh/authorized_keys; then echo PRESENT; else cat /tmp/{0} >> ~/.ssh/authorized_keys && echo ADDED; fi; }}; status=$?; rm -f /tmp/{0}; exit $status",
+        file_name
+    );
+
+    let output = run_remote_command(instance, &remote_cmd)?;
+    Ok(KeyCopyOutcome::from_remote_output(&output))
+}
+
+/// Adds the key to the instance's `ssh-keys` metadata, keeping existing entries
+///
+/// On guest-agent-managed VMs authorized_keys is regenerated from metadata, so
+/// this is the only way for a key to persist there.
+///
+/// # Arguments
+/// * `instance` - The selected VM instance
+/// * `entry` - The `user:key` metadata entry to add
+///
+/// # Returns
+/// * `Result<KeyCopyOutcome>` - Whether the key was added or already present
+fn copy_key_via_metadata(instance: &Instance, entry: &str) -> Result<KeyCopyOutcome> {
+    let zone = instance.gcloud_zone()?;
+
+    // Read the current ssh-keys value so it can be merged rather than replaced
+    let existing = read_metadata_ssh_keys(instance)?;
+    let Some(merged) = merge_ssh_keys(&existing, entry) else {
+        return Ok(KeyCopyOutcome::AlreadyPresent);
+    };
+
+    // Pass the value through a file since it spans multiple lines; the file is
+    // deleted when `metadata_file` drops, even on an early return
+    let mut metadata_file = tempfile::Builder::new()
+        .prefix("gcloud-ssh-keys-")
+        .tempfile()
+        .context("Failed to create temporary metadata file")?;
+    metadata_file
+        .write_all(merged.as_bytes())
+        .context("Failed to write temporary metadata file")?;
+
+    let mut command = GcloudCommand::new()
+        .args(["compute", "instances", "add-metadata", &instance.name])
+        .zone(&zone)
+        .arg(format!(
+            "--metadata-from-file=ssh-keys={}",
+            metadata_file.path().display()
+        ))
+        .mutating()
+        .build()?;
+    let status = gcloud::attach(&mut command)?;
+    if !status.success() {
+        return Err(AppError::MetadataUpdate(format!("gcloud exited with {}", status)).into());
+    }
+
+    Ok(KeyCopyOutcome::Added)
+}
+
+/// Reads the instance's `ssh-keys` metadata value
+///
+/// # Arguments
+/// * `instance` - The selected VM instance
+///
+/// # Returns
+/// * `Result<String>` - The newline-separated entries, empty when none are set
+fn read_metadata_ssh_keys(instance: &Instance) -> Result<String> {
+    let zone = instance.gcloud_zone()?;
+    let mut command = GcloudCommand::new()
+        .args(["compute", "instances", "describe", &instance.name])
+        .zone(&zone)
+        .arg("--format=json(metadata)")
+        .build()?;
+    let output = gcloud::capture(&mut command)?;
+    if !output.status.success() {
+        return Err(AppError::MetadataUpdate(gcloud::error_message(&output.stderr)).into());
+    }
+
+    let described: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .map_err(|e| AppError::MetadataUpdate(format!("unreadable instance metadata: {}", e)))?;
+    Ok(described["metadata"]["items"]
+        .as_array()
+        .and_then(|items| items.iter().find(|item| item["key"] == "ssh-keys"))
+        .and_then(|item| item["value"].as_str())
+        .unwrap_or_default()
+        .to_string())
+}
+
+/// Appends an entry to an `ssh-keys` metadata value
+///
+/// # Arguments
+/// * `existing` - The current metadata value (newline-separated entries)
+/// * `entry` - The `user:key` entry to add
+///
+/// # Returns
+/// * `Option<String>` - The merged value, or None if the entry is already present
+fn merge_ssh_keys(existing: &str, entry: &str) -> Option<String> {
+    let mut lines: Vec<&str> = existing
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    if lines.contains(&entry) {
+        return None;
+    }
+
+    lines.push(entry);
+    Some(lines.join("\n"))
+}
+
+/// Returns the username to log in as on the VM
+///
+/// # Arguments
+/// * `instance` - The selected VM instance
+fn remote_username(instance: &Instance) -> String {
+    // Use the host's own username when it has one, otherwise the local username
+    instance.user.clone().unwrap_or_else(whoami::username)
+}
+
+/// Runs a shell command on the VM through `gcloud compute ssh`
+///
+/// # Arguments
+/// * `instance` - The selected VM instance
+/// * `remote_cmd` - The command to run on the VM
+///
+/// # Returns
+/// * `Result<String>` - The command's standard output or error
+fn run_remote_command(instance: &Instance, remote_cmd: &str) -> Result<String> {
+    let zone = instance.gcloud_zone()?;
+    let mut command = GcloudCommand::new()
+        .args(["compute", "ssh", &instance.ssh_target()])
+        .zone(&zone)
+        .args(["--command", remote_cmd])
+        .mutating()
+        .build()?;
+    let spinner = terminal_fx::LiveSpinner::start(&format!(
+        "Running gcloud compute ssh on {}",
+        instance.name
+    ));
+    let output = gcloud::attach_showing_stages(&mut command, &spinner);
+    spinner.finish();
+    let output = output?;
+
+    if !output.status.success() {
+        let mut message = format!("gcloud compute ssh exited with {}", output.status);
+        if !output.stderr.is_empty() {
+            message = format!("{}: {}", message, gcloud::error_message(&output.stderr));
+        }
+        return Err(AppError::KeyCopy(message).into());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Lets the user choose what to do with the selected VM
+///
+/// # Arguments
+/// * `instance` - The selected VM instance
+///
+/// # Returns
+/// * `Result<cli::Action>` - The chosen action or error
+fn select_action(instance: &Instance) -> Result<cli::Action> {
+    let power_action = if instance.status == "RUNNING" {
+        cli::Action::Stop
+    } else {
+        cli::Action::Start
+    };
+    let actions = [
+        cli::Action::Connect,
+        cli::Action::CopyAndConnect,
+        cli::Action::CopyKey,
+        cli::Action::PrintCommand,
+        cli::Action::Run(String::new()),
+        cli::Action::SerialConsole,
+        cli::Action::WriteSshConfig,
+        power_action,
+        cli::Action::Delete,
+    ];
+    let labels: Vec<&str> = actions.iter().map(cli::Action::label).collect();
+
+    render::renderer().info(&format!(
+        "What would you like to do with {}?",
+        instance.name
+    ));
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .items(&labels)
+        .default(0)
+        .interact()
+        .context("Failed to display action menu")?;
+
+    Ok(actions[selection].clone())
+}
+
+/// Dispatches the chosen action for the selected VM
+///
+/// # Arguments
+/// * `action` - The action to run
+/// * `instance` - The selected VM instance
+/// * `options` - Parsed command-line options
+/// * `user_config` - Settings from the configuration file
+/// * `ssh_dir` - Directory holding the key pair
+///
+/// # Returns
+/// * `Result<()>` - Success or error information
+fn run_action(
+    action: &cli::Action,
+    instance: &Instance,
+    options: &cli::Options,
+    user_config: &UserConfig,
+    ssh_dir: &Path,
+) -> Result<()> {
+    // Windows VMs are reached over RDP, so keys aren't copied and SSH is refused
+    if is_windows(instance, options) {
+        match action {
+            cli::Action::CopyKey => {
+                render::renderer().info(&format!(
+                    "{} runs Windows, so no SSH key is copied; connect with RDP instead.",
+                    instance.name.bold()
+                ));
+                render::renderer().section(messages::text(Message::ConnectionSection));
+                return print_ssh_command(instance, options);
+            }
+            cli::Action::Connect
+            | cli::Action::CopyAndConnect
+            | cli::Action::Run(_)
+            | cli::Action::WriteSshConfig => {
+                return Err(
+                    AppError::UnsupportedOs(instance.name.clone(), "Windows".to_string()).into(),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    match action {
+        cli::Action::CopyKey => {
+            render::renderer().section(messages::text(Message::KeyDeploymentSection));
+            copy_ssh_key_to_vm(instance, options, user_config, ssh_dir)
+                .context("Failed to copy SSH key to VM")?;
+
+            render::renderer().section(messages::text(Message::ConnectionSection));
+            print_ssh_command(instance, options)
+        }
+        cli::Action::PrintCommand => {
+            render::renderer().section(messages::text(Message::ConnectionSection));
+            print_ssh_command(instance, options)
+        }
+        cli::Action::Connect => {
+            render::renderer().section(messages::text(Message::ConnectingSection));
+            let zone = instance.gcloud_zone()?;
+            let mut command = GcloudCommand::new()
+                .args(["compute", "ssh", &instance.ssh_target()])
+                .zone(&zone)
+                .mutating()
+                .build()?;
+            export_vm_env(&mut command, instance, options);
+            run_interactive(&mut command)
+        }
+        cli::Action::CopyAndConnect => {
+            render::renderer().section(messages::text(Message::KeyDeploymentSection));
+            copy_ssh_key_to_vm(instance, options, user_config, ssh_dir)
+                .context("Failed to copy SSH key to VM")?;
+
+            render::renderer().section(messages::text(Message::ConnectingSection));
+            let args = ssh_command_args(instance, options)?;
+            let mut command = local_command(&args[0], "opening an SSH session")?;
+            command.args(&args[1..]);
+            export_vm_env(&mut command, instance, options);
+            run_interactive(&mut command)
+        }
+        cli::Action::Run(remote_cmd) => {
+            let remote_cmd = if remote_cmd.is_empty() {
+                Input::<String>::with_theme(&ColorfulTheme::default())
+                    .with_prompt("Command to run")
+                    .interact_text()
+                    .context("Failed to read command")?
+            } else {
+                remote_cmd.clone()
+            };
+
+            render::renderer().section(messages::text(Message::RemoteCommandSection));
+            let zone = instance.gcloud_zone()?;
+            let mut command = GcloudCommand::new()
+                .args(["compute", "ssh", &instance.ssh_target()])
+                .zone(&zone)
+                .args(["--command", &remote_cmd])
+                .mutating()
+                .build()?;
+            export_vm_env(&mut command, instance, options);
+            run_paged(&mut command, options)
+        }
+        cli::Action::SerialConsole => {
+            render::renderer().section(messages::text(Message::SerialConsoleSection));
+            let zone = instance.gcloud_zone()?;
+            if options.pager {
+                // An interactive session can't be paged, so show the log so far;
+                // it stands in for the console and is refused under --read-only too
+                return run_paged(
+                    &mut GcloudCommand::new()
+                        .args(["compute", "instances", "get-serial-port-output"])
+                        .arg(&instance.name)
+                        .zone(&zone)
+                        .mutating()
+                        .build()?,
+                    options,
+                );
+            }
+            run_interactive(
+                &mut GcloudCommand::new()
+                    .args(["compute", "connect-to-serial-port", &instance.name])
+                    .zone(&zone)
+                    .mutating()
+                    .build()?,
+            )
+        }
+        cli::Action::Tunnel { local, remote } => {
+            render::renderer().section(messages::text(Message::TunnelSection));
+            open_tunnel(instance, *local, *remote)
+        }
+        cli::Action::WriteSshConfig => {
+            render::renderer().section(messages::text(Message::SshConfigSection));
+            write_iap_ssh_config(instance)
+        }
+        cli::Action::Start => set_vm_running(instance, true),
+        cli::Action::Stop => {
+            if !confirm_stop(instance, options)? {
+                render::renderer().info(messages::text(Message::StopCancelled));
+                return Ok(());
+            }
+            set_vm_running(instance, false)
+        }
+        cli::Action::Delete => {
+            if !confirm_delete(instance, options)? {
+                render::renderer().info(messages::text(Message::DeleteCancelled));
+                return Ok(());
+            }
+            delete_vm(instance)
+        }
+    }
+}
+
+/// Lets the user pick several VMs and runs the action on each of them
+///
+/// Only key copies and lifecycle actions make sense in bulk; each VM still gets
+/// the action's usual confirmation, after the bulk gate in [`confirm_bulk`].
+///
+/// # Arguments
+/// * `instances` - List of available VM instances
+/// * `starred` - Names of favorite VMs, marked in the menu
+/// * `options` - Parsed command-line options
+/// * `user_config` - Settings from the configuration file
+/// * `ssh_dir` - Directory holding the key pair
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error if any VM failed
+fn run_bulk(
+    instances: &[Instance],
+    starred: &BTreeSet<String>,
+    options: &cli::Options,
+    user_config: &UserConfig,
+    ssh_dir: &Path,
+) -> Result<()> {
+    let action = options.action.clone().unwrap_or(cli::Action::CopyKey);
+    if !matches!(
+        action,
+        cli::Action::CopyKey | cli::Action::Start | cli::Action::Stop | cli::Action::Delete
+    ) {
+        return Err(AppError::UnsupportedBulkAction(action.label().to_string()).into());
+    }
+    if !io::stdin().is_terminal() {
+        return Err(AppError::VmSelection("--multi needs an interactive terminal".into()).into());
+    }
+
+    render::renderer().section(messages::text(Message::SelectionSection));
+    let rows = menu_rows(instances, starred, options.list_columns());
+    let labels: Vec<&str> = rows.iter().map(|(_, label)| label.as_str()).collect();
+    let chosen = MultiSelect::with_theme(&ColorfulTheme::default())
+        .with_prompt("Select VMs (space to toggle, enter to confirm)")
+        .items(&labels)
+        .interact()
+        .map_err(|e| AppError::VmSelection(e.to_string()))?;
+
+    let mut selected = Vec::new();
+    for idx in chosen {
+        selected.push(
+            rows[idx]
+                .0
+                .clone()
+                .with_login_user(
+                    options.user.as_deref(),
+                    &user_config.listing.user_label,
+                    &user_config.os_users,
+                )
+                .with_nic(options.nic)?,
+        );
+    }
+    if selected.is_empty() {
+        render::renderer().info("No VMs selected.");
+        return Ok(());
+    }
+
+    let threshold = user_config.safety.bulk_confirm_threshold;
+    if !confirm_bulk(&selected, &action, threshold, options)? {
+        render::renderer().info("Bulk action cancelled.");
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for instance in &selected {
+        if let Some(project) = &instance.project {
+            gcloud::set_project(project);
+        }
+        if let Err(e) = run_action(&action, instance, options, user_config, ssh_dir) {
+            failed += 1;
+            render::renderer().warning(&format!("{}: {:#}", instance.name, e));
+        }
+    }
+
+    if failed > 0 {
+        return Err(AppError::BulkFailed(failed, selected.len()).into());
+    }
+    Ok(())
+}
+
+/// Asks for an extra confirmation when more VMs than the threshold are selected
+///
+/// The affected VMs are listed in a box first. Without a terminal, or with
+/// `--yes`, proceeding above the threshold also requires `--force`.
+///
+/// # Arguments
+/// * `selected` - The VMs the action will run on
+/// * `action` - The action to run
+/// * `threshold` - `safety.bulk_confirm_threshold` from the configuration file
+/// * `options` - Parsed command-line options
+///
+/// # Returns
+/// * `Result<bool>` - Whether to proceed
+fn confirm_bulk(
+    selected: &[Instance],
+    action: &cli::Action,
+    threshold: usize,
+    options: &cli::Options,
+) -> Result<bool> {
+    if selected.len() <= threshold {
+        return Ok(true);
+    }
+
+    let names: Vec<String> = selected
+        .iter()
+        .map(|instance| format!("{} ({})", instance.name, instance.zone()))
+        .collect();
+    render::renderer().warning(&format!(
+        "{}: {} VMs selected (threshold {})",
+        action.label(),
+        selected.len(),
+        threshold
+    ));
+    terminal_fx::framed_message(&names.join("\n"), term_utils::layout_width().min(72));
+
+    if options.yes || !io::stdin().is_terminal() {
+        if options.force {
+            return Ok(true);
+        }
+        return Err(AppError::BulkNotConfirmed(selected.len(), threshold).into());
+    }
+
+    Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!(
+            "Run '{}' on all {} VMs listed above?",
+            action.label(),
+            selected.len()
+        ))
+        .default(false)
+        .interact()
+        .context("Failed to read confirmation")
+}
+
+/// Passes the VM's details to a spawned ssh or gcloud process when `--export-env` is set
+///
+/// Sets `GCLOUD_VM_NAME`, `GCLOUD_VM_ZONE` and `GCLOUD_VM_IP` (when known) in the
+/// child's environment only; this process's environment is left alone.
+///
+/// # Arguments
+/// * `command` - The process about to be spawned
+/// * `instance` - The selected VM instance
+/// * `options` - Parsed command-line options
+fn export_vm_env(command: &mut Command, instance: &Instance, options: &cli::Options) {
+    if !options.export_env {
+        return;
+    }
+    command.env("GCLOUD_VM_NAME", &instance.name);
+    if !instance.is_static() {
+        command.env("GCLOUD_VM_ZONE", instance.zone());
+    }
+    if let Some(address) = instance.address(options.ipv6) {
+        command.env("GCLOUD_VM_IP", address);
+    }
+}
+
+/// Asks the user to confirm stopping a VM
+///
+/// # Arguments
+/// * `instance` - The VM to stop
+/// * `options` - Parsed command-line options
+///
+/// # Returns
+/// * `Result<bool>` - Whether the stop was confirmed
+fn confirm_stop(instance: &Instance, options: &cli::Options) -> Result<bool> {
+    if options.yes {
+        return Ok(true);
+    }
+    if !io::stdin().is_terminal() {
+        return Err(AppError::VmStop(
+            "confirmation required; pass --yes to stop without a prompt".to_string(),
+        )
+        .into());
+    }
+
+    Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!("Stop VM {}?", instance.name))
+        .default(false)
+        .interact()
+        .context("Failed to display confirmation prompt")
+}
+
+/// Asks the user to type the VM name to confirm deleting it
+///
+/// `--yes` alone is not enough for a delete; it must be combined with `--force`.
+///
+/// # Arguments
+/// * `instance` - The VM to delete
+/// * `options` - Parsed command-line options
+///
+/// # Returns
+/// * `Result<bool>` - Whether the delete was confirmed
+fn confirm_delete(instance: &Instance, options: &cli::Options) -> Result<bool> {
+    if options.yes {
+        if options.force {
+            return Ok(true);
+        }
+        return Err(AppError::VmDelete(
+            "refusing to delete with --yes unless --force is also given".to_string(),
+        )
+        .into());
+    }
+    if !io::stdin().is_terminal() {
+        return Err(AppError::VmDelete(
+            "confirmation required; pass --yes --force to delete without a prompt".to_string(),
+        )
+        .into());
+    }
+
+    render::renderer().info(&format!(
+        "This permanently deletes {} in {}. Type the VM name to confirm.",
+        instance.name.bold(),
+        instance.zone()
+    ));
+    let typed: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("VM name")
+        .allow_empty(true)
+        .interact_text()
+        .context("Failed to read confirmation")?;
+
+    Ok(typed.trim() == instance.name)
+}
+
+/// Deletes the selected VM
+///
+/// # Arguments
+/// * `instance` - The VM to delete
+///
+/// # Returns
+/// * `Result<()>` - Success or error information
+fn delete_vm(instance: &Instance) -> Result<()> {
+    render::renderer().section(messages::text(Message::DeletingSection));
+
+    let zone = instance.gcloud_zone()?;
+    let mut command = GcloudCommand::new()
+        .args(["compute", "instances", "delete", &instance.name])
+        .zone(&zone)
+        .arg("--quiet")
+        .mutating()
+        .build()?;
+    let status = gcloud::attach(&mut command)?;
+
+    if !status.success() {
+        return Err(AppError::VmDelete(format!("gcloud exited with {}", status)).into());
+    }
+
+    render::renderer().success(&format!("VM {} deleted.", instance.name.bold()));
+    Ok(())
+}
+
+/// Creates a process outside gcloud that changes something, refusing it under `--read-only`
+///
+/// This is the counterpart of [`GcloudCommand::mutating`] for local programs
+/// such as `ssh` and `ssh-keygen`.
+///
+/// # Arguments
+/// * `program` - The program to run
+/// * `operation` - What running it does, for the read-only error message
+///
+/// # Returns
+/// * `Result<Command, AppError>` - The command, or [`AppError::ReadOnly`] in read-only mode
+fn local_command(program: &str, operation: &str) -> Result<Command, AppError> {
+    settings::ensure_writable(operation)?;
+    Ok(Command::new(program))
+}
+
+/// Runs a command attached to the user's terminal, failing on a non-zero exit
+///
+/// These are interactive sessions on a VM, which can change anything there,
+/// so the command comes from a mutating [`GcloudCommand`] or [`local_command`]
+/// and has already been checked against `--read-only`.
+///
+/// # Arguments
+/// * `command` - The command to run
+///
+/// # Returns
+/// * `Result<()>` - Success or error information
+fn run_interactive(command: &mut Command) -> Result<()> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    logging::command(command);
+    let status = command
+        .status()
+        .with_context(|| format!("Failed to run {}", program))?;
+
+    if !status.success() {
+        bail!("{} exited with {}", program, status);
+    }
+    Ok(())
+}
+
+/// Runs a command with its output sent through a pager when `--pager` is set
+///
+/// # Arguments
+/// * `command` - The command to run
+/// * `options` - Parsed command-line options
+///
+/// # Returns
+/// * `Result<()>` - Success or error information
+fn run_paged(command: &mut Command, options: &cli::Options) -> Result<()> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let status = pager::run_paged(command, pager::pager(options.pager))?;
+
+    if !status.success() {
+        bail!("{} exited with {}", program, status);
+    }
+    Ok(())
+}
+
+/// Forwards a local port to a VM port through an IAP tunnel until it is interrupted
+///
+/// The tunnel's output is streamed to the terminal; Ctrl-C stops it.
+///
+/// # Arguments
+/// * `instance` - The selected VM instance
+/// * `local` - Port to listen on locally
+/// * `remote` - Port on the VM to forward to
+///
+/// # Returns
+/// * `Result<()>` - Success or error information
+fn open_tunnel(instance: &Instance, local: u16, remote: u16) -> Result<()> {
+    let zone = instance.gcloud_zone()?;
+    let mut command = GcloudCommand::new()
+        .args(["compute", "start-iap-tunnel", &instance.name])
+        .arg(remote.to_string())
+        .arg(format!("--local-host-port=localhost:{}", local))
+        .zone(&zone)
+        .mutating()
+        .build()?;
+    render::renderer().info(&format!(
+        "Forwarding localhost:{} to {}:{} (press Ctrl-C to stop)...",
+        local, instance.name, remote
+    ));
+    logging::command(&command);
+
+    let status = command
+        .status()
+        .map_err(|e| AppError::Tunnel(e.to_string()))?;
+    if !status.success() {
+        return Err(AppError::Tunnel(format!("gcloud exited with {}", status)).into());
+    }
+    Ok(())
+}
+
+/// Adds a `~/.ssh/config` entry that reaches the VM through an IAP tunnel
+///
+/// The entry's `ProxyCommand` runs `gcloud compute start-iap-tunnel` with
+/// `--listen-on-stdin`, so a plain `ssh <name>` connects afterwards. An existing
+/// entry for the VM is replaced. gcloud releases without `--listen-on-stdin` only
+/// get a warning, since the entry works once the SDK is updated.
+///
+/// # Arguments
+/// * `instance` - The selected VM instance
+///
+/// # Returns
+/// * `Result<()>` - Success or error information
+fn write_iap_ssh_config(instance: &Instance) -> Result<()> {
+    if instance.is_static() {
+        return Err(AppError::Tunnel(format!(
+            "{} comes from a hosts file, so gcloud can't tunnel to it",
+            instance.name
+        ))
+        .into());
+    }
+    let zone = instance.gcloud_zone()?;
+
+    match gcloud::accepts_flag(&["compute", "start-iap-tunnel"], "--listen-on-stdin") {
+        Ok(true) => {}
+        Ok(false) => render::renderer().warning(
+            "This gcloud's start-iap-tunnel has no --listen-on-stdin; run `gcloud components update` before using the entry",
+        ),
+        Err(e) => render::renderer().warning(&format!(
+            "Could not check that gcloud supports --listen-on-stdin: {:#}",
+            e
+        )),
+    }
+
+    let Some(path) = ssh_config::default_path() else {
+        bail!("Could not determine the home directory for ~/.ssh/config");
+    };
+    // Write through a symlinked config (e.g. from a dotfiles repository)
+    let path = fs::canonicalize(&path).unwrap_or(path);
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+
+    let block = ssh_config::iap_host_block(
+        &instance.name,
+        &remote_username(instance),
+        &iap_proxy_command(instance, &zone),
+    );
+    storage::save_text(
+        &path,
+        &ssh_config::upsert_host_block(&content, &instance.name, &block),
+    )?;
+
+    render::renderer().success(&format!(
+        "Wrote Host {} to {}; connect with: ssh {}",
+        instance.name,
+        path.display(),
+        instance.name
+    ));
+    Ok(())
+}
+
+/// Builds the `ProxyCommand` that carries ssh over an IAP tunnel to the VM
+///
+/// # Arguments
+/// * `instance` - The selected VM instance
+/// * `zone` - The VM's zone
+///
+/// # Returns
+/// * `String` - The command line, quoted for the shell ssh runs it with
+fn iap_proxy_command(instance: &Instance, zone: &str) -> String {
+    let mut gcloud = GcloudCommand::new();
+    if let Some(project) = &instance.project {
+        gcloud = gcloud.project(project);
+    }
+    let gcloud = gcloud
+        .args([
+            "compute",
+            "start-iap-tunnel",
+            &instance.name,
+            "%p",
+            "--listen-on-stdin",
+        ])
+        .zone(zone);
+    shell_command_line(&gcloud)
+}
+
+/// Starts or stops the selected VM
+///
+/// # Arguments
+/// * `instance` - The selected VM instance
+/// * `running` - True to start the VM, false to stop it
+///
+/// # Returns
+/// * `Result<()>` - Success or error information
+fn set_vm_running(instance: &Instance, running: bool) -> Result<()> {
+    let (verb, section) = if running {
+        ("start", "STARTING VM")
+    } else {
+        ("stop", "STOPPING VM")
+    };
+    render::renderer().section(section);
+
+    let zone = instance.gcloud_zone()?;
+    let mut command = GcloudCommand::new()
+        .args(["compute", "instances", verb, &instance.name])
+        .zone(&zone)
+        .mutating()
+        .build()?;
+    let status = gcloud::attach(&mut command)?;
+
+    if !status.success() {
+        let error_msg = format!("gcloud exited with {}", status);
+        return Err(if running {
+            AppError::VmStart(error_msg)
+        } else {
+            AppError::VmStop(error_msg)
+        }
+        .into());
+    }
+
+    let message = if running {
+        format!("VM {} started.", instance.name.bold())
+    } else {
+        format!("VM {} stopped.", instance.name.bold())
+    };
+    render::renderer().success(&message);
+    Ok(())
+}
+
+/// Builds the plain `ssh` command line used to connect to the VM
+///
+/// # Arguments
+/// * `instance` - The selected VM instance
+///
+/// # Returns
+/// * `Result<Vec<String>>` - The program and its arguments
+fn ssh_command_args(instance: &Instance, options: &cli::Options) -> Result<Vec<String>> {
+    Ok(connection_route(instance, options)?.command)
+}
+
+/// Builds the ssh command line for an address that is already resolved
+///
+/// # Arguments
+/// * `instance` - The selected VM instance
+/// * `address` - Host name or address for the SSH destination
+/// * `options` - Parsed command-line options
+///
+/// # Returns
+/// * `Vec<String>` - The program and its arguments
+fn ssh_command_to(instance: &Instance, address: &str, options: &cli::Options) -> Vec<String> {
+    let mut args = vec!["ssh".to_string()];
+    if let Some(bind) = options.bind {
+        args.extend(["-b".to_string(), bind.to_string()]);
+    }
+
+    // A matching Host entry already holds the user, key and any other options
+    if options.use_ssh_config {
+        match ssh_config::lookup(&instance.name, address) {
+            Some(alias) => {
+                logging::verbose(&format!("Using Host {} from ~/.ssh/config", alias));
+                args.push(alias);
+                return args;
+            }
+            None => logging::verbose(&format!(
+                "No Host in ~/.ssh/config matches {} or {}; building the command",
+                instance.name, address
+            )),
+        }
+    }
+
+    args.push(format!("{}@{}", remote_username(instance), address));
+    args
+}
+
+/// How the selected VM is reached
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Route {
+    /// What the address is, e.g. "External IP"
+    label: &'static str,
+
+    /// The host or address shown to the user
+    address: String,
+
+    /// The program and its arguments that connect
+    command: Vec<String>,
+}
+
+/// Works out how to connect to the VM
+///
+/// `--internal-dns` connects to the internal DNS name. Otherwise the external
+/// address is used, and a VM without one is handled as `--no-external-ip-fallback`
+/// or `connect.no_external_ip_fallback` says.
+///
+/// # Arguments
+/// * `instance` - The selected VM instance
+/// * `options` - Parsed command-line options
+///
+/// # Returns
+/// * `Result<Route>` - The route, or an error if the VM can't be reached
+fn connection_route(instance: &Instance, options: &cli::Options) -> Result<Route> {
+    if options.internal_dns {
+        return internal_dns_route(instance, options);
+    }
+    if let Some(address) = instance.ssh_address(options.ipv6) {
+        return Ok(Route {
+            label: "External IP",
+            command: ssh_command_to(instance, &address, options),
+            address,
+        });
+    }
+
+    let fallback = options.no_external_ip_fallback.unwrap_or_default();
+    if fallback != cli::NoExternalIpFallback::Error {
+        logging::verbose(&format!(
+            "{} has no external IP; falling back to {}",
+            instance.name,
+            fallback.name()
+        ));
+    }
+    match fallback {
+        cli::NoExternalIpFallback::Error => Err(AppError::NoExternalIp.into()),
+        cli::NoExternalIpFallback::Internal => internal_dns_route(instance, options),
+        // Hosts-file entries are unknown to gcloud
+        _ if instance.is_static() => Err(AppError::NoExternalIp.into()),
+        cli::NoExternalIpFallback::Iap | cli::NoExternalIpFallback::GcloudSsh => {
+            let zone = instance.gcloud_zone()?;
+            let mut gcloud = GcloudCommand::new();
+            if let Some(project) = &instance.project {
+                gcloud = gcloud.project(project);
+            }
+            let mut gcloud = gcloud
+                .args(["compute", "ssh", &instance.ssh_target()])
+                .zone(&zone);
+            let label = if fallback == cli::NoExternalIpFallback::Iap {
+                gcloud = gcloud.arg("--tunnel-through-iap");
+                "Internal IP (through IAP)"
+            } else {
+                "Internal IP (through gcloud)"
+            };
+
+            let mut command = vec![gcloud::program().display().to_string()];
+            command.extend(gcloud.to_args());
+            Ok(Route {
+                label,
+                address: instance
+                    .internal_ip()
+                    .unwrap_or_else(|| instance.name.clone()),
+                command,
+            })
+        }
+    }
+}
+
+/// Builds the route to the VM's internal DNS name
+///
+/// The internal name needs the VM's project, which is taken from a multi-project
+/// listing or else from the gcloud context.
+///
+/// # Arguments
+/// * `instance` - The selected VM instance
+/// * `options` - Parsed command-line options
+///
+/// # Returns
+/// * `Result<Route>` - The route, or an error if the name can't be determined
+fn internal_dns_route(instance: &Instance, options: &cli::Options) -> Result<Route> {
+    if instance.is_static() {
+        return Err(AppError::InternalDns(format!(
+            "{} comes from a hosts file, not from GCP",
+            instance.name
+        ))
+        .into());
+    }
+
+    let zone = instance.zone();
+    let project = match &instance.project {
+        Some(project) => Some(project.clone()),
+        None => {
+            gcloud::context()
+                .context("Failed to read the project for --internal-dns")?
+                .project
+        }
+    };
+    let address = internal_dns_name(
+        &instance.name,
+        &zone,
+        project.as_deref().unwrap_or_default(),
+    )?;
+    Ok(Route {
+        label: "Internal DNS",
+        command: ssh_command_to(instance, &address, options),
+        address,
+    })
+}
+
+/// Builds a VM's zonal internal DNS name, `<name>.<zone>.c.<project>.internal`
+///
+/// Domain-scoped projects (`example.com:my-project`) put the domain after the
+/// project ID, as GCP does.
+///
+/// # Arguments
+/// * `name` - The VM name
+/// * `zone` - The VM's zone
+/// * `project` - The VM's project ID
+///
+/// # Returns
+/// * `Result<String, AppError>` - The DNS name, or an error naming the missing part
+fn internal_dns_name(name: &str, zone: &str, project: &str) -> Result<String, AppError> {
+    let missing: Vec<&str> = [("VM name", name), ("zone", zone), ("project", project)]
+        .into_iter()
+        .filter(|(_, value)| value.trim().is_empty())
+        .map(|(part, _)| part)
+        .collect();
+    if !missing.is_empty() {
+        return Err(AppError::InternalDns(format!(
+            "no {} is known",
+            missing.join(" or ")
+        )));
+    }
+
+    let project = match project.split_once(':') {
+        Some((domain, id)) => format!("{}.{}", id, domain),
+        None => project.to_string(),
+    };
+    Ok(format!("{}.{}.c.{}.internal", name, zone, project))
+}
+
+/// Checks whether a VM is treated as Windows, detected or forced with `--windows`
+///
+/// # Arguments
+/// * `instance` - The selected VM instance
+/// * `options` - Parsed command-line options
+fn is_windows(instance: &Instance, options: &cli::Options) -> bool {
+    options.windows || instance.is_windows()
+}
+
+/// Prints how to reach a Windows VM over RDP instead of an SSH command
+///
+/// # Arguments
+/// * `instance` - The selected VM instance
+/// * `options` - Parsed command-line options
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error if the VM has no external address
+fn print_rdp_hint(instance: &Instance, options: &cli::Options) -> Result<()> {
+    let address = instance
+        .address(options.ipv6)
+        .ok_or(AppError::NoExternalIp)?;
+    let user = remote_username(instance);
+    let (password_reset, clients) = rdp_commands(instance, &address, &user);
+
+    render::renderer().rdp_info(&RdpInfo {
+        name: instance.name.clone(),
+        address,
+        password_reset,
+        clients,
+    });
+    Ok(())
+}
+
+/// Builds the password reset and RDP client commands for a Windows VM
+///
+/// # Arguments
+/// * `instance` - The selected VM instance
+/// * `address` - The external address to connect to
+/// * `user` - The Windows account name
+///
+/// # Returns
+/// * `(Option<String>, Vec<String>)` - The `gcloud compute reset-windows-password`
+///   command (none for hosts-file entries), then the `mstsc` and `xfreerdp` commands
+fn rdp_commands(instance: &Instance, address: &str, user: &str) -> (Option<String>, Vec<String>) {
+    let reset = (!instance.is_static()).then(|| {
+        let mut gcloud = GcloudCommand::new();
+        if let Some(project) = &instance.project {
+            gcloud = gcloud.project(project);
+        }
+        let gcloud = gcloud
+            .args(["compute", "reset-windows-password", &instance.name])
+            .zone(&instance.zone())
+            .args(["--user", user]);
+        shell_command_line(&gcloud)
+    });
+    let host = if address.contains(':') {
+        format!("[{}]", address)
+    } else {
+        address.to_string()
+    };
+    let clients = vec![
+        format!("mstsc /v:{}", host),
+        format!("xfreerdp /v:{} /u:{}", host, user),
+    ];
+    (reset, clients)
+}
+
+/// Formats a gcloud command as one shell-quoted line
+///
+/// # Arguments
+/// * `gcloud` - The command, with its global flags
+///
+/// # Returns
+/// * `String` - The program and arguments, quoted where the shell needs it
+fn shell_command_line(gcloud: &GcloudCommand) -> String {
+    gcloud
+        .command_line()
+        .iter()
+        .map(|arg| shell_word(arg))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Chooses how results are rendered for this run
+///
+/// `--json` wins, then inventory and env output, which keep every message on stderr,
+/// then `--info-format plain`. Styled output repeats the bare
+/// command under its box when asked, or when stdout is captured rather than
+/// shown on a terminal.
+///
+/// # Arguments
+/// * `options` - Parsed command-line options
+///
+/// # Returns
+/// * `render::Style` - The style to install
+fn render_style(options: &cli::Options) -> render::Style {
+    if options.json {
+        render::Style::Json
+    } else if options.inventory.is_some() || options.info_format == cli::InfoFormat::Env {
+        render::Style::Stderr
+    } else if options.info_format == cli::InfoFormat::Plain {
+        render::Style::Plain
+    } else if options.copyable || !term_utils::caps().is_tty {
+        render::Style::PrettyCopyable
+    } else {
+        render::Style::Pretty
+    }
+}
+
+/// Generates and prints the SSH command to connect to the VM
+///
+/// Windows VMs get RDP instructions instead.
+///
+/// # Arguments
+/// * `instance` - The selected VM instance
+/// * `options` - Parsed command-line options
+///
+/// # Returns
+/// * `Result<()>` - Success or error information
+fn print_ssh_command(instance: &Instance, options: &cli::Options) -> Result<()> {
+    if is_windows(instance, options) {
+        return print_rdp_hint(instance, options);
+    }
+
+    // Construct the SSH command
+    let route = connection_route(instance, options)?;
+    let ssh_cmd = route.command.join(" ");
+
+    let summary = instance.summary();
+    let mut info = render::ConnectionInfo {
+        name: summary.name,
+        zone: summary.zone,
+        address_label: route.label,
+        address: route.address,
+        ..Default::default()
+    };
+    if options.show_host_key && !instance.is_static() {
+        match fetch_host_keys(instance) {
+            Ok(keys) => {
+                info.host_keys = Some(
+                    keys.into_iter()
+                        .map(|(key_type, fingerprint)| render::HostKey {
+                            key_type,
+                            fingerprint,
+                        })
+                        .collect(),
+                )
+            }
+            Err(e) => {
+                info.host_keys = Some(Vec::new());
+                info.host_key_error = Some(e.to_string());
+            }
+        }
+    }
+
+    // Scripts get the bare command line in a file; people get it on screen
+    match &options.write_command {
+        Some(path) => {
+            render::renderer().connection_info(&info);
+            let line = format!("{}\n", ssh_cmd);
+            // stdout was handed to the UI at startup, so reach the original directly
+            let written = if STDOUT_PATHS.iter().any(|stdout| path == Path::new(stdout)) {
+                term_utils::write_data(&line).map_err(anyhow::Error::from)
+            } else {
+                storage::write_file(path, &line)
+            };
+            written.map_err(|e| match e.downcast::<io::Error>() {
+                Ok(e) => AppError::WriteCommand(path.display().to_string(), e.to_string()).into(),
+                Err(e) => e,
+            })?;
+            render::renderer().success(&format!("SSH command written to {}", path.display()));
+        }
+        None => {
+            info.command = Some(ssh_cmd);
+            render::renderer().connection_info(&info);
+        }
+    }
+    Ok(())
+}
+
+/// Fetches the VM's SSH host key fingerprints so the first-connection prompt can be verified
+///
+/// Guest attributes are tried first; images that don't publish them often print
+/// the keys to the serial console instead.
+///
+/// # Arguments
+/// * `instance` - The selected VM instance
+///
+/// # Returns
+/// * `Result<Vec<(String, String)>>` - Pairs of key type and `SHA256:` fingerprint
+fn fetch_host_keys(instance: &Instance) -> Result<Vec<(String, String)>> {
+    let zone = instance.gcloud_zone()?;
+
+    let mut command = gcloud::command();
+    command.args([
+        "compute",
+        "instances",
+        "get-guest-attributes",
+        &instance.name,
+        "--zone",
+        &zone,
+        "--query-path=hostkeys/",
+        "--format=json",
+    ]);
+    let output = gcloud::capture(&mut command)?;
+    if output.status.success() {
+        let attributes: Vec<serde_json::Value> =
+            serde_json::from_slice(&output.stdout).unwrap_or_default();
+        let keys: Vec<(String, String)> = attributes
+            .iter()
+            .filter_map(|attribute| {
+                let key_type = attribute["key"].as_str()?;
+                let fingerprint = logging::fingerprint(attribute["value"].as_str()?.trim())?;
+                Some((key_type.to_string(), fingerprint))
+            })
+            .collect();
+        if !keys.is_empty() {
+            return Ok(keys);
+        }
+    }
+
+    let mut command = gcloud::command();
+    command.args([
+        "compute",
+        "instances",
+        "get-serial-port-output",
+        &instance.name,
+        "--zone",
+        &zone,
+    ]);
+    let output = gcloud::capture(&mut command)?;
+    if !output.status.success() {
+        bail!("{}", gcloud::error_message(&output.stderr));
+    }
+    Ok(parse_serial_host_keys(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+/// Extracts host key fingerprints from the block some images print to the serial console
+///
+/// # Arguments
+/// * `serial_output` - The serial port output
+///
+/// # Returns
+/// * `Vec<(String, String)>` - Pairs of key type and `SHA256:` fingerprint
+fn parse_serial_host_keys(serial_output: &str) -> Vec<(String, String)> {
+    serial_output
+        .lines()
+        .skip_while(|line| !line.contains("-----BEGIN SSH HOST KEY KEYS-----"))
+        .skip(1)
+        .take_while(|line| !line.contains("-----END SSH HOST KEY KEYS-----"))
+        .filter_map(|line| {
+            // Console lines may carry a timestamp prefix before the key type
+            let mut tokens = line
+                .split_whitespace()
+                .skip_while(|token| !logging::KEY_TYPES.contains(token));
+            let key_type = tokens.next()?;
+            let fingerprint = logging::fingerprint(tokens.next()?)?;
+            Some((key_type.to_string(), fingerprint))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test host key extraction from serial console output
+    #[test]
+    fn test_parse_serial_host_keys() {
+        let serial = "boot...\n-----BEGIN SSH HOST KEY KEYS-----\n[  5.1] ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIA== root@vm\n-----END SSH HOST KEY KEYS-----\nssh-rsa AAAA\n";
+        let keys = parse_serial_host_keys(serial);
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].0, "ssh-ed25519");
+        assert!(keys[0].1.starts_with("SHA256:"));
+    }
+
+    fn instance_in_zone(zone_url: &str) -> Instance {
+        Instance {
+            name: "test-vm".to_string(),
+            zone_url: zone_url.to_string(),
+            network_interfaces: Vec::new(),
+            status: "RUNNING".to_string(),
+            labels: BTreeMap::new(),
+            machine_type_url: String::new(),
+            disks: Vec::new(),
+            user: None,
+            source: InstanceSource::Gcloud,
+            access_denied: false,
+            nic: None,
+            prefer_static_ip: false,
+            project: None,
+        }
+    }
+
+    /// Test SSH directory precedence: --ssh-dir, HOME, then USERPROFILE
+    #[test]
+    fn test_resolve_ssh_dir() {
+        let explicit = Path::new("/keys");
+        assert_eq!(
+            resolve_ssh_dir(Some(explicit), Some("/home/a".into()), None),
+            Some(PathBuf::from("/keys"))
+        );
+        assert_eq!(
+            resolve_ssh_dir(None, Some("/home/a".into()), Some("C:/Users/a".into())),
+            Some(PathBuf::from("/home/a/.ssh"))
+        );
+        assert_eq!(
+            resolve_ssh_dir(None, Some("".into()), Some("/profile".into())),
+            Some(PathBuf::from("/profile/.ssh"))
+        );
+    }
+
+    /// Test that the remote marker decides whether the key was added
+    #[test]
+    fn test_key_copy_outcome() {
+        assert_eq!(
+            KeyCopyOutcome::from_remote_output("Welcome!\nPRESENT\n"),
+            KeyCopyOutcome::AlreadyPresent
+        );
+        assert_eq!(
+            KeyCopyOutcome::from_remote_output("ADDED\n"),
+            KeyCopyOutcome::Added
+        );
+        assert_eq!(
+            key_body("ssh-ed25519 AAAAC3Nza gcloud-ssh@laptop"),
+            "ssh-ed25519 AAAAC3Nza"
+        );
+    }
+
+    /// Test that a quote in the key comment stays inside the appended line
+    #[test]
+    fn test_echo_append_command_quoting() {
+        let key = "ssh-ed25519 AAAAC3Nza o'brien@laptop'; touch /tmp/pwned; echo '";
+        let command = echo_append_command(key);
+        assert!(command.contains("echo 'ssh-ed25519 AAAAC3Nza o'\\''brien@laptop'\\''; touch"));
+    }
+
+    /// Test that a POSIX shell reads a quoted key back as one word, exactly as given
+    #[cfg(unix)]
+    #[test]
+    fn test_shell_quote_round_trip() {
+        let key = "ssh-ed25519 AAAAC3Nza o'brien@laptop'; touch /tmp/pwned; echo '";
+        let output = std::process::Command::new("sh")
+            .args(["-c", &format!("printf %s {}", shell_quote(key))])
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), key);
+    }
+
+    /// Test that IPv6 access configs are parsed and bracketed in SSH destinations
+    #[test]
+    fn test_ipv6_address() {
+        let json = r#"{
+            "name": "v6-vm",
+            "zone": "zones/us-central1-a",
+            "networkInterfaces": [{
+                "accessConfigs": [{ "natIP": "34.1.2.3" }],
+                "ipv6AccessConfigs": [{ "externalIpv6": "2600:1900:4000::1" }]
+            }]
+        }"#;
+        let instance: Instance = serde_json::from_str(json).unwrap();
+        assert_eq!(instance.ipv6().as_deref(), Some("2600:1900:4000::1"));
+        assert_eq!(instance.ssh_address(false).as_deref(), Some("34.1.2.3"));
+        assert_eq!(
+            instance.ssh_address(true).as_deref(),
+            Some("[2600:1900:4000::1]")
+        );
+
+        // IPv6-only VMs omit accessConfigs entirely
+        let json = r#"{
+            "name": "v6-only",
+            "zone": "zones/us-central1-a",
+            "networkInterfaces": [{ "ipv6AccessConfigs": [{ "externalIpv6": "2600::2" }] }]
+        }"#;
+        let instance: Instance = serde_json::from_str(json).unwrap();
+        assert_eq!(instance.ssh_address(false).as_deref(), Some("[2600::2]"));
+    }
+
+    /// Test that production projects are matched against the whole project ID
+    #[test]
+    fn test_is_production() {
+        assert!(is_production("acme-prod", ".*-prod.*").unwrap());
+        assert!(is_production("acme-prod-eu", ".*-prod.*").unwrap());
+        assert!(!is_production("acme-production-like", "acme-prod").unwrap());
+        assert!(!is_production("acme-dev", ".*-prod.*").unwrap());
+        assert!(!is_production("acme-prod", "").unwrap());
+        assert!(is_production("acme-prod", "(").is_err());
+    }
+
+    /// Test that a comment is added only to keys without one
+    #[test]
+    fn test_with_key_comment() {
+        let bare = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIA==";
+        assert_eq!(
+            with_key_comment(bare, "gcloud-ssh@laptop-2024-05-01"),
+            format!("{} gcloud-ssh@laptop-2024-05-01", bare)
+        );
+        let commented = format!("{} alice@laptop", bare);
+        assert_eq!(with_key_comment(&commented, "gcloud-ssh@x"), commented);
+        assert_eq!(with_key_comment(bare, ""), bare);
+        assert_eq!(
+            with_key_comment(bare, "my laptop"),
+            format!("{} my-laptop", bare)
+        );
+
+        assert_eq!(utc_date(0), "1970-01-01");
+        assert_eq!(utc_date(1_700_000_000), "2023-11-14");
+        assert_eq!(utc_date(951_782_400), "2000-02-29");
+        assert_eq!(utc_timestamp(1_700_000_000), "2023-11-14T22:13:20+0000");
+    }
+
+    /// Test that an expiry turns the metadata entry into the google-ssh form
+    #[test]
+    fn test_metadata_key_entry() {
+        let key = "ssh-ed25519 AAAAC3Nza alice@laptop";
+        assert_eq!(
+            metadata_key_entry("alice", key, None),
+            format!("alice:{}", key)
+        );
+        assert_eq!(
+            metadata_key_entry("alice", key, Some("2023-11-14T22:13:20+0000")),
+            r#"alice:ssh-ed25519 AAAAC3Nza google-ssh {"expireOn":"2023-11-14T22:13:20+0000","userName":"alice"}"#
+        );
+    }
+
+    /// Test that one name in two zones is reported with both zones
+    #[test]
+    fn test_same_name_in_two_zones() {
+        let instances = vec![
+            Instance {
+                name: "web".to_string(),
+                ..instance_in_zone("zones/us-central1-a")
+            },
+            Instance {
+                name: "web".to_string(),
+                ..instance_in_zone("zones/europe-west1-b")
+            },
+            Instance {
+                name: "web-canary".to_string(),
+                ..instance_in_zone("zones/us-central1-a")
+            },
+        ];
+
+        let matches = matching_instances(&instances, "web");
+        assert_eq!(matches.len(), 2);
+        assert_eq!(
+            describe_matches(&matches),
+            "web (us-central1-a), web (europe-west1-b)"
+        );
+        let error = AppError::AmbiguousVm("web".to_string(), describe_matches(&matches));
+        assert!(error.to_string().contains("--zone"));
+    }
+
+    /// Test that the summary normalizes zone, addresses and machine type
+    #[test]
+    fn test_instance_summary() {
+        let json = r#"{
+            "name": "web-1",
+            "zone": "https://www.googleapis.com/compute/v1/projects/p/zones/us-central1-a",
+            "machineType": "https://www.googleapis.com/compute/v1/projects/p/zones/us-central1-a/machineTypes/e2-medium",
+            "status": "RUNNING",
+            "labels": { "env": "prod" },
+            "networkInterfaces": [{
+                "networkIP": "10.128.0.2",
+                "accessConfigs": [{ "natIP": "34.1.2.3" }]
+            }]
+        }"#;
+        let instance: Instance = serde_json::from_str(json).unwrap();
+        let summary = instance.summary();
+        assert_eq!(summary.zone.as_deref(), Some("us-central1-a"));
+        assert_eq!(summary.machine_type, "e2-medium");
+        assert_eq!(summary.external_ip.as_deref(), Some("34.1.2.3"));
+        assert_eq!(summary.internal_ip.as_deref(), Some("10.128.0.2"));
+        assert_eq!(summary.labels["env"], "prod");
+
+        let value = serde_json::to_value(&summary).unwrap();
+        assert_eq!(value["machine_type"], "e2-medium");
+        assert_eq!(value["external_ipv6"], serde_json::Value::Null);
+
+        let host = Instance::from_host_entry(hosts::HostEntry {
+            name: "bastion".to_string(),
+            host: "203.0.113.7".to_string(),
+            user: None,
+        });
+        assert_eq!(host.summary().zone, None);
+        assert!(host.is_static());
+        assert!(host.gcloud_zone().is_err());
+
+        // The source decides, not the zone's name
+        assert!(!instance_in_zone("zones/static").is_static());
+    }
+
+    /// Test that warnings printed around the JSON array are skipped
+    #[test]
+    fn test_extract_json_array_with_noise() {
+        let raw = "WARNING: [compute] some notice\n[\n  {\"name\": \"a]b\", \"zone\": \"zones/z\", \"networkInterfaces\": []}\n]\ntrailing";
+        let json = extract_json(raw).unwrap();
+        let instances: Vec<Instance> = serde_json::from_str(json).unwrap();
+        assert_eq!(instances[0].name, "a]b");
+
+        // Truncated output has no complete array
+        assert_eq!(extract_json("[\n  {\"name\": \"a\""), None);
+    }
+
+    /// Test that bare-array, single-object and items-wrapped listings all parse
+    #[test]
+    fn test_parse_instances_shapes() {
+        let vm = r#"{"name": "web-1", "zone": "zones/z", "networkInterfaces": []}"#;
+        let names = |json: &str| -> Vec<String> {
+            parse_instances(extract_json(json).unwrap())
+                .unwrap()
+                .into_iter()
+                .map(|instance| instance.name)
+                .collect()
+        };
+
+        assert_eq!(names(&format!("[{}, {}]", vm, vm)), ["web-1", "web-1"]);
+        assert_eq!(names(vm), ["web-1"]);
+        assert_eq!(
+            names(&format!("WARNING: notice\n{{\"items\": [{}]}}", vm)),
+            ["web-1"]
+        );
+        assert!(names(r#"{"items": []}"#).is_empty());
+        assert!(parse_instances(r#"{"items": {"name": 1}}"#).is_err());
+    }
+
+    /// Test that external addresses are found on later interfaces and --nic selects one
+    #[test]
+    fn test_multi_nic_addresses() {
+        let json = r#"{
+            "name": "appliance",
+            "zone": "zones/us-central1-a",
+            "networkInterfaces": [
+                { "name": "nic0" },
+                { "name": "nic1", "accessConfigs": [{ "natIP": "34.1.1.1" }] },
+                { "name": "nic2", "accessConfigs": [{ "natIP": "34.2.2.2" }] }
+            ]
+        }"#;
+        let instance: Instance = serde_json::from_str(json).unwrap();
+        assert_eq!(instance.external_ip().as_deref(), Some("34.1.1.1"));
+
+        let nic2 = instance.clone().with_nic(Some(2)).unwrap();
+        assert_eq!(nic2.external_ip().as_deref(), Some("34.2.2.2"));
+        let nic0 = instance.clone().with_nic(Some(0)).unwrap();
+        assert_eq!(nic0.external_ip(), None);
+        assert!(instance.with_nic(Some(3)).is_err());
+    }
+
+    /// Test --vm lookups: exact names win, otherwise case-insensitive substrings
+    #[test]
+    fn test_matching_instances() {
+        let instances: Vec<Instance> = ["web-1", "web-10", "db-primary"]
+            .iter()
+            .map(|name| Instance {
+                name: name.to_string(),
+                ..instance_in_zone("zones/us-central1-a")
+            })
+            .collect();
+        let names = |pattern: &str| -> Vec<String> {
+            matching_instances(&instances, pattern)
+                .iter()
+                .map(|instance| instance.name.clone())
+                .collect()
+        };
+
+        assert_eq!(names("PRIMARY"), ["db-primary"]);
+        assert_eq!(names("web-1"), ["web-1"]);
+        assert_eq!(names("WEB"), ["web-1", "web-10"]);
+        assert!(names("cache").is_empty());
+    }
+
+    /// Test that --export-env sets the VM variables on the child process only
+    #[test]
+    fn test_export_vm_env() {
+        let instance = instance_in_zone("zones/us-central1-a");
+        let mut options = cli::Options::default();
+
+        let mut command = Command::new("ssh");
+        export_vm_env(&mut command, &instance, &options);
+        assert_eq!(command.get_envs().count(), 0);
+
+        options.export_env = true;
+        export_vm_env(&mut command, &instance, &options);
+        let envs: BTreeMap<String, String> = command
+            .get_envs()
+            .filter_map(|(key, value)| {
+                Some((key.to_str()?.to_string(), value?.to_str()?.to_string()))
+            })
+            .collect();
+        assert_eq!(envs["GCLOUD_VM_NAME"], "test-vm");
+        assert_eq!(envs["GCLOUD_VM_ZONE"], "us-central1-a");
+        assert!(!envs.contains_key("GCLOUD_VM_IP"));
+    }
+
+    /// Test the internal DNS name, including domain-scoped projects and missing parts
+    #[test]
+    fn test_internal_dns_name() {
+        assert_eq!(
+            internal_dns_name("web-1", "us-central1-a", "acme-prod").unwrap(),
+            "web-1.us-central1-a.c.acme-prod.internal"
+        );
+        assert_eq!(
+            internal_dns_name("web-1", "us-central1-a", "example.com:acme").unwrap(),
+            "web-1.us-central1-a.c.acme.example.com.internal"
+        );
+        let error = internal_dns_name("web-1", "", " ").unwrap_err();
+        assert!(error.to_string().contains("no zone or project is known"));
+    }
+
+    /// Test that --filter matches part of the name regardless of case and names itself when nothing matches
+    #[test]
+    fn test_filter_instances() {
+        let named = |name: &str| Instance {
+            name: name.to_string(),
+            ..instance_in_zone("zones/us-central1-a")
+        };
+        let instances = vec![named("web-1"), named("Web-2"), named("db-1")];
+        let mut options = cli::Options {
+            name_filter: Some("WEB".to_string()),
+            ..Default::default()
+        };
+
+        let kept = filter_instances(instances.clone(), None, &options).unwrap();
+        let names: Vec<&str> = kept.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, ["web-1", "Web-2"]);
+
+        options.name_filter = Some("cache".to_string());
+        let error = filter_instances(instances.clone(), None, &options).unwrap_err();
+        assert_eq!(error::exit_code(&error), error::EXIT_NOT_FOUND);
+        assert!(error.to_string().contains("'cache'"));
+
+        options.name_filter = None;
+        let error = filter_instances(instances, Some("europe-west1-b"), &options).unwrap_err();
+        assert_eq!(error::exit_code(&error), error::EXIT_NOT_FOUND);
+    }
+
+    /// Test that the reconnect command parses back into the same selection
+    #[test]
+    fn test_reconnect_args_round_trip() {
+        let instance = Instance {
+            name: "web-1".to_string(),
+            project: Some("acme-dev".to_string()),
+            ..instance_in_zone("zones/us-central1-a")
+        };
+        let options = cli::Options {
+            user: Some("deploy".to_string()),
+            ipv6: true,
+            copy_method: cli::CopyMethod::Metadata,
+            ..Default::default()
+        };
+        let action = cli::Action::Run("df -h /".to_string());
+
+        let args = reconnect_args(&instance, &action, &options);
+        let cli::Invocation::Run(parsed) = cli::parse(args[1..].iter().cloned()).unwrap() else {
+            panic!("reconnect arguments did not parse as a run");
+        };
+        assert_eq!(parsed.vm.as_deref(), Some("web-1"));
+        assert_eq!(parsed.zone.as_deref(), Some("us-central1-a"));
+        assert_eq!(parsed.projects, ["acme-dev"]);
+        assert_eq!(parsed.user.as_deref(), Some("deploy"));
+        assert!(parsed.ipv6);
+        assert_eq!(parsed.copy_method, cli::CopyMethod::Metadata);
+        assert_eq!(parsed.action, Some(action));
+
+        // A plain --project stays a --project, and the gcloud binary is kept
+        let single = cli::Options {
+            project: Some("acme-prod".to_string()),
+            gcloud_bin: Some(PathBuf::from("/opt/sdk/bin/gcloud")),
+            ..Default::default()
+        };
+        let args = reconnect_args(
+            &instance_in_zone("zones/us-central1-a"),
+            &cli::Action::PrintCommand,
+            &single,
+        );
+        let cli::Invocation::Run(parsed) = cli::parse(args[1..].iter().cloned()).unwrap() else {
+            panic!("reconnect arguments did not parse as a run");
+        };
+        assert_eq!(parsed.project.as_deref(), Some("acme-prod"));
+        assert!(parsed.projects.is_empty());
+        assert_eq!(parsed.gcloud_bin, single.gcloud_bin);
+
+        assert_eq!(shell_word("df -h /"), "'df -h /'");
+        assert_eq!(shell_word("us-central1-a"), "us-central1-a");
+    }
+
+    /// Test Windows detection from licenses, guest OS features and the os label
+    #[test]
+    fn test_windows_detection() {
+        let json = r#"{
+            "name": "win-1",
+            "zone": "zones/us-central1-a",
+            "networkInterfaces": [],
+            "disks": [{
+                "licenses": ["https://www.googleapis.com/compute/v1/projects/windows-cloud/global/licenses/windows-server-2022-dc"],
+                "guestOsFeatures": [{"type": "MULTI_IP_SUBNET"}]
+            }]
+        }"#;
+        let licensed: Instance = serde_json::from_str(json).unwrap();
+        assert!(licensed.is_windows());
+        assert!(!instance_in_zone("zones/us-central1-a").is_windows());
+
+        let mut labeled = instance_in_zone("zones/us-central1-a");
+        labeled
+            .labels
+            .insert("os".to_string(), "Windows-2019".to_string());
+        assert!(labeled.is_windows());
+
+        let (reset, clients) = rdp_commands(&licensed, "34.1.2.3", "admin");
+        assert_eq!(
+            reset.as_deref(),
+            Some("gcloud compute reset-windows-password win-1 --zone us-central1-a --user admin")
+        );
+        let elsewhere = Instance {
+            project: Some("win-prod".to_string()),
+            ..licensed.clone()
+        };
+        let (reset, _) = rdp_commands(&elsewhere, "34.1.2.3", "admin");
+        assert_eq!(
+            reset.as_deref(),
+            Some("gcloud --project=win-prod compute reset-windows-password win-1 --zone us-central1-a --user admin")
+        );
+        assert_eq!(
+            clients,
+            ["mstsc /v:34.1.2.3", "xfreerdp /v:34.1.2.3 /u:admin"]
+        );
+    }
+
+    /// Test that --bind adds a local bind address to the ssh command
+    #[test]
+    fn test_ssh_command_bind() {
+        let mut instance = instance_in_zone("zones/us-central1-a");
+        instance.user = Some("deploy".to_string());
+        instance.network_interfaces = vec![NetworkInterface {
+            access_configs: vec![AccessConfig {
+                nat_ip: Some("34.1.2.3".to_string()),
+                reserved: None,
+            }],
+            ipv6_access_configs: Vec::new(),
+            network_ip: None,
+        }];
+        let mut options = cli::Options::default();
+        assert_eq!(
+            ssh_command_args(&instance, &options).unwrap(),
+            ["ssh", "deploy@34.1.2.3"]
+        );
+
+        options.bind = Some("10.0.0.5".parse().unwrap());
+        assert_eq!(
+            ssh_command_args(&instance, &options).unwrap(),
+            ["ssh", "-b", "10.0.0.5", "deploy@34.1.2.3"]
+        );
+    }
+
+    /// Test that reserved addresses are static and --prefer-static-ip picks them
+    #[test]
+    fn test_external_ip_kind() {
+        let mut instance: Instance = serde_json::from_value(serde_json::json!({
+            "name": "edge-1",
+            "zone": "https://www.googleapis.com/compute/v1/projects/p/zones/us-central1-a",
+            "status": "RUNNING",
+            "networkInterfaces": [
+                {"accessConfigs": [{"name": "External NAT", "type": "ONE_TO_ONE_NAT", "natIP": "34.1.1.1"}]},
+                {"accessConfigs": [{"name": "External NAT", "type": "ONE_TO_ONE_NAT", "natIP": "35.2.2.2"}]}
+            ]
+        }))
+        .unwrap();
+        assert_eq!(instance.external_ip_kind(), None);
+
+        instance.mark_reserved(&BTreeSet::from(["35.2.2.2".to_string()]));
+        assert_eq!(instance.external_ip().as_deref(), Some("34.1.1.1"));
+        assert_eq!(instance.external_ip_kind(), Some(IpKind::Ephemeral));
+
+        instance.prefer_static_ip = true;
+        assert_eq!(instance.external_ip().as_deref(), Some("35.2.2.2"));
+        assert_eq!(instance.external_ip_kind(), Some(IpKind::Static));
+        assert_eq!(
+            serde_json::to_value(instance.summary()).unwrap()["external_ip_kind"],
+            "static"
+        );
+
+        // Without a static address the first one is still used
+        instance.mark_reserved(&BTreeSet::new());
+        assert_eq!(instance.external_ip().as_deref(), Some("34.1.1.1"));
+    }
+
+    /// Test that a VM without an external IP gets the configured fallback route
+    #[test]
+    fn test_no_external_ip_fallback() {
+        let mut instance = instance_in_zone("zones/us-central1-a");
+        instance.user = Some("deploy".to_string());
+        instance.network_interfaces = vec![NetworkInterface {
+            access_configs: Vec::new(),
+            ipv6_access_configs: Vec::new(),
+            network_ip: Some("10.0.0.7".to_string()),
+        }];
+        let mut options = cli::Options::default();
+        assert!(connection_route(&instance, &options).is_err());
+
+        options.no_external_ip_fallback = Some(cli::NoExternalIpFallback::Iap);
+        let route = connection_route(&instance, &options).unwrap();
+        assert_eq!(route.address, "10.0.0.7");
+        assert_eq!(route.command[0], "gcloud");
+        assert!(route.command.ends_with(&[
+            "compute".to_string(),
+            "ssh".to_string(),
+            format!("deploy@{}", instance.name),
+            "--zone".to_string(),
+            "us-central1-a".to_string(),
+            "--tunnel-through-iap".to_string(),
+        ]));
+
+        options.no_external_ip_fallback = Some(cli::NoExternalIpFallback::GcloudSsh);
+        let route = connection_route(&instance, &options).unwrap();
+        assert_eq!(route.command.last().unwrap(), "us-central1-a");
+    }
+
+    /// Test that the IAP ProxyCommand hands the ssh port to a stdin tunnel in the VM's zone
+    #[test]
+    fn test_iap_proxy_command() {
+        let instance = instance_in_zone("zones/us-central1-a");
+        let command = iap_proxy_command(&instance, "us-central1-a");
+        assert!(command.starts_with("gcloud "));
+        assert!(command.ends_with(&format!(
+            "compute start-iap-tunnel {} %p --listen-on-stdin --zone us-central1-a",
+            instance.name
+        )));
+    }
+
+    /// Test the bulk gate: below the threshold it passes, above it needs --force with --yes
+    #[test]
+    fn test_confirm_bulk_threshold() {
+        let selected = vec![instance_in_zone("zones/us-central1-a"); 3];
+        let mut options = cli::Options {
+            yes: true,
+            ..Default::default()
+        };
+
+        assert!(confirm_bulk(&selected, &cli::Action::Delete, 3, &options).unwrap());
+        assert!(confirm_bulk(&selected, &cli::Action::Delete, 2, &options).is_err());
+        options.force = true;
+        assert!(confirm_bulk(&selected, &cli::Action::Delete, 2, &options).unwrap());
+    }
+
+    /// Test that failed projects are reported while the rest are merged
+    #[test]
+    fn test_merge_project_listings() {
+        let mut tagged = instance_in_zone("zones/us-central1-a");
+        tagged.project = Some("web-prod".to_string());
+        let listings = vec![
+            ("web-prod".to_string(), Ok(vec![tagged])),
+            ("empty".to_string(), Err(AppError::NoVmsFound.into())),
+            (
+                "locked".to_string(),
+                Err(AppError::VmListing("PERMISSION_DENIED\nmore".to_string()).into()),
+            ),
+        ];
+
+        let (instances, failures) = merge_project_listings(listings);
+        assert_eq!(instances.len(), 1);
+        assert_eq!(instances[0].project.as_deref(), Some("web-prod"));
+        assert_eq!(
+            failures,
+            ["Skipping project locked: Failed to list VM instances: PERMISSION_DENIED"]
+        );
+    }
+
+    /// Test that VMs from a merged listing are starred under their own project
+    #[test]
+    fn test_starred_names_by_project() {
+        let mut favorites = Favorites::default();
+        favorites.toggle("web-prod", "api");
+        favorites.toggle("scratch", "runner");
+
+        let mut api = instance_in_zone("zones/us-central1-a");
+        api.name = "api".to_string();
+        api.project = Some("web-prod".to_string());
+        let mut runner = instance_in_zone("zones/us-central1-b");
+        runner.name = "runner".to_string();
+        let mut stray = runner.clone();
+        stray.project = Some("web-prod".to_string());
+
+        let starred = starred_names(&favorites, &[api.clone(), runner.clone()], "scratch");
+        assert_eq!(
+            starred,
+            BTreeSet::from(["api".to_string(), "runner".to_string()])
+        );
+        assert!(starred_names(&favorites, &[stray], "scratch").is_empty());
+        assert!(starred_names(&favorites, &[runner], "web-prod").is_empty());
+    }
+
+    /// Test that each menu row shows the instance it selects after reordering
+    #[test]
+    fn test_menu_rows_follow_instances() {
+        let names = ["charlie", "alpha", "bravo"];
+        let instances: Vec<Instance> = names
+            .iter()
+            .map(|name| Instance {
+                name: name.to_string(),
+                ..instance_in_zone("zones/us-central1-a")
+            })
+            .collect();
+        let starred: BTreeSet<String> = ["bravo".to_string()].into();
+        let mut ordered = apply_favorites(instances, &starred, false).unwrap();
+        ordered[2].access_denied = true;
+
+        let rows = menu_rows(&ordered, &starred, &cli::Column::DEFAULT);
+        assert_eq!(rows[0].0.name, "bravo");
+        for (instance, label) in &rows {
+            assert!(term_utils::strip_ansi(label).contains(&instance.name));
+        }
+        let locked = icons::icon(Icon::Locked);
+        assert!(rows[2].1.ends_with(locked));
+        assert!(!rows[0].1.contains(locked));
+    }
+
+    /// Test that env output survives eval, including single quotes in values
+    #[test]
+    fn test_connection_env_quoting() {
+        let mut instance = instance_in_zone("zones/us-central1-a");
+        instance.user = Some("o'neil".to_string());
+        let env = connection_env(&instance, "34.1.2.3", "ssh o'neil@34.1.2.3");
+
+        assert!(env.contains("export VM_NAME='test-vm'\n"));
+        assert!(env.contains("export VM_ZONE='us-central1-a'\n"));
+        assert!(env.contains("export VM_USER='o'\\''neil'\n"));
+        assert!(env.ends_with("export SSH_CMD='ssh o'\\''neil@34.1.2.3'\n"));
+    }
+
+    /// Test the status and zone summary line
+    #[test]
+    fn test_fleet_summary() {
+        let mut stopped = instance_in_zone("zones/europe-west1-b");
+        stopped.status = "TERMINATED".to_string();
+        let mut staging = instance_in_zone("zones/us-central1-a");
+        staging.status = "STAGING".to_string();
+        let instances = vec![
+            instance_in_zone("zones/us-central1-a"),
+            instance_in_zone("zones/us-central1-a"),
+            stopped,
+            staging,
+        ];
+        assert_eq!(
+            fleet_summary(&instances),
+            "2 running, 1 stopped, 1 staging across 2 zones"
+        );
+
+        // Hosts-file entries are counted apart, not as a zone of their own
+        let host = Instance::from_host_entry(hosts::HostEntry {
+            name: "bastion".to_string(),
+            host: "203.0.113.7".to_string(),
+            user: None,
+        });
+        assert_eq!(
+            fleet_summary(&[host.clone(), host]),
+            "2 from the hosts file"
+        );
+    }
+
+    /// Test that --user beats the user label, which beats the local username
+    #[test]
+    fn test_login_user_precedence() {
+        let mut instance = instance_in_zone("us-central1-a");
+        instance
+            .labels
+            .insert("ssh-user".to_string(), "deploy".to_string());
+
+        let os_users = OsUsers::default();
+        let labelled = instance
+            .clone()
+            .with_login_user(None, "ssh-user", &os_users);
+        assert_eq!(labelled.user.as_deref(), Some("deploy"));
+
+        let explicit = instance
+            .clone()
+            .with_login_user(Some("admin"), "ssh-user", &os_users);
+        assert_eq!(explicit.user.as_deref(), Some("admin"));
+
+        let unlabelled = instance.clone().with_login_user(None, "login", &os_users);
+        assert_eq!(unlabelled.user, None);
+
+        instance.disks.push(AttachedDisk {
+            licenses: vec![
+                "https://www.googleapis.com/compute/v1/projects/debian-cloud/global/licenses/debian-12-bookworm"
+                    .to_string(),
+            ],
+            guest_os_features: Vec::new(),
+        });
+        let from_image = instance.clone().with_login_user(None, "login", &os_users);
+        assert_eq!(from_image.user.as_deref(), Some("debian"));
+        let labelled = instance.with_login_user(None, "ssh-user", &os_users);
+        assert_eq!(labelled.user.as_deref(), Some("deploy"));
+    }
+
+    /// Test zone extraction from well-formed and malformed zone URLs
+    #[test]
+    fn test_zone_parsing() {
+        let url = "https://www.googleapis.com/compute/v1/projects/p/zones/us-central1-a";
+        assert_eq!(instance_in_zone(url).zone(), "us-central1-a");
+        assert_eq!(
+            instance_in_zone(&format!("{}/", url)).zone(),
+            "us-central1-a"
+        );
+        assert_eq!(
+            instance_in_zone("zones//europe-west1-b//").zone(),
+            "europe-west1-b"
+        );
+        assert_eq!(instance_in_zone("us-east1-c").zone(), "us-east1-c");
+        assert_eq!(instance_in_zone("///").zone(), "");
+    }
+
+    /// Test that --yes alone never confirms a delete
+    #[test]
+    fn test_delete_requires_force_with_yes() {
+        let vm = instance_in_zone("us-central1-a");
+        let yes = cli::Options {
+            yes: true,
+            ..Default::default()
+        };
+        assert!(confirm_delete(&vm, &yes).is_err());
+
+        let forced = cli::Options {
+            yes: true,
+            force: true,
+            ..Default::default()
+        };
+        assert!(confirm_delete(&vm, &forced).unwrap());
+    }
+
+    /// Test that metadata keys are merged without duplicating entries
+    #[test]
+    fn test_merge_ssh_keys() {
+        let existing = "alice:ssh-ed25519 AAAA alice\n\nbob:ssh-rsa BBBB bob\n";
+        let merged = merge_ssh_keys(existing, "me:ssh-ed25519 CCCC me").unwrap();
+        assert_eq!(
+            merged,
+            "alice:ssh-ed25519 AAAA alice\nbob:ssh-rsa BBBB bob\nme:ssh-ed25519 CCCC me"
+        );
+        assert_eq!(merge_ssh_keys(&merged, "bob:ssh-rsa BBBB bob"), None);
+        assert_eq!(merge_ssh_keys("", "me:key").unwrap(), "me:key");
+    }
+
+    /// Test that an empty zone is rejected before reaching gcloud
+    #[test]
+    fn test_gcloud_zone_rejects_empty() {
+        assert!(instance_in_zone("").gcloud_zone().is_err());
+        assert_eq!(
+            instance_in_zone("zones/asia-east1-a/")
+                .gcloud_zone()
+                .unwrap(),
+            "asia-east1-a"
+        );
+    }
+
+    /// Test that the configured filter applies unless --all or --vm is given
+    #[test]
+    fn test_listing_query_resolution() {
+        let mut config = UserConfig::default();
+        config.listing.limit = Some(10);
+
+        let options = cli::Options::default();
+        let query = ListingQuery::resolve(&options, &config);
+        assert_eq!(query.filter.as_deref(), Some("status=RUNNING"));
+        assert_eq!(query.args(), vec!["--filter=status=RUNNING", "--limit=10"]);
+
+        let all = cli::Options {
+            all: true,
+            limit: Some(3),
+            ..Default::default()
+        };
+        let query = ListingQuery::resolve(&all, &config);
+        assert_eq!(query.args(), vec!["--limit=3"]);
+
+        let named = cli::Options {
+            vm: Some("stopped-vm".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            ListingQuery::resolve(&named, &config),
+            ListingQuery::default()
+        );
+    }
+
+    /// Test that reserved addresses are looked up only when static IPs matter
+    #[test]
+    fn test_listing_query_static_ips() {
+        let config = UserConfig::default();
+        let options = cli::Options::default();
+        assert!(!ListingQuery::resolve(&options, &config).static_ips);
+        assert!(
+            ListingQuery::resolve(&options, &config)
+                .marking_static_ips(true)
+                .static_ips
+        );
+
+        let prefer = cli::Options {
+            prefer_static_ip: true,
+            vm: Some("web-1".to_string()),
+            ..Default::default()
+        };
+        assert!(
+            ListingQuery::resolve(&prefer, &config)
+                .marking_static_ips(false)
+                .static_ips
+        );
+    }
 }
@@ -0,0 +1,225 @@
+/// This module is the single access point for the most-seen interface text
+/// (welcome line, section headers, common notices), so it can be replaced
+/// from the `[messages]` table of the configuration file, for example with a
+/// translation. English is the built-in default.
+use std::{collections::BTreeMap, sync::RwLock};
+
+/// Text replacements loaded from the configuration file
+static OVERRIDES: RwLock<Vec<(Message, &'static str)>> = RwLock::new(Vec::new());
+
+/// A piece of interface text that can be replaced
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+    /// Line typed under the banner; `{app}` is replaced with the application title
+    Welcome,
+    /// Tagline inside the banner
+    Tagline,
+    /// Subtitle inside the banner
+    Subtitle,
+    /// Prefix of the message printed when the run fails
+    ErrorPrefix,
+    /// Section shown while the SSH key is checked or generated
+    KeyManagementSection,
+    /// Section shown while the VMs are listed
+    InstancesSection,
+    /// Section shown while a VM is chosen
+    SelectionSection,
+    /// Section shown while a host from a hosts file is chosen
+    HostSelectionSection,
+    /// Section shown while a hosts file is read
+    HostsFileSection,
+    /// Section shown while the key is copied to the VM
+    KeyDeploymentSection,
+    /// Section holding the SSH command
+    ConnectionSection,
+    /// Section shown while a session is opened
+    ConnectingSection,
+    /// Section shown while a remote command runs
+    RemoteCommandSection,
+    /// Section shown while the serial console is attached
+    SerialConsoleSection,
+    /// Section shown while an IAP tunnel is open
+    TunnelSection,
+    /// Section shown while an ssh config entry is written
+    SshConfigSection,
+    /// Section shown while a VM is deleted
+    DeletingSection,
+    /// Line above the SSH command box
+    ConnectPrompt,
+    /// Notice that stopping the VM was cancelled
+    StopCancelled,
+    /// Notice that deleting the VM was cancelled
+    DeleteCancelled,
+    /// Label shown for the whole run under `--read-only`
+    ReadOnlyNotice,
+}
+
+impl Message {
+    /// Every replaceable message
+    pub const ALL: [Message; 21] = [
+        Message::Welcome,
+        Message::Tagline,
+        Message::Subtitle,
+        Message::ErrorPrefix,
+        Message::KeyManagementSection,
+        Message::InstancesSection,
+        Message::SelectionSection,
+        Message::HostSelectionSection,
+        Message::HostsFileSection,
+        Message::KeyDeploymentSection,
+        Message::ConnectionSection,
+        Message::ConnectingSection,
+        Message::RemoteCommandSection,
+        Message::SerialConsoleSection,
+        Message::TunnelSection,
+        Message::SshConfigSection,
+        Message::DeletingSection,
+        Message::ConnectPrompt,
+        Message::StopCancelled,
+        Message::DeleteCancelled,
+        Message::ReadOnlyNotice,
+    ];
+
+    /// The key naming this message in the `[messages]` configuration table
+    pub fn key(self) -> &'static str {
+        match self {
+            Message::Welcome => "welcome",
+            Message::Tagline => "tagline",
+            Message::Subtitle => "subtitle",
+            Message::ErrorPrefix => "error_prefix",
+            Message::KeyManagementSection => "section_key_management",
+            Message::InstancesSection => "section_instances",
+            Message::SelectionSection => "section_selection",
+            Message::HostSelectionSection => "section_host_selection",
+            Message::HostsFileSection => "section_hosts_file",
+            Message::KeyDeploymentSection => "section_key_deployment",
+            Message::ConnectionSection => "section_connection",
+            Message::ConnectingSection => "section_connecting",
+            Message::RemoteCommandSection => "section_remote_command",
+            Message::SerialConsoleSection => "section_serial_console",
+            Message::TunnelSection => "section_tunnel",
+            Message::SshConfigSection => "section_ssh_config",
+            Message::DeletingSection => "section_deleting",
+            Message::ConnectPrompt => "connect_prompt",
+            Message::StopCancelled => "stop_cancelled",
+            Message::DeleteCancelled => "delete_cancelled",
+            Message::ReadOnlyNotice => "read_only_notice",
+        }
+    }
+
+    /// The built-in English text
+    pub fn english(self) -> &'static str {
+        match self {
+            Message::Welcome => "Welcome to {app}! Let's set up your SSH access.",
+            Message::Tagline => "Secure • Fast • Simple",
+            Message::Subtitle => "Rust-powered CLI tool",
+            Message::ErrorPrefix => "Error",
+            Message::KeyManagementSection => "SSH KEY MANAGEMENT",
+            Message::InstancesSection => "VM INSTANCES",
+            Message::SelectionSection => "VM SELECTION",
+            Message::HostSelectionSection => "HOST SELECTION",
+            Message::HostsFileSection => "HOSTS FILE",
+            Message::KeyDeploymentSection => "SSH KEY DEPLOYMENT",
+            Message::ConnectionSection => "CONNECTION INFORMATION",
+            Message::ConnectingSection => "CONNECTING",
+            Message::RemoteCommandSection => "REMOTE COMMAND",
+            Message::SerialConsoleSection => "SERIAL CONSOLE",
+            Message::TunnelSection => "IAP TUNNEL",
+            Message::SshConfigSection => "SSH CONFIG",
+            Message::DeletingSection => "DELETING VM",
+            Message::ConnectPrompt => "To connect to your VM, run:",
+            Message::StopCancelled => "Stop cancelled.",
+            Message::DeleteCancelled => "Delete cancelled; the VM was not deleted.",
+            Message::ReadOnlyNotice => {
+                "READ-ONLY: VMs are listed and commands shown; nothing is created or changed"
+            }
+        }
+    }
+}
+
+/// Returns the text for a message, preferring the configured replacement
+///
+/// # Arguments
+/// * `message` - The message to show
+///
+/// # Returns
+/// * `&'static str` - The configured text, or the English default
+pub fn text(message: Message) -> &'static str {
+    configured(message).unwrap_or_else(|| message.english())
+}
+
+/// Returns the text configured for a message in the `[messages]` table, if any
+fn configured(message: Message) -> Option<&'static str> {
+    let overrides = OVERRIDES.read().ok()?;
+    overrides
+        .iter()
+        .find(|(key, _)| *key == message)
+        .map(|(_, text)| *text)
+}
+
+/// Replaces the built-in English text with the `[messages]` table from the configuration file
+///
+/// Unknown keys and values that are empty or contain control characters are
+/// skipped, so those messages keep their English text.
+///
+/// # Arguments
+/// * `table` - Message keys (e.g. `welcome`) mapped to the text to show
+///
+/// # Returns
+/// * `Vec<String>` - A description of every entry that was ignored
+pub fn install_overrides(table: &BTreeMap<String, String>) -> Vec<String> {
+    let mut overrides = Vec::new();
+    let mut problems = Vec::new();
+
+    for (key, value) in table {
+        let Some(message) = Message::ALL
+            .into_iter()
+            .find(|message| message.key() == key)
+        else {
+            problems.push(format!("unknown message '{}'", key));
+            continue;
+        };
+
+        let value = value.trim();
+        if value.is_empty() || value.chars().any(char::is_control) {
+            problems.push(format!("message '{}' must be visible text", key));
+        } else {
+            // Loaded once at startup, so leaking keeps `text()` allocation-free
+            overrides.push((message, &*Box::leak(value.to_string().into_boxed_str())));
+        }
+    }
+
+    if let Ok(mut current) = OVERRIDES.write() {
+        *current = overrides;
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that configured text replaces the English default per key, skipping bad values
+    #[test]
+    fn test_install_overrides() {
+        let table: BTreeMap<String, String> = [
+            ("stop_cancelled", "Arrêt annulé."),
+            ("delete_cancelled", " "),
+            ("greeting", "Hallo"),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+        let problems = install_overrides(&table);
+
+        assert_eq!(text(Message::StopCancelled), "Arrêt annulé.");
+        assert_eq!(
+            text(Message::DeleteCancelled),
+            Message::DeleteCancelled.english()
+        );
+        assert_eq!(problems.len(), 2);
+
+        install_overrides(&BTreeMap::new());
+        assert_eq!(text(Message::StopCancelled), "Stop cancelled.");
+    }
+}
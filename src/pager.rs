@@ -0,0 +1,135 @@
+/// This module pipes long command output through a pager (`$PAGER`, `less` or
+/// `more`) so it can be scrolled instead of running off the screen.
+use crate::logging;
+use anyhow::{Context, Result};
+use std::{
+    env,
+    io::{self, IsTerminal},
+    process::{Command, ExitStatus, Stdio},
+};
+
+/// Pagers tried in order when `$PAGER` is not set
+const FALLBACK_PAGERS: &[&str] = &["less", "more"];
+
+/// Flags given to `less` through `$LESS` when the user has not set any:
+/// quit when the output fits one screen, keep colors and leave the screen alone
+const DEFAULT_LESS_FLAGS: &str = "FRX";
+
+/// Returns the pager command to use, or `None` when output should pass through
+///
+/// Paging only happens when it was requested and stdout is a terminal, so
+/// redirected or piped output is never held back.
+///
+/// # Arguments
+/// * `requested` - Whether `--pager` is in effect
+///
+/// # Returns
+/// * `Option<Command>` - The pager, ready to be spawned
+pub fn pager(requested: bool) -> Option<Command> {
+    if !requested || !io::stdout().is_terminal() {
+        return None;
+    }
+
+    let configured = env::var("PAGER")
+        .ok()
+        .filter(|value| !value.trim().is_empty());
+    let words: Vec<String> = match configured {
+        Some(value) => value.split_whitespace().map(str::to_string).collect(),
+        None => vec![FALLBACK_PAGERS
+            .iter()
+            .find(|name| is_on_path(name))?
+            .to_string()],
+    };
+
+    let mut command = Command::new(&words[0]);
+    command.args(&words[1..]);
+    if env::var_os("LESS").is_none() {
+        command.env("LESS", DEFAULT_LESS_FLAGS);
+    }
+    Some(command)
+}
+
+/// Runs a command, sending its stdout through the pager when one is given
+///
+/// When the user quits the pager early the remaining output is discarded and
+/// the command is stopped, which counts as success rather than a broken pipe.
+///
+/// # Arguments
+/// * `command` - The command whose output to show
+/// * `pager` - The pager from [`pager`], or `None` to pass output through
+///
+/// # Returns
+/// * `Result<ExitStatus>` - The command's exit status
+pub fn run_paged(command: &mut Command, pager: Option<Command>) -> Result<ExitStatus> {
+    logging::command(command);
+    let program = command.get_program().to_string_lossy().into_owned();
+
+    let Some(mut pager) = pager else {
+        return command
+            .status()
+            .with_context(|| format!("Failed to run {}", program));
+    };
+
+    let mut child = command
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run {}", program))?;
+    let mut viewer = match pager.stdin(Stdio::piped()).spawn() {
+        Ok(viewer) => viewer,
+        Err(e) => {
+            // Show the output unpaged rather than losing it
+            logging::verbose(&format!("Pager unavailable ({}), printing directly", e));
+            let mut output = child.stdout.take().expect("stdout is piped");
+            io::copy(&mut output, &mut io::stdout().lock())?;
+            return Ok(child.wait()?);
+        }
+    };
+
+    let mut output = child.stdout.take().expect("stdout is piped");
+    let mut input = viewer.stdin.take().expect("stdin is piped");
+    let copied = io::copy(&mut output, &mut input);
+    // Close the pager's input so it sees end of file
+    drop(input);
+
+    let quit_early = match copied {
+        Ok(_) => false,
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => true,
+        Err(e) => return Err(e).context("Failed to write to the pager"),
+    };
+    if quit_early {
+        let _ = child.kill();
+    }
+
+    let status = child.wait()?;
+    viewer.wait().context("Failed to wait for the pager")?;
+    if quit_early {
+        return Ok(ExitStatus::default());
+    }
+    Ok(status)
+}
+
+/// Checks whether a program can be found in one of the `$PATH` directories
+fn is_on_path(program: &str) -> bool {
+    env::var_os("PATH")
+        .map(|paths| env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that output is only paged when requested, and that an early-quitting
+    /// pager is not treated as a failure
+    #[test]
+    fn test_run_paged() {
+        assert!(pager(false).is_none());
+
+        let mut producer = Command::new("sh");
+        producer.args(["-c", "yes | head -n 100000"]);
+        let mut early_quit = Command::new("head");
+        early_quit.args(["-n", "1"]).stdout(Stdio::null());
+        let status = run_paged(&mut producer, Some(early_quit)).unwrap();
+        assert!(status.success());
+    }
+}
@@ -0,0 +1,96 @@
+/// This module runs independent jobs (such as per-project gcloud listings) on a
+/// bounded number of threads, so large fleets don't start one gcloud process
+/// per item at once.
+use crate::logging;
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+/// Simultaneous jobs allowed when `--max-concurrency` is not given
+pub const DEFAULT_MAX_CONCURRENCY: usize = 4;
+
+/// Applies a function to every item with at most `limit` calls running at once
+///
+/// Results are returned in the order of the items, whichever finishes first.
+/// A job that panics yields `None`.
+///
+/// # Arguments
+/// * `items` - The inputs
+/// * `limit` - Maximum simultaneous calls (values below 1 are treated as 1)
+/// * `job` - The function to run for each item
+///
+/// # Returns
+/// * `Vec<Option<R>>` - One result per item, `None` where the job panicked
+pub fn bounded_map<T, R, F>(items: &[T], limit: usize, job: F) -> Vec<Option<R>>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let workers = limit.max(1).min(items.len());
+    if items.len() > workers {
+        logging::verbose(&format!(
+            "Running {} jobs, at most {} at a time (--max-concurrency)",
+            items.len(),
+            workers
+        ));
+    }
+
+    // Each worker claims the next unstarted item until none are left
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<Option<R>> = std::iter::repeat_with(|| None).take(items.len()).collect();
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let idx = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(idx) else {
+                            return done;
+                        };
+                        let result = panic::catch_unwind(AssertUnwindSafe(|| job(item)));
+                        done.push((idx, result.ok()));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            for (idx, result) in handle.join().unwrap_or_default() {
+                results[idx] = result;
+            }
+        }
+    });
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Test that results keep item order and no more than `limit` jobs overlap
+    #[test]
+    fn test_bounded_map() {
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let items: Vec<u32> = (0..12).collect();
+
+        let results = bounded_map(&items, 3, |item| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(5));
+            running.fetch_sub(1, Ordering::SeqCst);
+            item * 2
+        });
+
+        assert_eq!(
+            results,
+            items.iter().map(|item| Some(item * 2)).collect::<Vec<_>>()
+        );
+        assert!(peak.load(Ordering::SeqCst) <= 3);
+        assert!(bounded_map(&[] as &[u32], 0, |item| *item).is_empty());
+    }
+}
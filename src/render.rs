@@ -0,0 +1,556 @@
+/// This module separates what the tool shows from how it is shown: the flow
+/// hands plain data to a [`Renderer`], and the renderer selected at startup
+/// decides between styled terminal output, unstyled lines and JSON.
+use crate::{
+    banner,
+    icons::{self, Icon},
+    messages::{self, Message},
+};
+use colored::*;
+use serde::Serialize;
+use std::{collections::BTreeMap, sync::RwLock};
+
+/// The details of an instance shown by the listing and output formats
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InstanceSummary {
+    /// Name of the VM instance
+    pub name: String,
+
+    /// Zone name, or `None` for hosts from a static hosts file
+    pub zone: Option<String>,
+
+    /// External IPv4 address, if assigned
+    pub external_ip: Option<String>,
+
+    /// Whether the external IPv4 address is static or ephemeral, when known
+    pub external_ip_kind: Option<IpKind>,
+
+    /// External IPv6 address, if assigned
+    pub external_ipv6: Option<String>,
+
+    /// Internal IPv4 address, if known
+    pub internal_ip: Option<String>,
+
+    /// Lifecycle status reported by gcloud (empty for static hosts)
+    pub status: String,
+
+    /// Machine type name (e.g. "e2-medium"), empty when unknown
+    pub machine_type: String,
+
+    /// Labels attached to the VM
+    pub labels: BTreeMap<String, String>,
+}
+
+/// How an external IP address was assigned
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IpKind {
+    /// A reserved address that outlives the VM
+    Static,
+    /// An address assigned with the VM and released when it stops
+    Ephemeral,
+}
+
+/// One SSH host key fingerprint published by a VM
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HostKey {
+    /// Key type, e.g. `ssh-ed25519`
+    pub key_type: String,
+
+    /// `SHA256:` fingerprint
+    pub fingerprint: String,
+}
+
+/// Everything shown about the selected VM before connecting
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ConnectionInfo {
+    /// Name of the VM
+    pub name: String,
+
+    /// Zone name, or `None` for hosts from a static hosts file
+    pub zone: Option<String>,
+
+    /// What the address is, e.g. "External IP" or "Internal DNS"
+    #[serde(skip)]
+    pub address_label: &'static str,
+
+    /// The address ssh connects to
+    pub address: String,
+
+    /// Host key fingerprints, when `--show-host-key` asked for them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host_keys: Option<Vec<HostKey>>,
+
+    /// Why the host keys could not be fetched
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host_key_error: Option<String>,
+
+    /// The SSH command line, or `None` when it was written to a file instead
+    pub command: Option<String>,
+}
+
+/// How to reach a Windows VM over RDP, shown instead of an SSH command
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RdpInfo {
+    /// Name of the VM
+    pub name: String,
+
+    /// External address the RDP client connects to
+    pub address: String,
+
+    /// The command that sets a Windows password, or `None` for hosts-file entries
+    pub password_reset: Option<String>,
+
+    /// RDP client command lines
+    pub clients: Vec<String>,
+}
+
+/// Presents the tool's messages and results in one output style
+pub trait Renderer: Sync {
+    /// Shows a section heading
+    fn section(&self, title: &str);
+
+    /// Shows that something succeeded
+    fn success(&self, message: &str);
+
+    /// Shows an informational message
+    fn info(&self, message: &str);
+
+    /// Shows a warning
+    fn warning(&self, message: &str);
+
+    /// Shows that something failed
+    fn error(&self, message: &str);
+
+    /// Shows a list of VMs, numbered from 1
+    fn vm_list(&self, vms: &[InstanceSummary]);
+
+    /// Shows the command that connects to the VM
+    fn ssh_command(&self, command: &str);
+
+    /// Shows the selected VM's details and the command that connects to it
+    fn connection_info(&self, info: &ConnectionInfo);
+
+    /// Shows how to reach a Windows VM over RDP
+    fn rdp_info(&self, info: &RdpInfo);
+}
+
+/// Colored, emoji-decorated output with framed command boxes
+pub struct PrettyRenderer {
+    /// Print the bare command under the box so it can be copied
+    pub copyable: bool,
+}
+
+impl Renderer for PrettyRenderer {
+    fn section(&self, title: &str) {
+        println!("{}", banner::section_header(title));
+    }
+
+    fn success(&self, message: &str) {
+        println!("{}", banner::success_message(message));
+    }
+
+    fn info(&self, message: &str) {
+        println!("{}", banner::info_message(message));
+    }
+
+    fn warning(&self, message: &str) {
+        println!("{}", banner::warning_message(message));
+    }
+
+    fn error(&self, message: &str) {
+        println!("{}", banner::error_message(message));
+    }
+
+    fn vm_list(&self, vms: &[InstanceSummary]) {
+        for (index, vm) in vms.iter().enumerate() {
+            println!(
+                "{}",
+                banner::vm_list_item(
+                    index,
+                    &vm.name,
+                    vm.zone.as_deref().unwrap_or("static"),
+                    vm.external_ip.as_deref(),
+                    vm.external_ipv6.as_deref(),
+                    1,
+                    vm.external_ip_kind == Some(IpKind::Static),
+                )
+            );
+        }
+    }
+
+    fn ssh_command(&self, command: &str) {
+        println!(
+            "\n{}",
+            messages::text(Message::ConnectPrompt).green().bold()
+        );
+        if self.copyable {
+            println!("{}", banner::ssh_command_box_copyable(command));
+        } else {
+            println!("{}", banner::ssh_command_box(command));
+        }
+    }
+
+    fn connection_info(&self, info: &ConnectionInfo) {
+        println!("{}", icons::with_icon(Icon::Vm, "VM Name:".yellow()));
+        println!("   {}", info.name.bright_cyan().bold());
+
+        if let Some(zone) = &info.zone {
+            println!("{}", icons::with_icon(Icon::Zone, "Zone:".yellow()));
+            println!("   {}", zone.bright_cyan());
+        }
+
+        println!(
+            "{}",
+            icons::with_icon(Icon::IpAddress, format!("{}:", info.address_label).yellow())
+        );
+        println!("   {}", info.address.bright_cyan());
+
+        if let Some(keys) = &info.host_keys {
+            println!(
+                "{}",
+                icons::with_icon(Icon::Key, "Host key fingerprints:".yellow())
+            );
+            match &info.host_key_error {
+                Some(error) => println!(
+                    "   {}",
+                    format!("Could not fetch host keys: {}", error).bright_black()
+                ),
+                None if keys.is_empty() => println!(
+                    "   {}",
+                    "Not published by this VM (guest attributes are disabled)".bright_black()
+                ),
+                None => {
+                    for key in keys {
+                        println!("   {} {}", key.key_type, key.fingerprint.bright_cyan());
+                    }
+                }
+            }
+        }
+
+        if let Some(command) = &info.command {
+            self.ssh_command(command);
+        }
+    }
+
+    fn rdp_info(&self, info: &RdpInfo) {
+        println!("{}", icons::with_icon(Icon::Vm, "VM Name:".yellow()));
+        println!("   {}", info.name.bright_cyan().bold());
+        println!(
+            "{}",
+            icons::with_icon(Icon::IpAddress, "External IP:".yellow())
+        );
+        println!("   {}", info.address.bright_cyan());
+
+        if let Some(reset) = &info.password_reset {
+            println!(
+                "\n{}",
+                "Set a Windows password first (prints it once):"
+                    .green()
+                    .bold()
+            );
+            println!("{}", banner::ssh_command_box(reset));
+        }
+        println!("{}", "Then connect with RDP:".green().bold());
+        for client in &info.clients {
+            println!("{}", banner::ssh_command_box(client));
+        }
+    }
+}
+
+/// Unstyled `Label: value` lines for logs and scripts that read text
+pub struct PlainRenderer;
+
+impl Renderer for PlainRenderer {
+    fn section(&self, title: &str) {
+        println!("== {} ==", title);
+    }
+
+    fn success(&self, message: &str) {
+        println!("{}", message);
+    }
+
+    fn info(&self, message: &str) {
+        println!("{}", message);
+    }
+
+    fn warning(&self, message: &str) {
+        println!("Warning: {}", message);
+    }
+
+    fn error(&self, message: &str) {
+        println!("Error: {}", message);
+    }
+
+    fn vm_list(&self, vms: &[InstanceSummary]) {
+        for (index, vm) in vms.iter().enumerate() {
+            println!("{}", plain_vm_line(index, vm));
+        }
+    }
+
+    fn ssh_command(&self, command: &str) {
+        println!("SSH command: {}", command);
+    }
+
+    fn connection_info(&self, info: &ConnectionInfo) {
+        for line in plain_connection_lines(info) {
+            println!("{}", line);
+        }
+        if let Some(error) = &info.host_key_error {
+            eprintln!("Could not fetch host keys: {}", error);
+        }
+    }
+
+    fn rdp_info(&self, info: &RdpInfo) {
+        for line in plain_rdp_lines(info) {
+            println!("{}", line);
+        }
+    }
+}
+
+/// JSON documents on stdout, with messages moved to stderr so stdout stays parseable
+pub struct JsonRenderer;
+
+impl Renderer for JsonRenderer {
+    fn section(&self, title: &str) {
+        eprintln!("== {} ==", title);
+    }
+
+    fn success(&self, message: &str) {
+        eprintln!("{}", message);
+    }
+
+    fn info(&self, message: &str) {
+        eprintln!("{}", message);
+    }
+
+    fn warning(&self, message: &str) {
+        eprintln!("Warning: {}", message);
+    }
+
+    fn error(&self, message: &str) {
+        eprintln!("Error: {}", message);
+    }
+
+    fn vm_list(&self, vms: &[InstanceSummary]) {
+        print_json(&vms);
+    }
+
+    fn ssh_command(&self, command: &str) {
+        print_json(&serde_json::json!({ "command": command }));
+    }
+
+    fn connection_info(&self, info: &ConnectionInfo) {
+        print_json(info);
+    }
+
+    fn rdp_info(&self, info: &RdpInfo) {
+        print_json(info);
+    }
+}
+
+/// Unstyled lines on stderr, for runs whose stdout carries their own data
+/// (such as an Ansible inventory)
+pub struct StderrRenderer;
+
+impl Renderer for StderrRenderer {
+    fn section(&self, title: &str) {
+        eprintln!("== {} ==", title);
+    }
+
+    fn success(&self, message: &str) {
+        eprintln!("{}", message);
+    }
+
+    fn info(&self, message: &str) {
+        eprintln!("{}", message);
+    }
+
+    fn warning(&self, message: &str) {
+        eprintln!("Warning: {}", message);
+    }
+
+    fn error(&self, message: &str) {
+        eprintln!("Error: {}", message);
+    }
+
+    fn vm_list(&self, vms: &[InstanceSummary]) {
+        for (index, vm) in vms.iter().enumerate() {
+            eprintln!("{}", plain_vm_line(index, vm));
+        }
+    }
+
+    fn ssh_command(&self, command: &str) {
+        eprintln!("SSH command: {}", command);
+    }
+
+    fn connection_info(&self, info: &ConnectionInfo) {
+        for line in plain_connection_lines(info) {
+            eprintln!("{}", line);
+        }
+    }
+
+    fn rdp_info(&self, info: &RdpInfo) {
+        for line in plain_rdp_lines(info) {
+            eprintln!("{}", line);
+        }
+    }
+}
+
+/// Prints a value as pretty JSON; the data types here always serialize
+fn print_json(value: &impl Serialize) {
+    if let Ok(json) = serde_json::to_string_pretty(value) {
+        println!("{}", json);
+    }
+}
+
+/// Formats one VM as a tab-separated `index name zone address` line
+fn plain_vm_line(index: usize, vm: &InstanceSummary) -> String {
+    format!(
+        "{}\t{}\t{}\t{}",
+        index + 1,
+        vm.name,
+        vm.zone.as_deref().unwrap_or("static"),
+        vm.external_ip
+            .as_deref()
+            .or(vm.external_ipv6.as_deref())
+            .unwrap_or("-")
+    )
+}
+
+/// Formats the connection details as `Label: value` lines
+fn plain_connection_lines(info: &ConnectionInfo) -> Vec<String> {
+    let mut lines = vec![format!("VM Name: {}", info.name)];
+    if let Some(zone) = &info.zone {
+        lines.push(format!("Zone: {}", zone));
+    }
+    lines.push(format!("{}: {}", info.address_label, info.address));
+    for key in info.host_keys.iter().flatten() {
+        lines.push(format!("Host key: {} {}", key.key_type, key.fingerprint));
+    }
+    if let Some(command) = &info.command {
+        lines.push(format!("SSH command: {}", command));
+    }
+    lines
+}
+
+/// Formats the RDP details as `Label: value` lines
+fn plain_rdp_lines(info: &RdpInfo) -> Vec<String> {
+    let mut lines = vec![
+        format!("VM Name: {}", info.name),
+        format!("External IP: {}", info.address),
+    ];
+    if let Some(reset) = &info.password_reset {
+        lines.push(format!("Password reset: {}", reset));
+    }
+    for client in &info.clients {
+        lines.push(format!("RDP command: {}", client));
+    }
+    lines
+}
+
+/// The output styles a renderer can be selected for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Style {
+    /// Colored output with command boxes
+    #[default]
+    Pretty,
+    /// Like `Pretty`, with the bare command repeated under each box
+    PrettyCopyable,
+    /// Unstyled lines
+    Plain,
+    /// JSON documents
+    Json,
+    /// Unstyled lines on stderr
+    Stderr,
+}
+
+/// The style chosen at startup
+static CURRENT: RwLock<Style> = RwLock::new(Style::Pretty);
+
+static PRETTY: PrettyRenderer = PrettyRenderer { copyable: false };
+static PRETTY_COPYABLE: PrettyRenderer = PrettyRenderer { copyable: true };
+static PLAIN: PlainRenderer = PlainRenderer;
+static JSON: JsonRenderer = JsonRenderer;
+static STDERR: StderrRenderer = StderrRenderer;
+
+/// Selects the output style for the rest of the run
+///
+/// # Arguments
+/// * `style` - The style to use
+pub fn install(style: Style) {
+    if let Ok(mut current) = CURRENT.write() {
+        *current = style;
+    }
+}
+
+/// Returns the renderer for the style selected at startup
+pub fn renderer() -> &'static dyn Renderer {
+    let style = CURRENT.read().map(|style| *style).unwrap_or_default();
+    match style {
+        Style::Pretty => &PRETTY,
+        Style::PrettyCopyable => &PRETTY_COPYABLE,
+        Style::Plain => &PLAIN,
+        Style::Json => &JSON,
+        Style::Stderr => &STDERR,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that plain lines and JSON carry the same connection details
+    #[test]
+    fn test_connection_info_formats() {
+        let info = ConnectionInfo {
+            name: "web-1".to_string(),
+            zone: Some("us-central1-a".to_string()),
+            address_label: "External IP",
+            address: "34.1.2.3".to_string(),
+            host_keys: Some(vec![HostKey {
+                key_type: "ssh-ed25519".to_string(),
+                fingerprint: "SHA256:abc".to_string(),
+            }]),
+            host_key_error: None,
+            command: Some("ssh alice@34.1.2.3".to_string()),
+        };
+
+        assert_eq!(
+            plain_connection_lines(&info),
+            [
+                "VM Name: web-1",
+                "Zone: us-central1-a",
+                "External IP: 34.1.2.3",
+                "Host key: ssh-ed25519 SHA256:abc",
+                "SSH command: ssh alice@34.1.2.3",
+            ]
+        );
+
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["address"], "34.1.2.3");
+        assert_eq!(json["host_keys"][0]["fingerprint"], "SHA256:abc");
+        assert_eq!(json["command"], "ssh alice@34.1.2.3");
+        assert!(json.get("address_label").is_none());
+        assert!(json.get("host_key_error").is_none());
+    }
+    /// Test that the RDP details keep their labels and order as plain lines
+    #[test]
+    fn test_plain_rdp_lines() {
+        let info = RdpInfo {
+            name: "win-1".to_string(),
+            address: "34.1.2.3".to_string(),
+            password_reset: None,
+            clients: vec!["mstsc /v:34.1.2.3".to_string()],
+        };
+
+        assert_eq!(
+            plain_rdp_lines(&info),
+            [
+                "VM Name: win-1",
+                "External IP: 34.1.2.3",
+                "RDP command: mstsc /v:34.1.2.3",
+            ]
+        );
+    }
+}
@@ -0,0 +1,124 @@
+/// This module holds the runtime settings resolved once at startup from
+/// command-line flags, environment variables and terminal detection. Display
+/// helpers consult these instead of compile-time constants.
+use crate::{cli::Options, config, error::AppError, term_utils};
+use std::{env, sync::RwLock};
+
+/// Environment variable that disables emoji output
+pub const NO_EMOJI_ENV: &str = "GCLOUD_SSH_NO_EMOJI";
+
+/// Standard environment variable that disables colored output (https://no-color.org)
+pub const NO_COLOR_ENV: &str = "NO_COLOR";
+
+/// Environment variable set by CI systems; animations are turned off under it
+pub const CI_ENV: &str = "CI";
+
+/// Runtime settings shared by the display helpers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settings {
+    /// Whether emoji are rendered (ASCII substitutes are used otherwise)
+    pub emoji: bool,
+
+    /// Whether ANSI colors and styles are emitted
+    pub color: bool,
+
+    /// Whether verbose diagnostics are printed to stderr
+    pub verbose: bool,
+
+    /// Layout width forced with `--width`, instead of the detected terminal width
+    pub width: Option<usize>,
+
+    /// Whether `--read-only` forbids every operation that changes something
+    pub read_only: bool,
+
+    /// Whether typing effects, spinners and progress bars are played
+    pub animations: bool,
+}
+
+impl Settings {
+    /// Settings used before anything has been resolved
+    pub const DEFAULT: Settings = Settings {
+        emoji: true,
+        color: false,
+        verbose: false,
+        width: None,
+        read_only: false,
+        animations: config::animations::ENABLED,
+    };
+
+    /// Resolves settings from command-line options, the environment and the terminal
+    ///
+    /// # Arguments
+    /// * `options` - Parsed command-line options
+    ///
+    /// # Returns
+    /// * `Settings` - The resolved settings
+    pub fn resolve(options: &Options) -> Settings {
+        Settings {
+            emoji: !options.no_emoji && !env_flag(NO_EMOJI_ENV) && term_utils::rich_terminal(),
+            color: !options.no_color
+                && env::var_os(NO_COLOR_ENV).is_none_or(|value| value.is_empty())
+                && term_utils::rich_terminal(),
+            verbose: options.verbose,
+            width: options.width.filter(|width| *width > 0),
+            read_only: options.read_only,
+            animations: config::animations::ENABLED
+                && !options.no_animations
+                && env::var_os(NO_COLOR_ENV).is_none_or(|value| value.is_empty())
+                && !env_flag(CI_ENV),
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings::DEFAULT
+    }
+}
+
+static CURRENT: RwLock<Settings> = RwLock::new(Settings::DEFAULT);
+
+/// Returns a copy of the active runtime settings
+pub fn current() -> Settings {
+    CURRENT.read().map(|s| s.clone()).unwrap_or_default()
+}
+
+/// Replaces the active runtime settings
+///
+/// This is also where color output is switched on or off for the whole process,
+/// so strings styled before and after installation render the same way.
+///
+/// # Arguments
+/// * `settings` - The settings to install
+pub fn install(settings: Settings) {
+    colored::control::set_override(settings.color);
+    if let Ok(mut current) = CURRENT.write() {
+        *current = settings;
+    }
+}
+
+/// Refuses an operation that changes something while `--read-only` is in effect
+///
+/// This is the single gate for read-only mode. gcloud commands marked as
+/// mutating reach it when they are built, local programs such as `ssh` when
+/// they are created, and file writes through the `storage` helpers.
+///
+/// # Arguments
+/// * `operation` - What would be done, for the error message
+///
+/// # Returns
+/// * `Result<(), AppError>` - Success, or [`AppError::ReadOnly`] in read-only mode
+pub fn ensure_writable(operation: &str) -> Result<(), AppError> {
+    if current().read_only {
+        return Err(AppError::ReadOnly(operation.to_string()));
+    }
+    Ok(())
+}
+
+/// Returns true when an environment variable is set to anything but empty or `0`
+///
+/// # Arguments
+/// * `name` - The environment variable name
+pub fn env_flag(name: &str) -> bool {
+    env::var(name).is_ok_and(|value| !value.is_empty() && value != "0")
+}
@@ -0,0 +1,251 @@
+/// This module reads the user's OpenSSH client configuration (`~/.ssh/config`)
+/// so an existing `Host` entry for a VM can be reused instead of building the
+/// ssh command line from scratch, and writes `Host` entries that reach a VM
+/// through an IAP tunnel.
+use std::{fs, path::PathBuf};
+
+/// A `Host` block from an OpenSSH client configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostBlock {
+    /// The patterns on the `Host` line
+    pub patterns: Vec<String>,
+
+    /// The block's `HostName`, if it sets one
+    pub host_name: Option<String>,
+}
+
+impl HostBlock {
+    /// Returns the first pattern usable as an alias on the ssh command line
+    ///
+    /// Wildcard and negated patterns match many hosts, so they are skipped.
+    pub fn alias(&self) -> Option<&str> {
+        self.patterns
+            .iter()
+            .map(String::as_str)
+            .find(|pattern| !pattern.contains(['*', '?', '!']))
+    }
+}
+
+/// Returns the path of the user's OpenSSH client configuration
+///
+/// # Returns
+/// * `Option<PathBuf>` - `~/.ssh/config`, if a home directory could be determined
+pub fn default_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".ssh").join("config"))
+}
+
+/// Parses the `Host` blocks of an OpenSSH client configuration
+///
+/// Only `Host` and `HostName` are read. Keywords are case-insensitive and may be
+/// separated from their value by spaces or `=`; a `Match` block ends the
+/// current `Host` block.
+///
+/// # Arguments
+/// * `content` - The configuration text
+///
+/// # Returns
+/// * `Vec<HostBlock>` - The blocks in file order
+pub fn parse(content: &str) -> Vec<HostBlock> {
+    let mut blocks = Vec::new();
+    let mut current: Option<HostBlock> = None;
+
+    for line in content.lines() {
+        let Some((keyword, value)) = keyword_value(line) else {
+            continue;
+        };
+
+        match keyword.as_str() {
+            "host" => {
+                blocks.extend(current.take());
+                current = Some(HostBlock {
+                    patterns: value.split_whitespace().map(str::to_string).collect(),
+                    host_name: None,
+                });
+            }
+            "match" => blocks.extend(current.take()),
+            "hostname" => {
+                if let Some(block) = current.as_mut() {
+                    // As with every ssh option, the first value wins
+                    block.host_name.get_or_insert_with(|| value.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    blocks.extend(current);
+    blocks
+}
+
+/// Splits a configuration line into its lowercased keyword and its value
+///
+/// # Returns
+/// * `Option<(String, &str)>` - The keyword and value, or `None` for blank and comment lines
+fn keyword_value(line: &str) -> Option<(String, &str)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let (keyword, value) = line
+        .split_once(|c: char| c.is_whitespace() || c == '=')
+        .map(|(keyword, value)| {
+            (
+                keyword,
+                value.trim_start_matches(|c: char| c.is_whitespace() || c == '='),
+            )
+        })
+        .unwrap_or((line, ""));
+    Some((keyword.to_ascii_lowercase(), value.trim().trim_matches('"')))
+}
+
+/// Finds the alias of a `Host` block for a VM
+///
+/// A block matches when its `HostName` is the VM's address or one of its
+/// aliases is the VM's name; the first match in file order wins.
+///
+/// # Arguments
+/// * `blocks` - Parsed configuration blocks
+/// * `name` - The VM name
+/// * `address` - The address the VM would be reached at
+///
+/// # Returns
+/// * `Option<String>` - The alias to pass to ssh, if a block matches
+pub fn find_alias(blocks: &[HostBlock], name: &str, address: &str) -> Option<String> {
+    blocks
+        .iter()
+        .filter(|block| {
+            block.host_name.as_deref() == Some(address)
+                || block.patterns.iter().any(|pattern| pattern == name)
+        })
+        .find_map(|block| block.alias().map(str::to_string))
+}
+
+/// Looks up a VM in the user's `~/.ssh/config`
+///
+/// A missing or unreadable file simply has no matches.
+///
+/// # Arguments
+/// * `name` - The VM name
+/// * `address` - The address the VM would be reached at
+///
+/// # Returns
+/// * `Option<String>` - The alias to pass to ssh, if a block matches
+pub fn lookup(name: &str, address: &str) -> Option<String> {
+    let content = fs::read_to_string(default_path()?).ok()?;
+    find_alias(&parse(&content), name, address)
+}
+
+/// Renders a `Host` block that reaches a VM through an IAP tunnel
+///
+/// # Arguments
+/// * `alias` - The `Host` alias, normally the VM name
+/// * `user` - The user to log in as
+/// * `proxy_command` - The `start-iap-tunnel` command ssh runs to reach the VM
+///
+/// # Returns
+/// * `String` - The block, ending with a newline
+pub fn iap_host_block(alias: &str, user: &str, proxy_command: &str) -> String {
+    format!(
+        "Host {alias}\n    HostName {alias}\n    User {user}\n    ProxyCommand {proxy_command}\n"
+    )
+}
+
+/// Replaces the `Host` block for an alias, or appends the block when there is none
+///
+/// Only a block whose `Host` line names exactly this alias is replaced; comments
+/// and blank lines just above the next block stay with that block.
+///
+/// # Arguments
+/// * `content` - The current configuration text
+/// * `alias` - The alias the block is for
+/// * `block` - The new block
+///
+/// # Returns
+/// * `String` - The updated configuration text
+pub fn upsert_host_block(content: &str, alias: &str, block: &str) -> String {
+    let lines: Vec<&str> = content.lines().collect();
+    let starts_block = |line: &str| {
+        keyword_value(line).is_some_and(|(keyword, _)| keyword == "host" || keyword == "match")
+    };
+    let start = lines.iter().position(|line| {
+        keyword_value(line).is_some_and(|(keyword, value)| {
+            keyword == "host" && value.split_whitespace().eq([alias])
+        })
+    });
+
+    let Some(start) = start else {
+        let mut updated = content.trim_end().to_string();
+        if !updated.is_empty() {
+            updated.push_str("\n\n");
+        }
+        updated.push_str(block);
+        return updated;
+    };
+
+    let mut end = lines[start + 1..]
+        .iter()
+        .position(|line| starts_block(line))
+        .map_or(lines.len(), |offset| start + 1 + offset);
+    if end < lines.len() {
+        while end > start + 1 && keyword_value(lines[end - 1]).is_none() {
+            end -= 1;
+        }
+    }
+
+    let mut updated: Vec<&str> = lines[..start].to_vec();
+    updated.extend(block.lines());
+    updated.extend(&lines[end..]);
+    updated.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that blocks match by HostName or alias and wildcard patterns are never used
+    #[test]
+    fn test_find_alias() {
+        let blocks = parse(
+            "# personal hosts\n\
+             Host *\n  ServerAliveInterval 30\n\n\
+             Host web web.prod\n  HostName 34.1.2.3\n  User deploy\n\n\
+             host db-1\n  hostname=10.0.0.7\n\n\
+             Match host *.internal\n  HostName ignored\n\n\
+             Host *.gcp !bastion\n  HostName 35.9.9.9\n",
+        );
+        assert_eq!(blocks.len(), 4);
+
+        assert_eq!(
+            find_alias(&blocks, "web-1", "34.1.2.3"),
+            Some("web".to_string())
+        );
+        assert_eq!(
+            find_alias(&blocks, "db-1", "35.0.0.1"),
+            Some("db-1".to_string())
+        );
+        assert_eq!(find_alias(&blocks, "batch", "35.9.9.9"), None);
+        assert_eq!(find_alias(&blocks, "cache", "35.1.1.1"), None);
+    }
+
+    /// Test that an IAP block replaces the VM's own block and leaves the others alone
+    #[test]
+    fn test_upsert_host_block() {
+        let block = iap_host_block("web-1", "alice", "gcloud compute start-iap-tunnel web-1 %p");
+        assert_eq!(upsert_host_block("", "web-1", &block), block);
+
+        let content = "Host *\n  ServerAliveInterval 30\n";
+        let appended = upsert_host_block(content, "web-1", &block);
+        assert_eq!(appended, format!("{}\n{}", content, block));
+        assert_eq!(upsert_host_block(&appended, "web-1", &block), appended);
+
+        let content = "Host web-1\n  HostName 34.1.2.3\n\n# databases\nHost web-1 db-1\n  HostName 10.0.0.7\n";
+        let replaced = upsert_host_block(content, "web-1", &block);
+        assert_eq!(
+            replaced,
+            format!(
+                "{}\n# databases\nHost web-1 db-1\n  HostName 10.0.0.7\n",
+                block
+            )
+        );
+        assert_eq!(parse(&replaced)[0].host_name.as_deref(), Some("web-1"));
+    }
+}
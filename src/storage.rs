@@ -0,0 +1,301 @@
+/// This module locates the tool's configuration directory and reads and writes
+/// the small JSON files that persist state between runs.
+use crate::settings;
+use anyhow::{bail, Context, Result};
+use fs2::FileExt;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+/// Name of the application's directory under the config and cache roots
+pub const APP_DIR: &str = "gcloud-ssh";
+
+/// File name of the lock guarding persisted files, inside the cache directory
+pub const LOCK_FILE: &str = "gcloud-ssh.lock";
+
+/// How long to wait for another run to release the lock
+pub const LOCK_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Size above which an append-only log is rotated before the next append
+pub const DEFAULT_ROTATE_BYTES: u64 = 1024 * 1024;
+
+/// Number of rotated generations kept next to a log (`.1` is the newest)
+pub const ROTATED_GENERATIONS: usize = 2;
+
+/// Returns the configuration directory (`$XDG_CONFIG_HOME/gcloud-ssh` or `~/.config/gcloud-ssh`)
+///
+/// # Returns
+/// * `Option<PathBuf>` - The directory, if a home directory could be determined
+pub fn config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".config")))
+        .map(|root| root.join(APP_DIR))
+}
+
+/// Returns the path of a file inside the configuration directory
+///
+/// # Arguments
+/// * `file_name` - The file name
+///
+/// # Returns
+/// * `Result<PathBuf>` - The full path or an error if no home directory exists
+pub fn config_file(file_name: &str) -> Result<PathBuf> {
+    config_dir()
+        .map(|dir| dir.join(file_name))
+        .context("Could not determine the configuration directory")
+}
+
+/// Returns the cache directory (`$XDG_CACHE_HOME/gcloud-ssh` or `~/.cache/gcloud-ssh`)
+///
+/// # Returns
+/// * `Option<PathBuf>` - The directory, if a home directory could be determined
+pub fn cache_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".cache")))
+        .map(|root| root.join(APP_DIR))
+}
+
+/// Returns the path of a file inside the cache directory
+///
+/// # Arguments
+/// * `file_name` - The file name
+///
+/// # Returns
+/// * `Result<PathBuf>` - The full path or an error if no home directory exists
+pub fn cache_file(file_name: &str) -> Result<PathBuf> {
+    cache_dir()
+        .map(|dir| dir.join(file_name))
+        .context("Could not determine the cache directory")
+}
+
+/// An advisory lock serializing writes to persisted files across concurrent runs
+///
+/// The lock is an OS file lock, so it is released when the guard is dropped and
+/// also by the kernel when the process exits for any reason, including Ctrl-C.
+#[derive(Debug)]
+pub struct PersistenceLock {
+    file: File,
+}
+
+impl PersistenceLock {
+    /// Acquires the lock, waiting up to [`LOCK_TIMEOUT`] for another run to release it
+    ///
+    /// # Returns
+    /// * `Result<PersistenceLock>` - The held lock or an error naming the lock file
+    pub fn acquire() -> Result<Self> {
+        Self::acquire_at(&cache_file(LOCK_FILE)?, LOCK_TIMEOUT)
+    }
+
+    /// Acquires a lock on a specific file
+    ///
+    /// # Arguments
+    /// * `path` - The lock file, created if missing
+    /// * `timeout` - How long to keep retrying
+    ///
+    /// # Returns
+    /// * `Result<PersistenceLock>` - The held lock or an error naming the lock file
+    pub fn acquire_at(path: &Path, timeout: Duration) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let file = File::create(path)
+            .with_context(|| format!("Failed to open lock file {}", path.display()))?;
+
+        let started = Instant::now();
+        while file.try_lock_exclusive().is_err() {
+            if started.elapsed() >= timeout {
+                bail!(
+                    "Another gcloud-ssh run is saving its files (lock held on {}); try again once it finishes",
+                    path.display()
+                );
+            }
+            sleep(Duration::from_millis(50));
+        }
+
+        Ok(PersistenceLock { file })
+    }
+}
+
+impl Drop for PersistenceLock {
+    fn drop(&mut self) {
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+/// Reads a JSON file, returning the default value when it doesn't exist yet
+///
+/// # Arguments
+/// * `path` - The file to read
+///
+/// # Returns
+/// * `Result<T>` - The parsed value or error
+pub fn load_json<T: DeserializeOwned + Default>(path: &Path) -> Result<T> {
+    if !path.exists() {
+        return Ok(T::default());
+    }
+
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Writes a value as pretty JSON, creating parent directories as needed
+///
+/// The data is written to a sibling temporary file and renamed into place so an
+/// interrupted write never leaves a truncated file behind; the temporary file is
+/// removed if anything fails before the rename. The write happens
+/// under the [`PersistenceLock`] so concurrent runs can't interleave.
+///
+/// # Arguments
+/// * `path` - The file to write
+/// * `value` - The value to serialize
+///
+/// # Returns
+/// * `Result<()>` - Success or error information
+pub fn save_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    save_text(path, &serde_json::to_string_pretty(value)?)
+}
+
+/// Writes text to a file the same way [`save_json`] writes JSON
+///
+/// # Arguments
+/// * `path` - The file to write
+/// * `content` - The text to write
+///
+/// # Returns
+/// * `Result<()>` - Success or error information
+pub fn save_text(path: &Path, content: &str) -> Result<()> {
+    settings::ensure_writable(&format!("writing {}", path.display()))?;
+    let _lock = PersistenceLock::acquire()?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut tmp = tempfile::NamedTempFile::new_in(dir)
+        .with_context(|| format!("Failed to create a temporary file in {}", dir.display()))?;
+    tmp.write_all(content.as_bytes())
+        .with_context(|| format!("Failed to write {}", tmp.path().display()))?;
+    tmp.persist(path)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
+
+/// Writes a file in place, as given on the command line
+///
+/// Unlike [`save_text`] there is no lock or temporary file, so paths such as
+/// `/dev/stdout` work.
+///
+/// # Arguments
+/// * `path` - The file to write
+/// * `content` - The text to write
+///
+/// # Returns
+/// * `Result<()>` - Success, [`crate::error::AppError::ReadOnly`] in read-only
+///   mode, or the `io::Error` from writing
+pub fn write_file(path: &Path, content: &str) -> Result<()> {
+    settings::ensure_writable(&format!("writing {}", path.display()))?;
+    fs::write(path, content)?;
+    Ok(())
+}
+
+/// Appends a line to a log file, rotating the file first when it has grown too large
+///
+/// When the file exceeds `max_bytes` it is renamed to `<name>.1`, shifting older
+/// generations up to [`ROTATED_GENERATIONS`] and dropping the oldest. Rotation and
+/// append both happen under the [`PersistenceLock`], so concurrent runs never
+/// append to a file that is being renamed and no entry is lost.
+///
+/// # Arguments
+/// * `path` - The log file, created if missing
+/// * `line` - The entry to append, without a trailing newline
+/// * `max_bytes` - Size above which the file is rotated (0 disables rotation)
+///
+/// # Returns
+/// * `Result<()>` - Success or error information
+pub fn append_rotating(path: &Path, line: &str, max_bytes: u64) -> Result<()> {
+    settings::ensure_writable(&format!("appending to {}", path.display()))?;
+    let _lock = PersistenceLock::acquire()?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+
+    let size = fs::metadata(path).map(|meta| meta.len()).unwrap_or(0);
+    if max_bytes > 0 && size > max_bytes {
+        rotate(path)?;
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    writeln!(file, "{}", line).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Shifts `<name>.1` .. `<name>.N` up one generation and moves the file to `<name>.1`
+fn rotate(path: &Path) -> Result<()> {
+    let generation = |n: usize| PathBuf::from(format!("{}.{}", path.display(), n));
+
+    for n in (1..ROTATED_GENERATIONS).rev() {
+        let older = generation(n);
+        if older.exists() {
+            fs::rename(&older, generation(n + 1))
+                .with_context(|| format!("Failed to rotate {}", older.display()))?;
+        }
+    }
+    fs::rename(path, generation(1)).with_context(|| format!("Failed to rotate {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that a held lock times out a second acquirer and is released on drop
+    #[test]
+    fn test_persistence_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lock");
+        let held = PersistenceLock::acquire_at(&path, Duration::ZERO).unwrap();
+
+        let err = PersistenceLock::acquire_at(&path, Duration::from_millis(100)).unwrap_err();
+        assert!(err.to_string().contains("Another gcloud-ssh run"));
+
+        drop(held);
+        assert!(PersistenceLock::acquire_at(&path, Duration::ZERO).is_ok());
+    }
+
+    /// Test that an oversized log moves to `.1`, older generations shift, and the oldest is dropped
+    #[test]
+    fn test_rotate_generations() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.log");
+        let read = |suffix: &str| {
+            fs::read_to_string(format!("{}{}", path.display(), suffix)).unwrap_or_default()
+        };
+
+        for entry in ["first", "second", "third"] {
+            fs::write(&path, format!("{}\n", entry)).unwrap();
+            rotate(&path).unwrap();
+        }
+
+        assert!(!path.exists());
+        assert_eq!(read(".1"), "third\n");
+        assert_eq!(read(".2"), "second\n");
+        assert_eq!(read(".3"), "");
+    }
+}
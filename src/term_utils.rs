@@ -1,9 +1,12 @@
 /// This module provides utility functions for terminal operations and detection
 /// to enhance the user experience without modifying core functionality.
 use std::{
-    io::{self, Write},
+    fs::File,
+    io::{self, IsTerminal, Write},
     process::Command,
+    sync::{atomic::AtomicBool, Arc, Mutex, OnceLock},
 };
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 /// Represents terminal dimensions
 #[derive(Debug, Clone, Copy)]
@@ -14,66 +17,71 @@ pub struct TerminalSize {
     pub height: usize,
 }
 
+/// Reads the terminal size from `COLUMNS` and `LINES` values
+///
+/// `COLUMNS` must be a positive number; a missing or invalid `LINES` falls back
+/// to 24 rows.
+///
+/// # Arguments
+/// * `columns` - The value of `COLUMNS`, if set
+/// * `lines` - The value of `LINES`, if set
+///
+/// # Returns
+/// * `Option<TerminalSize>` - The size, if `COLUMNS` is usable
+pub fn size_from_env(columns: Option<&str>, lines: Option<&str>) -> Option<TerminalSize> {
+    let width = columns?.trim().parse::<usize>().ok().filter(|w| *w > 0)?;
+    let height = lines
+        .and_then(|lines| lines.trim().parse::<usize>().ok())
+        .filter(|h| *h > 0)
+        .unwrap_or(24);
+    Some(TerminalSize { width, height })
+}
+
 /// Detects the terminal size
 ///
+/// The size of the terminal on stdout is asked for directly; when stdout isn't
+/// a terminal (e.g. redirected to a file), `COLUMNS`/`LINES` are used instead.
+///
 /// # Returns
 /// * `Option<TerminalSize>` - Terminal dimensions if detection succeeds
 pub fn get_terminal_size() -> Option<TerminalSize> {
     #[cfg(unix)]
-    {
-        // Try using stty size command
-        let output = Command::new("stty").args(["size"]).output().ok()?;
-
-        if output.status.success() {
-            let output_str = String::from_utf8_lossy(&output.stdout);
-            let values: Vec<&str> = output_str.trim().split_whitespace().collect();
-
-            if values.len() == 2 {
-                let height = values[0].parse::<usize>().ok()?;
-                let width = values[1].parse::<usize>().ok()?;
-                return Some(TerminalSize { width, height });
-            }
-        }
+    if let Some(size) = size_from_ioctl() {
+        return Some(size);
     }
 
-    // Fallback to tput
-    let width = Command::new("tput")
-        .args(["cols"])
-        .output()
-        .ok()
-        .and_then(|output| {
-            if output.status.success() {
-                String::from_utf8_lossy(&output.stdout)
-                    .trim()
-                    .parse::<usize>()
-                    .ok()
-            } else {
-                None
-            }
-        });
+    let columns = std::env::var("COLUMNS").ok();
+    let lines = std::env::var("LINES").ok();
+    size_from_env(columns.as_deref(), lines.as_deref())
+}
 
-    let height = Command::new("tput")
-        .args(["lines"])
-        .output()
-        .ok()
-        .and_then(|output| {
-            if output.status.success() {
-                String::from_utf8_lossy(&output.stdout)
-                    .trim()
-                    .parse::<usize>()
-                    .ok()
-            } else {
-                None
-            }
-        });
+/// Reads the size of the terminal on stdout with the `TIOCGWINSZ` ioctl
+///
+/// # Returns
+/// * `Option<TerminalSize>` - The size, or `None` when stdout isn't a terminal
+#[cfg(unix)]
+fn size_from_ioctl() -> Option<TerminalSize> {
+    use std::os::fd::AsRawFd;
 
-    match (width, height) {
-        (Some(w), Some(h)) => Some(TerminalSize {
-            width: w,
-            height: h,
-        }),
-        _ => None,
+    let mut size = libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    // SAFETY: TIOCGWINSZ only writes a `winsize` into the struct passed to it,
+    // and the call fails harmlessly when the descriptor isn't a terminal
+    let result = unsafe { libc::ioctl(io::stdout().as_raw_fd(), libc::TIOCGWINSZ, &mut size) };
+    if result != 0 || size.ws_col == 0 {
+        return None;
     }
+    Some(TerminalSize {
+        width: usize::from(size.ws_col),
+        height: match size.ws_row {
+            0 => 24,
+            rows => usize::from(rows),
+        },
+    })
 }
 
 /// Checks if the terminal supports ANSI colors
@@ -104,6 +112,130 @@ pub fn supports_color() -> bool {
         .unwrap_or(false)
 }
 
+/// How many colors the terminal can show
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorDepth {
+    /// No color support
+    None,
+    /// The 16 basic ANSI colors
+    Basic,
+    /// The 256-color palette
+    Ansi256,
+    /// 24-bit RGB colors
+    TrueColor,
+}
+
+/// Works out the color depth from `TERM` and `COLORTERM` values
+///
+/// # Arguments
+/// * `term` - The value of `TERM`, if set
+/// * `colorterm` - The value of `COLORTERM`, if set
+///
+/// # Returns
+/// * `ColorDepth` - The advertised color depth
+pub fn color_depth(term: Option<&str>, colorterm: Option<&str>) -> ColorDepth {
+    match term {
+        None | Some("") | Some("dumb") => ColorDepth::None,
+        _ if is_truecolor(colorterm) => ColorDepth::TrueColor,
+        Some(term) if term.contains("256color") => ColorDepth::Ansi256,
+        Some(_) => ColorDepth::Basic,
+    }
+}
+
+/// Capabilities of the terminal the application is writing to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TerminalCaps {
+    /// Whether ANSI colors are supported
+    pub color: bool,
+    /// Whether 24-bit colors are supported
+    pub truecolor: bool,
+    /// How many colors are supported
+    pub depth: ColorDepth,
+    /// Width in columns (falls back to the default frame width)
+    pub width: usize,
+    /// Whether stdout is an interactive terminal
+    pub is_tty: bool,
+}
+
+impl TerminalCaps {
+    /// Probes the terminal for its capabilities
+    ///
+    /// This may spawn subprocesses, so prefer the cached [`caps()`] getter.
+    ///
+    /// # Returns
+    /// * `TerminalCaps` - The detected capabilities
+    pub fn detect() -> Self {
+        let color = supports_color();
+        let term = std::env::var("TERM").ok();
+        let colorterm = std::env::var("COLORTERM").ok();
+        let depth = match color_depth(term.as_deref(), colorterm.as_deref()) {
+            // `tput colors` may report color support without TERM being set
+            ColorDepth::None if color => ColorDepth::Basic,
+            _ if !color => ColorDepth::None,
+            depth => depth,
+        };
+        TerminalCaps {
+            color,
+            truecolor: depth == ColorDepth::TrueColor,
+            depth,
+            width: get_terminal_size()
+                .map(|size| size.width)
+                .unwrap_or(crate::config::layout::DEFAULT_FRAME_WIDTH),
+            is_tty: io::stdout().is_terminal(),
+        }
+    }
+}
+
+static CAPS: OnceLock<TerminalCaps> = OnceLock::new();
+
+/// Returns the terminal capabilities, detecting them on first use
+///
+/// # Returns
+/// * `TerminalCaps` - The cached capabilities
+pub fn caps() -> TerminalCaps {
+    *CAPS.get_or_init(TerminalCaps::detect)
+}
+
+/// Returns the width layout helpers should fit their output to
+///
+/// `--width` wins, then `config::layout::TERMINAL_WIDTH` when non-zero, then the
+/// detected terminal width.
+///
+/// # Returns
+/// * `usize` - Width in columns
+pub fn layout_width() -> usize {
+    let configured = Some(crate::config::layout::TERMINAL_WIDTH).filter(|width| *width > 0);
+    crate::settings::current()
+        .width
+        .or(configured)
+        .unwrap_or_else(|| caps().width)
+}
+
+/// Checks whether a `COLORTERM` value advertises 24-bit color support
+///
+/// # Arguments
+/// * `colorterm` - The value of the `COLORTERM` environment variable, if set
+pub fn is_truecolor(colorterm: Option<&str>) -> bool {
+    matches!(colorterm, Some("truecolor") | Some("24bit"))
+}
+
+/// Checks whether stdout is an interactive terminal able to render rich output
+///
+/// # Returns
+/// * `bool` - True if stdout is a TTY that supports colors
+pub fn rich_terminal() -> bool {
+    let caps = caps();
+    caps.is_tty && caps.color
+}
+
+/// Rings the terminal bell, unless stdout is piped or redirected
+pub fn ring_bell() {
+    if caps().is_tty {
+        print!("\x07");
+        let _ = io::stdout().flush();
+    }
+}
+
 /// Clears the terminal screen
 pub fn clear_screen() {
     if Command::new("clear").status().is_ok() {
@@ -129,7 +261,8 @@ pub fn goto_xy(row: usize, col: usize) {
 /// Returns the terminal to normal mode (resets attributes)
 pub fn reset_terminal() {
     print!("\x1B[0m");
-    io::stdout().flush().unwrap();
+    // The terminal may already be gone (SIGHUP), which is no reason to panic
+    let _ = io::stdout().flush();
 }
 
 /// Hides the cursor
@@ -141,7 +274,102 @@ pub fn hide_cursor() {
 /// Shows the cursor
 pub fn show_cursor() {
     print!("\x1B[?25h");
-    io::stdout().flush().unwrap();
+    let _ = io::stdout().flush();
+}
+
+/// Restores the terminal before exiting when the process is interrupted or terminated
+///
+/// SIGINT, SIGTERM and SIGHUP share one cleanup, run on a background thread:
+/// attributes are reset and the cursor shown, then the process exits with the
+/// shell's `128 + signal` status.
+///
+/// # Returns
+/// * `io::Result<()>` - Success, or an error if the handlers could not be registered
+#[cfg(unix)]
+pub fn install_signal_cleanup() -> io::Result<()> {
+    use signal_hook::{
+        consts::{SIGHUP, SIGINT, SIGTERM},
+        iterator::Signals,
+    };
+
+    let mut signals = Signals::new([SIGINT, SIGTERM, SIGHUP])?;
+    std::thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            reset_terminal();
+            show_cursor();
+            std::process::exit(128 + signal);
+        }
+    });
+    Ok(())
+}
+
+/// The stdout the run started with, kept by [`move_ui_to_stderr`] for data
+static DATA_OUT: Mutex<Option<File>> = Mutex::new(None);
+
+/// Points stdout at stderr so every line of UI lands there, keeping the original for data
+///
+/// Everything printed afterwards, by this process or the programs it starts,
+/// goes to stderr; [`write_data`] still reaches the original stdout. On
+/// platforms without `dup2` nothing moves.
+///
+/// # Returns
+/// * `io::Result<()>` - Success, or an error if the descriptors could not be duplicated
+#[cfg(unix)]
+pub fn move_ui_to_stderr() -> io::Result<()> {
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+
+    io::stdout().flush()?;
+    let stdout = io::stdout().as_raw_fd();
+    // SAFETY: dup and dup2 only take descriptor numbers; the duplicate is owned by the File
+    let saved = unsafe { libc::dup(stdout) };
+    if saved < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let data = unsafe { File::from_raw_fd(saved) };
+    if unsafe { libc::dup2(io::stderr().as_raw_fd(), stdout) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if let Ok(mut out) = DATA_OUT.lock() {
+        *out = Some(data);
+    }
+    Ok(())
+}
+
+/// Leaves stdout alone where descriptors can't be duplicated
+#[cfg(not(unix))]
+pub fn move_ui_to_stderr() -> io::Result<()> {
+    Ok(())
+}
+
+/// Writes data to the stdout the run started with, even after [`move_ui_to_stderr`]
+///
+/// # Arguments
+/// * `data` - The text to write
+///
+/// # Returns
+/// * `io::Result<()>` - Success or the write error
+pub fn write_data(data: &str) -> io::Result<()> {
+    let mut saved = DATA_OUT
+        .lock()
+        .map_err(|_| io::Error::other("stdout lock poisoned"))?;
+    match saved.as_mut() {
+        Some(out) => out.write_all(data.as_bytes()),
+        None => io::stdout().lock().write_all(data.as_bytes()),
+    }
+}
+
+/// Returns a flag that is raised whenever the terminal is resized
+///
+/// The caller clears the flag once it has redrawn. On platforms without
+/// `SIGWINCH` the flag is never raised.
+///
+/// # Returns
+/// * `io::Result<Arc<AtomicBool>>` - The flag, or an error if the handler could not be registered
+pub fn resize_flag() -> io::Result<Arc<AtomicBool>> {
+    let flag = Arc::new(AtomicBool::new(false));
+    #[cfg(unix)]
+    signal_hook::flag::register(signal_hook::consts::SIGWINCH, Arc::clone(&flag))?;
+    Ok(flag)
 }
 
 /// Centers text in the terminal
@@ -153,54 +381,156 @@ pub fn show_cursor() {
 /// # Returns
 /// * `String` - Centered text with appropriate padding
 pub fn center_text(text: &str, width: usize) -> String {
-    let term_width = if width > 0 {
-        width
-    } else if let Some(size) = get_terminal_size() {
-        size.width
-    } else {
-        80 // Default fallback width
+    let term_width = if width > 0 { width } else { layout_width() };
+
+    // Text wider than the terminal is wrapped and each line centered on its own;
+    // a line can still be too wide when one glyph is, so it just gets no padding
+    let pad = |line: &str| {
+        let padding = term_width.saturating_sub(display_width(line)) / 2;
+        format!("{}{}", " ".repeat(padding), line)
     };
+    if display_width(text) > term_width {
+        return wrap_text(text, term_width)
+            .iter()
+            .map(|line| pad(line))
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+
+    pad(text)
+}
+
+/// Returns the number of terminal columns a string occupies
+///
+/// Unlike `str::len()`, this accounts for multi-byte characters and
+/// double-width glyphs such as CJK text and most emoji.
+///
+/// # Arguments
+/// * `text` - Text to measure
+///
+/// # Returns
+/// * `usize` - Display width in columns
+pub fn display_width(text: &str) -> usize {
+    UnicodeWidthStr::width(text)
+}
 
-    if text.len() >= term_width {
+/// Removes ANSI escape sequences (colors and styles) from a string
+///
+/// # Arguments
+/// * `text` - Text that may contain escape sequences
+///
+/// # Returns
+/// * `String` - The text without escape sequences
+pub fn strip_ansi(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1B' {
+            // Skip the CSI sequence up to and including its final letter
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// Shortens a possibly colored line to fit within `width` columns
+///
+/// Escape sequences are kept but not counted; a cut line ends in `…` and a
+/// style reset so the color doesn't leak into the next line.
+///
+/// # Arguments
+/// * `text` - A single line that may contain escape sequences
+/// * `width` - Available width in columns
+///
+/// # Returns
+/// * `String` - The line, cut if it was too wide
+pub fn truncate_to_width(text: &str, width: usize) -> String {
+    if display_width(&strip_ansi(text)) <= width {
         return text.to_string();
     }
 
-    let padding = (term_width - text.len()) / 2;
-    format!("{}{}", " ".repeat(padding), text)
+    let mut result = String::with_capacity(text.len());
+    let mut used = 0;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1B' {
+            result.push(c);
+            for c in chars.by_ref() {
+                result.push(c);
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+            continue;
+        }
+        let char_width = UnicodeWidthChar::width(c).unwrap_or(0);
+        // Leave one column for the ellipsis
+        if used + char_width + 1 > width {
+            break;
+        }
+        used += char_width;
+        result.push(c);
+    }
+    result.push('…');
+    result.push_str("\x1B[0m");
+    result
+}
+
+/// Splits a string at the last character boundary that fits within `width` columns
+///
+/// At least one character is always placed in the head so callers make progress
+/// even when a single glyph is wider than the available space.
+fn split_at_width(text: &str, width: usize) -> (&str, &str) {
+    let mut used = 0;
+    for (idx, c) in text.char_indices() {
+        let char_width = UnicodeWidthChar::width(c).unwrap_or(0);
+        if idx > 0 && used + char_width > width {
+            return text.split_at(idx);
+        }
+        used += char_width;
+    }
+    (text, "")
 }
 
 /// Wraps text to fit within a specified width
 ///
+/// Words are separated by single spaces; words wider than a line are hard-broken
+/// over as many lines as they need, so no characters are ever dropped.
+///
 /// # Arguments
 /// * `text` - Text to wrap
-/// * `width` - Maximum width per line
+/// * `width` - Maximum width per line (at least 1 is assumed)
 ///
 /// # Returns
 /// * `Vec<String>` - Lines of text wrapped to fit the width
 pub fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
     let mut result = Vec::new();
     let mut current_line = String::new();
 
-    for word in text.split_whitespace() {
-        if current_line.len() + word.len() + 1 <= width {
-            if !current_line.is_empty() {
+    for mut word in text.split_whitespace() {
+        if !current_line.is_empty() {
+            if display_width(&current_line) + 1 + display_width(word) <= width {
                 current_line.push(' ');
+                current_line.push_str(word);
+                continue;
             }
-            current_line.push_str(word);
-        } else {
-            if !current_line.is_empty() {
-                result.push(current_line);
-                current_line = word.to_string();
-            } else {
-                // Word is too long for the width, need to break it up
-                result.push(word[..width.min(word.len())].to_string());
-                if word.len() > width {
-                    current_line = word[width.min(word.len())..].to_string();
-                } else {
-                    current_line = String::new();
-                }
-            }
+            result.push(std::mem::take(&mut current_line));
+        }
+
+        // Word is too long for the width, so break it up until the rest fits
+        while display_width(word) > width {
+            let (head, tail) = split_at_width(word, width);
+            result.push(head.to_string());
+            word = tail;
         }
+        current_line.push_str(word);
     }
 
     if !current_line.is_empty() {
@@ -209,3 +539,132 @@ pub fn wrap_text(text: &str, width: usize) -> Vec<String> {
 
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test the COLUMNS and LINES fallback used when stdout isn't a terminal
+    #[test]
+    fn test_terminal_size_from_env() {
+        let size = size_from_env(Some("123"), Some("45")).unwrap();
+        assert_eq!((size.width, size.height), (123, 45));
+
+        assert!(size_from_env(None, Some("45")).is_none());
+        assert!(size_from_env(Some("0"), Some("45")).is_none());
+        assert!(size_from_env(Some("wide"), Some("45")).is_none());
+        assert_eq!(size_from_env(Some("100"), None).unwrap().height, 24);
+    }
+
+    /// Test truecolor detection from COLORTERM values
+    #[test]
+    fn test_is_truecolor() {
+        assert!(is_truecolor(Some("truecolor")));
+        assert!(is_truecolor(Some("24bit")));
+        assert!(!is_truecolor(Some("")));
+        assert!(!is_truecolor(None));
+    }
+
+    /// Test color depth detection from TERM and COLORTERM
+    #[test]
+    fn test_color_depth() {
+        assert_eq!(color_depth(None, Some("truecolor")), ColorDepth::None);
+        assert_eq!(color_depth(Some("dumb"), None), ColorDepth::None);
+        assert_eq!(color_depth(Some("xterm"), None), ColorDepth::Basic);
+        assert_eq!(
+            color_depth(Some("xterm-256color"), None),
+            ColorDepth::Ansi256
+        );
+        assert_eq!(
+            color_depth(Some("xterm-256color"), Some("24bit")),
+            ColorDepth::TrueColor
+        );
+    }
+
+    /// Test on generated inputs that wrapping never loses characters or overflows
+    #[test]
+    fn test_wrap_text_keeps_every_word() {
+        // A small linear congruential generator keeps the inputs reproducible
+        let mut seed: u64 = 0x5eed;
+        let mut next = |bound: usize| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as usize % bound
+        };
+
+        for _ in 0..500 {
+            let words: Vec<String> = (0..next(8))
+                .map(|_| {
+                    (0..1 + next(30))
+                        .map(|_| (b'a' + next(26) as u8) as char)
+                        .collect()
+                })
+                .collect();
+            let text = words.join(&" ".repeat(1 + next(3)));
+            let width = 1 + next(12);
+
+            let lines = wrap_text(&text, width);
+            assert_eq!(
+                lines.concat().replace(' ', ""),
+                words.concat(),
+                "{:?}",
+                text
+            );
+            for line in &lines {
+                assert!(display_width(line) <= width, "{:?} at {}", line, width);
+            }
+        }
+
+        // A word exactly as wide as the line, and one several lines long
+        assert_eq!(wrap_text("abcd", 4), ["abcd"]);
+        assert_eq!(wrap_text("ab abcdefghij", 4), ["ab", "abcd", "efgh", "ij"]);
+    }
+
+    /// Test that a wide emoji line narrower than the terminal is centered by display width
+    #[test]
+    fn test_center_text_wide_chars_fit() {
+        let text = "🚀 起動";
+        let centered = center_text(text, 20);
+        // "🚀 起動" occupies 7 columns, leaving (20 - 7) / 2 = 6 columns of padding
+        assert_eq!(centered, format!("{}{}", " ".repeat(6), text));
+        assert!(display_width(&centered) <= 20);
+    }
+
+    /// Test that a wide emoji line wider than the terminal is wrapped and each line centered
+    #[test]
+    fn test_center_text_wide_chars_overflow() {
+        let text = "🚀🚀 起動中 サーバー 🌐🌐 接続 完了";
+        let centered = center_text(text, 10);
+        let lines: Vec<&str> = centered.lines().collect();
+        assert!(lines.len() > 1);
+        for line in &lines {
+            assert!(display_width(line) <= 10, "line too wide: {:?}", line);
+        }
+        assert_eq!(
+            centered.split_whitespace().collect::<String>(),
+            text.split_whitespace().collect::<String>()
+        );
+    }
+
+    /// Test that a glyph wider than the whole width is returned as-is instead of recursing
+    #[test]
+    fn test_center_text_glyph_wider_than_width() {
+        assert_eq!(center_text("🚀", 1), "🚀");
+        assert_eq!(center_text("🚀🚀", 1), "🚀\n🚀");
+    }
+
+    /// Test that truncation counts only visible columns and closes any open style
+    #[test]
+    fn test_truncate_to_width() {
+        assert_eq!(truncate_to_width("web-1  RUNNING", 20), "web-1  RUNNING");
+
+        let colored = "\x1B[32mRUNNING\x1B[0m web-1";
+        assert_eq!(truncate_to_width(colored, 13), colored);
+        let cut = truncate_to_width(colored, 5);
+        assert_eq!(strip_ansi(&cut), "RUNN…");
+        assert!(cut.ends_with("\x1B[0m"));
+
+        assert_eq!(strip_ansi(&truncate_to_width("日本語のVM", 5)), "日本…");
+    }
+}
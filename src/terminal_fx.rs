@@ -1,76 +1,366 @@
 /// This module provides terminal effects and animations
 /// to enhance the user experience without modifying core functionality.
+///
+/// Animations write through a buffered, locked stdout and flush once per frame,
+/// so slow terminals (SSH sessions, tmux) get one write per frame instead of
+/// one per character.
+///
+/// Setting `GCLOUD_SSH_TEST=1` (or calling [`set_fast_mode`]) skips every pause
+/// while drawing the same frames, so tests and benchmarks can run the UI path
+/// without waiting. It is not meant as a user-facing speed setting.
+use crate::{settings, term_utils};
 use std::{
-    io::{self, Write},
-    thread::sleep,
+    io::{self, BufWriter, StdoutLock, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
     time::Duration,
 };
 
+/// Environment variable that turns on fast mode for tests and benchmarks
+pub const FAST_MODE_ENV: &str = "GCLOUD_SSH_TEST";
+
+/// Fast mode switched on from code rather than the environment
+static FAST_MODE: AtomicBool = AtomicBool::new(false);
+
+/// Turns fast mode on or off for the whole process
+///
+/// # Arguments
+/// * `enabled` - Whether animations should skip their pauses
+pub fn set_fast_mode(enabled: bool) {
+    FAST_MODE.store(enabled, Ordering::Relaxed);
+}
+
+/// Checks whether animations skip their pauses
+pub fn fast_mode() -> bool {
+    FAST_MODE.load(Ordering::Relaxed) || settings::env_flag(FAST_MODE_ENV)
+}
+
+/// Waits between animation frames, unless fast mode is on
+fn sleep(duration: Duration) {
+    pause(duration, fast_mode());
+}
+
+/// Waits between animation frames, unless `fast` skips the wait
+fn pause(duration: Duration, fast: bool) {
+    if !fast {
+        thread::sleep(duration);
+    }
+}
+
+/// Returns a buffered handle on stdout that is locked for the whole animation
+fn animation_out() -> BufWriter<StdoutLock<'static>> {
+    BufWriter::new(io::stdout().lock())
+}
+
 /// Creates a typing effect for text, simulating someone typing
 ///
 /// # Arguments
 /// * `text` - The text to display with typing effect
 /// * `delay_ms` - Delay between characters in milliseconds
 pub fn type_text(text: &str, delay_ms: u64) {
+    let _ = type_text_to(&mut animation_out(), text, delay_ms, fast_mode());
+}
+
+/// Writes text to `out` one character at a time, each character being a frame
+///
+/// # Arguments
+/// * `out` - Where to write the text
+/// * `text` - The text to display with typing effect
+/// * `delay_ms` - Delay between characters in milliseconds
+/// * `fast` - Whether to skip the delays (see [`fast_mode`])
+pub fn type_text_to(out: &mut impl Write, text: &str, delay_ms: u64, fast: bool) -> io::Result<()> {
     for c in text.chars() {
-        print!("{}", c);
-        io::stdout().flush().unwrap();
-        sleep(Duration::from_millis(delay_ms));
+        write!(out, "{}", c)?;
+        out.flush()?;
+        pause(Duration::from_millis(delay_ms), fast);
     }
-    println!();
+    writeln!(out)?;
+    out.flush()
 }
 
 /// Displays a loading spinner with message
 ///
+/// When stdout is not a terminal the message is printed once instead of being
+/// redrawn in place.
+///
 /// # Arguments
 /// * `message` - The message to display next to the spinner
 /// * `duration_ms` - How long to show the spinner in milliseconds
 pub fn spinner(message: &str, duration_ms: u64) {
+    let _ = spinner_to(
+        &mut animation_out(),
+        term_utils::caps().is_tty,
+        message,
+        duration_ms,
+        fast_mode(),
+    );
+}
+
+/// Writes a loading spinner to `out`, redrawing with `\r` only when `interactive`
+///
+/// # Arguments
+/// * `out` - Where to write the spinner
+/// * `interactive` - Whether `out` is a terminal
+/// * `message` - The message to display next to the spinner
+/// * `duration_ms` - How long to show the spinner in milliseconds
+/// * `fast` - Whether to skip the pauses between frames (see [`fast_mode`])
+pub fn spinner_to(
+    out: &mut impl Write,
+    interactive: bool,
+    message: &str,
+    duration_ms: u64,
+    fast: bool,
+) -> io::Result<()> {
+    if !interactive {
+        writeln!(out, "{}...", message)?;
+        return out.flush();
+    }
+
     let spinner_chars = ["⠋", "⠙", "⠸", "⠴", "⠦", "⠇"];
     let interval = Duration::from_millis(80);
     let iterations = duration_ms / 80;
 
     for i in 0..iterations {
-        print!(
+        write!(
+            out,
             "\r{} {}",
             spinner_chars[i as usize % spinner_chars.len()],
             message
-        );
-        io::stdout().flush().unwrap();
-        sleep(interval);
+        )?;
+        out.flush()?;
+        pause(interval, fast);
     }
-    print!("\r");
     // Clear the line
-    print!("\r{}\r", " ".repeat(message.len() + 2));
-    io::stdout().flush().unwrap();
+    write!(
+        out,
+        "\r{}\r",
+        " ".repeat(term_utils::display_width(message) + 2)
+    )?;
+    out.flush()
+}
+
+/// A spinner redrawn on a background thread while a command runs, whose
+/// message can be changed as the command reports progress
+///
+/// When stdout is not a terminal nothing is redrawn; each new message is
+/// printed once on its own line instead.
+pub struct LiveSpinner {
+    state: Arc<Mutex<LiveState>>,
+    ticker: Option<JoinHandle<()>>,
+}
+
+/// What the spinner thread draws, shared with the owner
+struct LiveState {
+    message: String,
+    /// Width of the frame currently on screen, 0 when the line is clear
+    drawn: usize,
+    /// Set while a prompt waits for an answer on the spinner's line
+    paused: bool,
+    done: bool,
+}
+
+impl LiveSpinner {
+    /// Starts the spinner with an initial message
+    ///
+    /// # Arguments
+    /// * `message` - The message to display next to the spinner
+    pub fn start(message: &str) -> Self {
+        let state = Arc::new(Mutex::new(LiveState {
+            message: message.to_string(),
+            drawn: 0,
+            paused: false,
+            done: false,
+        }));
+
+        if !term_utils::caps().is_tty {
+            println!("{}...", message);
+            return LiveSpinner {
+                state,
+                ticker: None,
+            };
+        }
+
+        let shared = Arc::clone(&state);
+        let ticker = thread::spawn(move || {
+            let spinner_chars = ["⠋", "⠙", "⠸", "⠴", "⠦", "⠇"];
+            for frame in spinner_chars.iter().cycle() {
+                {
+                    let Ok(mut state) = shared.lock() else {
+                        return;
+                    };
+                    if state.done {
+                        return;
+                    }
+                    if !state.paused {
+                        let line = format!("{} {}", frame, state.message);
+                        let width = term_utils::display_width(&line);
+                        let padding = " ".repeat(state.drawn.saturating_sub(width));
+                        let mut out = io::stdout().lock();
+                        let _ = write!(out, "\r{}{}", line, padding);
+                        let _ = out.flush();
+                        state.drawn = width;
+                    }
+                }
+                thread::sleep(Duration::from_millis(80));
+            }
+        });
+        LiveSpinner {
+            state,
+            ticker: Some(ticker),
+        }
+    }
+
+    /// Replaces the message shown next to the spinner
+    ///
+    /// # Arguments
+    /// * `message` - The new message
+    pub fn set_message(&self, message: &str) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        if state.message != message {
+            state.message = message.to_string();
+            if self.ticker.is_none() {
+                println!("{}...", message);
+            }
+        }
+    }
+
+    /// Prints a line of the command's own output above the spinner
+    ///
+    /// # Arguments
+    /// * `line` - The line to print on stderr
+    pub fn print_line(&self, line: &str) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        clear_live_line(&mut state);
+        state.paused = false;
+        eprintln!("{}", line);
+    }
+
+    /// Shows a prompt and stops redrawing until the next line of output
+    ///
+    /// # Arguments
+    /// * `prompt` - The prompt text, printed without a newline on stderr
+    pub fn prompt(&self, prompt: &str) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        clear_live_line(&mut state);
+        state.paused = true;
+        eprint!("{}", prompt);
+        let _ = io::stderr().flush();
+    }
+
+    /// Stops the spinner and clears its line
+    pub fn finish(mut self) {
+        self.stop();
+    }
+
+    /// Stops the ticker thread and clears the line, once
+    fn stop(&mut self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.done = true;
+            clear_live_line(&mut state);
+        }
+        if let Some(ticker) = self.ticker.take() {
+            let _ = ticker.join();
+        }
+    }
+}
+
+impl Drop for LiveSpinner {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Blanks the spinner's line if a frame is on screen
+fn clear_live_line(state: &mut LiveState) {
+    if state.drawn > 0 {
+        let mut out = io::stdout().lock();
+        let _ = write!(out, "\r{}\r", " ".repeat(state.drawn));
+        let _ = out.flush();
+        state.drawn = 0;
+    }
 }
 
 /// Creates a progress bar effect
 ///
+/// When stdout is not a terminal a dot is printed per step instead of
+/// redrawing the bar in place.
+///
 /// # Arguments
 /// * `message` - The message to display with the progress bar
 /// * `total` - Total number of steps
 /// * `duration_ms` - Total duration of the progress bar in milliseconds
 pub fn progress_bar(message: &str, total: u64, duration_ms: u64) {
+    let _ = progress_bar_to(
+        &mut animation_out(),
+        term_utils::caps().is_tty,
+        message,
+        total,
+        duration_ms,
+        fast_mode(),
+    );
+}
+
+/// Writes a progress bar to `out`, redrawing with `\r` only when `interactive`
+///
+/// # Arguments
+/// * `out` - Where to write the progress bar
+/// * `interactive` - Whether `out` is a terminal
+/// * `message` - The message to display with the progress bar
+/// * `total` - Total number of steps
+/// * `duration_ms` - Total duration of the progress bar in milliseconds
+/// * `fast` - Whether to skip the pauses between steps (see [`fast_mode`])
+pub fn progress_bar_to(
+    out: &mut impl Write,
+    interactive: bool,
+    message: &str,
+    total: u64,
+    duration_ms: u64,
+    fast: bool,
+) -> io::Result<()> {
     let width = 30;
+    let total = total.max(1);
     let step_duration = duration_ms / total;
 
+    if !interactive {
+        write!(out, "{} ", message)?;
+    }
+
     for i in 1..=total {
-        let percentage = (i as f64 / total as f64) * 100.0;
-        let filled = (width as f64 * i as f64 / total as f64) as usize;
-        let empty = width - filled;
-
-        print!(
-            "\r{} [{}{}] {:.1}%",
-            message,
-            "█".repeat(filled),
-            " ".repeat(empty),
-            percentage
-        );
-        io::stdout().flush().unwrap();
-        sleep(Duration::from_millis(step_duration));
+        if interactive {
+            let percentage = (i as f64 / total as f64) * 100.0;
+            let filled = (width as f64 * i as f64 / total as f64) as usize;
+            let empty = width - filled;
+
+            write!(
+                out,
+                "\r{} [{}{}] {:.1}%",
+                message,
+                "█".repeat(filled),
+                " ".repeat(empty),
+                percentage
+            )?;
+        } else {
+            write!(out, ".")?;
+        }
+        out.flush()?;
+        pause(Duration::from_millis(step_duration), fast);
     }
-    println!();
+
+    if interactive {
+        writeln!(out)?;
+    } else {
+        writeln!(out, " done")?;
+    }
+    out.flush()
 }
 
 /// Displays a framed message in the terminal
@@ -79,73 +369,175 @@ pub fn progress_bar(message: &str, total: u64, duration_ms: u64) {
 /// * `message` - The message to display in the frame
 /// * `width` - Width of the frame
 pub fn framed_message(message: &str, width: usize) {
+    let width = width.max(5);
     let top = "┌".to_owned() + &"─".repeat(width - 2) + "┐";
     let bottom = "└".to_owned() + &"─".repeat(width - 2) + "┘";
-    
+
     println!("{}", top);
-    
-    // Split message into lines that fit within the frame
+
+    // Split each line of the message into lines that fit within the frame
     let max_line_width = width - 4;
-    let mut current_line = String::new();
-    
-    for word in message.split_whitespace() {
-        if current_line.len() + word.len() < max_line_width {
-            if !current_line.is_empty() {
-                current_line.push(' ');
-            }
-            current_line.push_str(word);
-        } else if !current_line.is_empty() {
-            let padding = " ".repeat(width - 4 - current_line.len());
-            println!("│ {} {} │", current_line, padding);
-            current_line = word.to_string();
-        } else {
-            // Word is too long, need to split it
-            current_line = word.to_string();
+
+    for paragraph in message.lines() {
+        let lines = term_utils::wrap_text(paragraph, max_line_width);
+        if lines.is_empty() {
+            println!("│ {} │", " ".repeat(max_line_width));
+        }
+        for line in lines {
+            let padding =
+                " ".repeat(max_line_width.saturating_sub(term_utils::display_width(&line)));
+            println!("│ {}{} │", line, padding);
         }
     }
-    
-    if !current_line.is_empty() {
-        let padding = " ".repeat(width - 4 - current_line.len());
-        println!("│ {} {} │", current_line, padding);
-    }
-    
+
     println!("{}", bottom);
 }
 
+/// Returns the escape sequence coloring text at an opacity on a dark background
+///
+/// Truecolor terminals get a smooth RGB gray; 256-color terminals get the
+/// 24-step grayscale ramp. Fewer colors can't fade, so they get `None`.
+///
+/// # Arguments
+/// * `opacity` - From 0.0 (invisible) to 1.0 (fully visible)
+/// * `depth` - The terminal's color depth
+///
+/// # Returns
+/// * `Option<String>` - The foreground color sequence, if the depth allows a fade
+pub fn fade_color(opacity: f64, depth: term_utils::ColorDepth) -> Option<String> {
+    let opacity = opacity.clamp(0.0, 1.0);
+    match depth {
+        term_utils::ColorDepth::TrueColor => {
+            let level = (opacity * 255.0).round() as u8;
+            Some(format!("\x1b[38;2;{0};{0};{0}m", level))
+        }
+        term_utils::ColorDepth::Ansi256 => {
+            // 232..=255 is the grayscale ramp
+            let gray_level = (opacity * 23.0).round() as u8;
+            Some(format!("\x1b[38;5;{}m", 232 + gray_level))
+        }
+        term_utils::ColorDepth::Basic | term_utils::ColorDepth::None => None,
+    }
+}
+
 /// Creates a fading effect for text
 ///
+/// Terminals with 16 colors or fewer just print the text once.
+///
 /// # Arguments
 /// * `text` - The text to fade in and out
 /// * `duration_ms` - Total duration of the effect in milliseconds
 pub fn fade_text(text: &str, duration_ms: u64) {
+    let depth = term_utils::caps().depth;
+    if !settings::current().color || fade_color(1.0, depth).is_none() {
+        println!("{}", text);
+        return;
+    }
+
+    // The fade supplies the color, so styles already in the text would fight it
+    let plain = term_utils::strip_ansi(text);
     let half_duration = duration_ms / 2;
-    let steps = 10;
+    let steps: u64 = if depth == term_utils::ColorDepth::TrueColor {
+        30
+    } else {
+        10
+    };
     let step_duration = half_duration / steps;
+    let mut out = animation_out();
 
-    // Fade in
-    for i in 1..=steps {
-        print!("\r");
-        let opacity = i as f64 / steps as f64;
-        let gray_level = (opacity * 24.0) as u8;
-        print!("\x1b[38;5;{}m{}\x1b[0m", 232 + gray_level, text);
-        io::stdout().flush().unwrap();
+    let mut draw = |step: u64| {
+        let color = fade_color(step as f64 / steps as f64, depth).unwrap_or_default();
+        let _ = write!(out, "\r{}{}\x1b[0m", color, plain).and_then(|_| out.flush());
         sleep(Duration::from_millis(step_duration));
-    }
+    };
+
+    // Fade in
+    (1..=steps).for_each(&mut draw);
 
     // Fully visible pause
     sleep(Duration::from_millis(half_duration));
 
     // Fade out
-    for i in (1..=steps).rev() {
-        print!("\r");
-        let opacity = i as f64 / steps as f64;
-        let gray_level = (opacity * 24.0) as u8;
-        print!("\x1b[38;5;{}m{}\x1b[0m", 232 + gray_level, text);
-        io::stdout().flush().unwrap();
-        sleep(Duration::from_millis(step_duration));
-    }
+    (1..=steps).rev().for_each(&mut draw);
 
     // Clear line
-    print!("\r{}\r", " ".repeat(text.len()));
-    io::stdout().flush().unwrap();
+    let _ = write!(out, "\r{}\r", " ".repeat(term_utils::display_width(&plain)))
+        .and_then(|_| out.flush());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    /// Test that fast mode keeps every frame but drops the waiting
+    #[test]
+    fn test_fast_mode_skips_pauses() {
+        let started = Instant::now();
+        let mut out = Vec::new();
+        spinner_to(&mut out, true, "Fetching VMs", 4000, true).unwrap();
+        progress_bar_to(&mut out, true, "Copying key", 20, 4000, true).unwrap();
+
+        assert!(started.elapsed() < Duration::from_secs(2));
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(text.matches("Fetching VMs").count(), 4000 / 80);
+        assert!(text.contains("100.0%"));
+    }
+
+    /// Test that non-terminal output never redraws with carriage returns
+    #[test]
+    fn test_no_carriage_return_when_not_a_terminal() {
+        let mut out = Vec::new();
+        spinner_to(&mut out, false, "Fetching VMs", 0, false).unwrap();
+        progress_bar_to(&mut out, false, "Copying key", 5, 0, false).unwrap();
+
+        let text = String::from_utf8(out).unwrap();
+        assert!(!text.contains('\r'));
+        assert_eq!(text, "Fetching VMs...\nCopying key ..... done\n");
+    }
+
+    /// Test that typing flushes once per character, with nothing left buffered
+    #[test]
+    fn test_type_text_flushes_per_character() {
+        /// Records the text present at each flush
+        #[derive(Default)]
+        struct Frames {
+            pending: Vec<u8>,
+            frames: Vec<String>,
+        }
+        impl Write for Frames {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.pending.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                self.frames
+                    .push(String::from_utf8(std::mem::take(&mut self.pending)).unwrap());
+                Ok(())
+            }
+        }
+
+        let mut out = Frames::default();
+        type_text_to(&mut out, "hi", 0, false).unwrap();
+        assert_eq!(out.frames, ["h", "i", "\n"]);
+    }
+
+    /// Test the fade colors for each color depth
+    #[test]
+    fn test_fade_color_by_depth() {
+        use term_utils::ColorDepth;
+        assert_eq!(
+            fade_color(1.0, ColorDepth::TrueColor).as_deref(),
+            Some("\x1b[38;2;255;255;255m")
+        );
+        assert_eq!(
+            fade_color(0.0, ColorDepth::Ansi256).as_deref(),
+            Some("\x1b[38;5;232m")
+        );
+        assert_eq!(
+            fade_color(1.0, ColorDepth::Ansi256).as_deref(),
+            Some("\x1b[38;5;255m")
+        );
+        assert_eq!(fade_color(0.5, ColorDepth::Basic), None);
+    }
 }
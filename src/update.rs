@@ -0,0 +1,116 @@
+/// This module checks whether a newer release has been tagged in the project's
+/// git repository, both for `--update` and for the quiet check at startup.
+use anyhow::{Context, Result};
+use std::{
+    process::{Command, Stdio},
+    thread::{self, JoinHandle},
+};
+
+/// Repository whose `vX.Y.Z` tags mark releases
+pub const REPOSITORY: &str = "https://github.com/RustSandbox/gcloud-ssh";
+
+/// Version of the running binary
+pub const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Outcome of an update check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdateStatus {
+    /// No newer release exists
+    UpToDate,
+    /// A newer release exists, with its version (e.g. "0.2.0")
+    Available(String),
+    /// The releases couldn't be fetched, with the reason
+    Failed(String),
+}
+
+/// Checks the repository's release tags against the running version
+///
+/// Network and git problems are reported as [`UpdateStatus::Failed`] rather
+/// than as errors, since they say nothing about the installation itself.
+///
+/// # Returns
+/// * `Result<UpdateStatus>` - The status, or an error if the running version is malformed
+pub fn check() -> Result<UpdateStatus> {
+    let current = parse_version(CURRENT_VERSION)
+        .with_context(|| format!("Invalid package version '{}'", CURRENT_VERSION))?;
+
+    let output = Command::new("git")
+        .args(["ls-remote", "--tags", "--refs", REPOSITORY])
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::null())
+        .output();
+    let output = match output {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            return Ok(UpdateStatus::Failed(
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ))
+        }
+        Err(e) => return Ok(UpdateStatus::Failed(format!("could not run git: {}", e))),
+    };
+
+    Ok(status_from_tags(
+        current,
+        &String::from_utf8_lossy(&output.stdout),
+    ))
+}
+
+/// Starts [`check`] on a background thread so startup doesn't wait for the network
+///
+/// # Returns
+/// * `JoinHandle<Result<UpdateStatus>>` - The running check
+pub fn check_in_background() -> JoinHandle<Result<UpdateStatus>> {
+    thread::spawn(check)
+}
+
+/// Compares the newest `vX.Y.Z` tag in `git ls-remote` output with a version
+///
+/// # Arguments
+/// * `current` - The running version
+/// * `ls_remote` - Lines of `<hash>\trefs/tags/<tag>`
+///
+/// # Returns
+/// * `UpdateStatus` - Whether a newer release is tagged
+fn status_from_tags(current: (u64, u64, u64), ls_remote: &str) -> UpdateStatus {
+    let latest = ls_remote
+        .lines()
+        .filter_map(|line| line.split_once("refs/tags/"))
+        .filter_map(|(_, tag)| parse_version(tag.trim()))
+        .max();
+
+    match latest {
+        Some(latest) if latest > current => {
+            UpdateStatus::Available(format!("{}.{}.{}", latest.0, latest.1, latest.2))
+        }
+        _ => UpdateStatus::UpToDate,
+    }
+}
+
+/// Parses `X.Y.Z` or `vX.Y.Z`, ignoring pre-release tags such as `1.0.0-rc1`
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let mut parts = version.strip_prefix('v').unwrap_or(version).split('.');
+    let version = (
+        parts.next()?.parse().ok()?,
+        parts.next()?.parse().ok()?,
+        parts.next()?.parse().ok()?,
+    );
+    parts.next().is_none().then_some(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that only newer, well-formed release tags count as updates
+    #[test]
+    fn test_status_from_tags() {
+        let tags = "a1\trefs/tags/v0.1.0\nb2\trefs/tags/v0.2.0\nc3\trefs/tags/v0.10.0-rc1\nd4\trefs/tags/latest\n";
+        assert_eq!(
+            status_from_tags((0, 1, 0), tags),
+            UpdateStatus::Available("0.2.0".to_string())
+        );
+        assert_eq!(status_from_tags((0, 2, 0), tags), UpdateStatus::UpToDate);
+        assert_eq!(status_from_tags((0, 1, 0), ""), UpdateStatus::UpToDate);
+        assert!(parse_version(CURRENT_VERSION).is_some());
+    }
+}
@@ -0,0 +1,773 @@
+/// This module loads the optional user configuration file
+/// (`~/.config/gcloud-ssh/config.toml`) and the nearest project-local
+/// `.gcloud-ssh.toml`, which provide defaults that command-line flags can override.
+use crate::{cli, storage};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// File name of the configuration file inside the configuration directory
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// File name of the per-directory configuration searched for from the cwd upwards
+pub const LOCAL_CONFIG_FILE: &str = ".gcloud-ssh.toml";
+
+/// Settings read from the user configuration file
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UserConfig {
+    /// Instance listing defaults
+    pub listing: ListingConfig,
+
+    /// SSH key handling
+    pub keys: KeysConfig,
+
+    /// Named bundles of settings selected with `--profile`
+    pub profiles: BTreeMap<String, Profile>,
+
+    /// Named sets of listing filters selected with `--query`
+    pub queries: BTreeMap<String, Query>,
+
+    /// Icon roles (e.g. `vm`, `success`) mapped to the emoji to show for them
+    pub emojis: BTreeMap<String, String>,
+
+    /// Message keys (e.g. `welcome`) mapped to replacement text, such as a translation
+    pub messages: BTreeMap<String, String>,
+
+    /// Guards for actions on many VMs at once
+    pub safety: SafetyConfig,
+
+    /// Display adjustments
+    pub display: DisplayConfig,
+
+    /// Interface behavior
+    pub ui: UiConfig,
+
+    /// SSH key deployment
+    pub ssh: SshConfig,
+
+    /// Connection behavior
+    pub connect: ConnectConfig,
+
+    /// Operating system families mapped to the login user of their images
+    pub os_users: OsUsers,
+}
+
+/// A `[profiles.<name>]` section
+///
+/// Profile values override the top-level configuration but never a flag given
+/// on the command line.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    /// gcloud project passed to every gcloud call
+    pub project: Option<String>,
+
+    /// Named gcloud configuration passed to every gcloud call
+    pub configuration: Option<String>,
+
+    /// gcloud `--filter` expression, replacing `listing.default_filter`
+    pub filter: Option<String>,
+
+    /// Maximum number of instances to list, replacing `listing.limit`
+    pub limit: Option<u32>,
+
+    /// Connect through `gcloud compute ssh` after selecting a VM
+    pub connect: Option<bool>,
+
+    /// Ask before changing anything (`false` behaves like `--yes`)
+    pub confirm: Option<bool>,
+
+    /// Use emoji markers (`false` behaves like `--no-emoji`)
+    pub emoji: Option<bool>,
+}
+
+/// A `[queries.<name>]` section: a saved view of the instance listing
+///
+/// Every field that is set narrows the listing; together they replace
+/// `listing.default_filter` and the profile's filter.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Query {
+    /// Only list VMs in this zone, unless `--zone` is given
+    pub zone: Option<String>,
+
+    /// Only list VMs with this status (e.g. `RUNNING`)
+    pub status: Option<String>,
+
+    /// Only list VMs carrying all of these labels
+    pub labels: BTreeMap<String, String>,
+
+    /// Any further gcloud `--filter` expression
+    pub filter: Option<String>,
+}
+
+impl Query {
+    /// Combines the status, labels and filter into one gcloud `--filter` expression
+    ///
+    /// # Returns
+    /// * `Option<String>` - The expression, or None if the query sets none of them
+    pub fn filter_expression(&self) -> Option<String> {
+        let mut terms = Vec::new();
+        if let Some(status) = &self.status {
+            terms.push(format!("status={}", status.to_uppercase()));
+        }
+        for (key, value) in &self.labels {
+            terms.push(format!("labels.{}={}", key, value));
+        }
+        if let Some(filter) = self.filter.as_deref().filter(|filter| !filter.is_empty()) {
+            terms.push(format!("({})", filter));
+        }
+        (!terms.is_empty()).then(|| terms.join(" AND "))
+    }
+}
+
+/// Per-repository defaults read from the nearest `.gcloud-ssh.toml`
+///
+/// These sit between command-line flags and the user configuration file,
+/// including its selected profile.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LocalConfig {
+    /// gcloud project passed to every gcloud call
+    pub project: Option<String>,
+
+    /// Only list VMs in this zone (full name, abbreviation or unique prefix)
+    pub zone: Option<String>,
+
+    /// gcloud `--filter` expression, replacing `listing.default_filter`
+    pub filter: Option<String>,
+
+    /// Remote username, as with `--user`
+    pub user: Option<String>,
+
+    /// The file these values were read from
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+impl LocalConfig {
+    /// Finds and loads the `.gcloud-ssh.toml` nearest to a directory
+    ///
+    /// The directory itself is checked first, then each of its parents up to
+    /// the filesystem root; the first file found wins and the others are ignored.
+    ///
+    /// # Arguments
+    /// * `start` - The directory to search from, usually the cwd
+    ///
+    /// # Returns
+    /// * `Result<Option<LocalConfig>>` - The nearest configuration, if any, or
+    ///   an error if that file is invalid
+    pub fn discover(start: &Path) -> Result<Option<Self>> {
+        let Some(path) = start
+            .ancestors()
+            .map(|dir| dir.join(LOCAL_CONFIG_FILE))
+            .find(|path| path.is_file())
+        else {
+            return Ok(None);
+        };
+
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let mut config: LocalConfig = toml::from_str(&content)
+            .with_context(|| format!("Invalid configuration in {}", path.display()))?;
+        config.path = path;
+        Ok(Some(config))
+    }
+}
+
+/// The `[listing]` section
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ListingConfig {
+    /// gcloud `--filter` expression applied when listing instances (empty for none)
+    pub default_filter: String,
+
+    /// Maximum number of instances to list
+    pub limit: Option<u32>,
+
+    /// Instance label holding the remote login user (e.g. `ssh-user=deploy`)
+    pub user_label: String,
+
+    /// Fields shown for each VM in the selection menu, as with `--columns`
+    pub columns: Vec<String>,
+}
+
+/// The `[keys]` section
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeysConfig {
+    /// Whether a missing SSH key may be generated (`false` behaves like `--no-key-gen`)
+    pub generate: bool,
+}
+
+/// The `[safety]` section
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SafetyConfig {
+    /// Selecting more VMs than this with `--multi` asks for an extra confirmation
+    pub bulk_confirm_threshold: usize,
+}
+
+/// The `[display]` section
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplayConfig {
+    /// Terminals narrower than this many columns get a warning at startup
+    pub min_width: usize,
+
+    /// Switch to plain ASCII markers on narrow terminals
+    pub auto_downgrade: bool,
+}
+
+/// The `[ssh]` section
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SshConfig {
+    /// Comment added to pushed keys that have none; `{host}` and `{date}` are
+    /// replaced with the local hostname and today's date, and empty disables it
+    pub pushed_key_comment: String,
+}
+
+impl Default for SshConfig {
+    fn default() -> Self {
+        SshConfig {
+            pushed_key_comment: "gcloud-ssh@{host}-{date}".to_string(),
+        }
+    }
+}
+
+/// The `[connect]` section
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConnectConfig {
+    /// What to do when the selected VM has no external IP: `error`, `iap`,
+    /// `internal` or `gcloud-ssh`, as with `--no-external-ip-fallback`
+    pub no_external_ip_fallback: String,
+}
+
+impl Default for ConnectConfig {
+    fn default() -> Self {
+        ConnectConfig {
+            no_external_ip_fallback: cli::NoExternalIpFallback::Error.name().to_string(),
+        }
+    }
+}
+
+/// The `[os_users]` section
+///
+/// Keys are the start of an image license name (e.g. `ubuntu` matches
+/// `ubuntu-2204-lts`), values the user to log in as. Writing the section
+/// replaces the built-in table, so an empty section turns the detection off.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct OsUsers(pub BTreeMap<String, String>);
+
+impl Default for OsUsers {
+    fn default() -> Self {
+        OsUsers(
+            [
+                ("centos", "centos"),
+                ("debian", "debian"),
+                ("fedora-coreos", "core"),
+                ("rocky-linux", "rocky"),
+                ("ubuntu", "ubuntu"),
+            ]
+            .into_iter()
+            .map(|(family, user)| (family.to_string(), user.to_string()))
+            .collect(),
+        )
+    }
+}
+
+impl OsUsers {
+    /// Finds the login user for an image from its license URLs
+    ///
+    /// The longest family matching any license wins, so `rhel-sap` can be told
+    /// apart from `rhel`.
+    ///
+    /// # Arguments
+    /// * `licenses` - License URLs of the VM's disks
+    ///
+    /// # Returns
+    /// * `Option<&str>` - The user for the longest matching family, if any
+    pub fn user_for(&self, licenses: &[String]) -> Option<&str> {
+        let names: Vec<&str> = licenses
+            .iter()
+            .filter_map(|license| license.rsplit('/').find(|segment| !segment.is_empty()))
+            .collect();
+        self.0
+            .iter()
+            .filter(|(family, user)| {
+                !user.is_empty() && names.iter().any(|name| name.starts_with(family.as_str()))
+            })
+            .max_by_key(|(family, _)| family.len())
+            .map(|(_, user)| user.as_str())
+    }
+}
+
+/// The `[ui]` section
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UiConfig {
+    /// Show the banner and welcome line at startup (`false` behaves like `--no-welcome`)
+    pub show_welcome: bool,
+
+    /// Show the active project, account and configuration before listing VMs
+    pub show_context: bool,
+
+    /// Regular expression matching project IDs to highlight as production
+    pub prod_pattern: String,
+
+    /// Ring the terminal bell when the flow finishes (`true` behaves like `--bell`)
+    pub bell_on_complete: bool,
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        UiConfig {
+            show_welcome: true,
+            show_context: true,
+            prod_pattern: ".*-prod.*".to_string(),
+            bell_on_complete: false,
+        }
+    }
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        DisplayConfig {
+            min_width: 50,
+            auto_downgrade: true,
+        }
+    }
+}
+
+impl Default for SafetyConfig {
+    fn default() -> Self {
+        SafetyConfig {
+            bulk_confirm_threshold: 5,
+        }
+    }
+}
+
+impl Default for KeysConfig {
+    fn default() -> Self {
+        KeysConfig { generate: true }
+    }
+}
+
+impl Default for ListingConfig {
+    fn default() -> Self {
+        ListingConfig {
+            default_filter: "status=RUNNING".to_string(),
+            limit: None,
+            user_label: "ssh-user".to_string(),
+            columns: cli::Column::DEFAULT
+                .iter()
+                .map(|column| column.name().to_string())
+                .collect(),
+        }
+    }
+}
+
+impl UserConfig {
+    /// Loads the configuration file, falling back to defaults when it doesn't exist
+    ///
+    /// # Returns
+    /// * `Result<UserConfig>` - The configuration or error if the file is invalid
+    pub fn load() -> Result<Self> {
+        match storage::config_dir() {
+            Some(dir) => Self::load_from(&dir.join(CONFIG_FILE)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Loads a configuration file from a specific path
+    ///
+    /// # Arguments
+    /// * `path` - The TOML file to read
+    ///
+    /// # Returns
+    /// * `Result<UserConfig>` - The configuration or error if the file is invalid
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(&content)
+            .with_context(|| format!("Invalid configuration in {}", path.display()))
+    }
+
+    /// Parses configuration from TOML text
+    ///
+    /// # Arguments
+    /// * `content` - The TOML text
+    ///
+    /// # Returns
+    /// * `Result<UserConfig>` - The configuration or parse error
+    pub fn parse(content: &str) -> Result<Self> {
+        Ok(toml::from_str(content)?)
+    }
+
+    /// Layers the project-local file and the selected profile under the flags
+    ///
+    /// The resulting precedence is: flags, then the query selected with
+    /// `--query`, then the nearest `.gcloud-ssh.toml`, then the profile, then
+    /// the top-level configuration. Anything still unset is left to gcloud's
+    /// own configuration.
+    ///
+    /// # Arguments
+    /// * `local` - The project-local configuration, if one was found
+    /// * `options` - Parsed command-line options, updated in place
+    ///
+    /// # Returns
+    /// * `Result<()>` - Success, or an error if the profile or query is unknown
+    pub fn resolve(
+        &mut self,
+        local: Option<&LocalConfig>,
+        options: &mut cli::Options,
+    ) -> Result<()> {
+        let query = self.selected_query(options)?;
+        if let Some(query) = &query {
+            if options.zone.is_none() {
+                options.zone = query.zone.clone();
+            }
+        }
+
+        if let Some(local) = local {
+            if options.project.is_none() {
+                options.project = local.project.clone();
+            }
+            if options.zone.is_none() {
+                options.zone = local.zone.clone();
+            }
+            if options.user.is_none() {
+                options.user = local.user.clone();
+            }
+        }
+
+        self.apply_profile(options)?;
+
+        if let Some(filter) = local.and_then(|local| local.filter.clone()) {
+            self.listing.default_filter = filter;
+        }
+        if let Some(filter) = query.and_then(|query| query.filter_expression()) {
+            self.listing.default_filter = filter;
+        }
+        if options.columns.is_none() {
+            let columns = cli::parse_columns(&self.listing.columns)
+                .map_err(|e| anyhow::anyhow!("listing.columns: {}", e))?;
+            options.columns = Some(columns);
+        }
+        if options.no_external_ip_fallback.is_none() {
+            let fallback = self
+                .connect
+                .no_external_ip_fallback
+                .parse()
+                .map_err(|e| anyhow::anyhow!("connect.no_external_ip_fallback: {}", e))?;
+            options.no_external_ip_fallback = Some(fallback);
+        }
+        Ok(())
+    }
+
+    /// Looks up the query named with `--query`, if any
+    ///
+    /// # Arguments
+    /// * `options` - Parsed command-line options
+    ///
+    /// # Returns
+    /// * `Result<Option<Query>>` - The saved query, or an error listing the
+    ///   queries if the name is unknown
+    pub fn selected_query(&self, options: &cli::Options) -> Result<Option<Query>> {
+        let Some(name) = &options.query else {
+            return Ok(None);
+        };
+        if let Some(query) = self.queries.get(name) {
+            return Ok(Some(query.clone()));
+        }
+
+        let available: Vec<&str> = self.queries.keys().map(String::as_str).collect();
+        if available.is_empty() {
+            bail!(
+                "Unknown query '{}': no queries are configured (add a [queries.{}] section)",
+                name,
+                name
+            );
+        }
+        bail!(
+            "Unknown query '{}' (available: {})",
+            name,
+            available.join(", ")
+        );
+    }
+
+    /// Applies the profile named with `--profile`, if any
+    ///
+    /// Profile listing values replace the top-level `[listing]` ones, and the
+    /// remaining values fill in options that weren't given as flags.
+    ///
+    /// # Arguments
+    /// * `options` - Parsed command-line options, updated in place
+    ///
+    /// # Returns
+    /// * `Result<()>` - Success, or an error listing the profiles if the name is unknown
+    pub fn apply_profile(&mut self, options: &mut cli::Options) -> Result<()> {
+        let Some(name) = &options.profile else {
+            return Ok(());
+        };
+        let Some(profile) = self.profiles.get(name).cloned() else {
+            let available: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            if available.is_empty() {
+                bail!("Unknown profile '{}': no profiles are configured", name);
+            }
+            bail!(
+                "Unknown profile '{}' (available: {})",
+                name,
+                available.join(", ")
+            );
+        };
+
+        if let Some(filter) = profile.filter {
+            self.listing.default_filter = filter;
+        }
+        if profile.limit.is_some() {
+            self.listing.limit = profile.limit;
+        }
+
+        if options.project.is_none() {
+            options.project = profile.project;
+        }
+        if options.configuration.is_none() {
+            options.configuration = profile.configuration;
+        }
+        if options.action.is_none() && profile.connect == Some(true) {
+            options.action = Some(cli::Action::Connect);
+        }
+        if profile.confirm == Some(false) {
+            options.yes = true;
+        }
+        if profile.emoji == Some(false) {
+            options.no_emoji = true;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that missing sections and keys fall back to defaults
+    #[test]
+    fn test_defaults() {
+        let config = UserConfig::parse("").unwrap();
+        assert_eq!(config.listing.default_filter, "status=RUNNING");
+        assert_eq!(config.listing.limit, None);
+        assert!(config.keys.generate);
+        assert_eq!(config.display.min_width, 50);
+        assert!(config.display.auto_downgrade);
+        assert!(config.ui.show_welcome);
+        assert!(config.ui.show_context);
+    }
+
+    /// Test that listing options are read and unknown keys rejected
+    #[test]
+    fn test_listing_section() {
+        let config = UserConfig::parse("[listing]\ndefault_filter = \"\"\nlimit = 25\n").unwrap();
+        assert!(config.listing.default_filter.is_empty());
+        assert_eq!(config.listing.limit, Some(25));
+
+        assert!(UserConfig::parse("[listing]\nlimt = 25\n").is_err());
+    }
+
+    /// Test that listing.columns fills in --columns and is validated
+    #[test]
+    fn test_listing_columns() {
+        let mut config =
+            UserConfig::parse("[listing]\ncolumns = [\"name\", \"status\"]\n").unwrap();
+        let mut options = cli::Options::default();
+        config.resolve(None, &mut options).unwrap();
+        assert_eq!(
+            options.columns,
+            Some(vec![cli::Column::Name, cli::Column::Status])
+        );
+
+        let mut options = cli::Options {
+            columns: Some(vec![cli::Column::Ip]),
+            ..Default::default()
+        };
+        config.resolve(None, &mut options).unwrap();
+        assert_eq!(options.columns, Some(vec![cli::Column::Ip]));
+
+        let mut config = UserConfig::parse("[listing]\ncolumns = [\"region\"]\n").unwrap();
+        let err = config
+            .resolve(None, &mut cli::Options::default())
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("listing.columns: Invalid column 'region'"));
+    }
+
+    /// Test that the fallback flag beats connect.no_external_ip_fallback, which is validated
+    #[test]
+    fn test_no_external_ip_fallback() {
+        let mut config =
+            UserConfig::parse("[connect]\nno_external_ip_fallback = \"iap\"\n").unwrap();
+        let mut options = cli::Options::default();
+        config.resolve(None, &mut options).unwrap();
+        assert_eq!(
+            options.no_external_ip_fallback,
+            Some(cli::NoExternalIpFallback::Iap)
+        );
+
+        let mut options = cli::Options {
+            no_external_ip_fallback: Some(cli::NoExternalIpFallback::Internal),
+            ..Default::default()
+        };
+        config.resolve(None, &mut options).unwrap();
+        assert_eq!(
+            options.no_external_ip_fallback,
+            Some(cli::NoExternalIpFallback::Internal)
+        );
+
+        let mut config =
+            UserConfig::parse("[connect]\nno_external_ip_fallback = \"vpn\"\n").unwrap();
+        let err = config
+            .resolve(None, &mut cli::Options::default())
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("connect.no_external_ip_fallback: Invalid fallback 'vpn'"));
+    }
+
+    /// Test that a saved query narrows the listing and an unknown name lists the others
+    #[test]
+    fn test_query() {
+        let mut config = UserConfig::parse(
+            "[profiles.ops]\nfilter = \"labels.team=ops\"\n\n[queries.staging]\nzone = \"uc1a\"\nstatus = \"running\"\nlabels = { env = \"staging\", tier = \"web\" }\n",
+        )
+        .unwrap();
+        let mut options = cli::Options {
+            query: Some("staging".to_string()),
+            profile: Some("ops".to_string()),
+            ..Default::default()
+        };
+        config.resolve(None, &mut options).unwrap();
+        assert_eq!(options.zone.as_deref(), Some("uc1a"));
+        assert_eq!(
+            config.listing.default_filter,
+            "status=RUNNING AND labels.env=staging AND labels.tier=web"
+        );
+
+        let mut options = cli::Options {
+            query: Some("prod".to_string()),
+            ..Default::default()
+        };
+        let err = config.resolve(None, &mut options).unwrap_err();
+        assert_eq!(err.to_string(), "Unknown query 'prod' (available: staging)");
+    }
+
+    /// Test that image licenses map to login users and a written section replaces the defaults
+    #[test]
+    fn test_os_users() {
+        let license = |name: &str| {
+            format!(
+                "https://www.googleapis.com/compute/v1/projects/p/global/licenses/{}",
+                name
+            )
+        };
+        let defaults = OsUsers::default();
+        assert_eq!(
+            defaults.user_for(&[license("ubuntu-2204-lts")]),
+            Some("ubuntu")
+        );
+        assert_eq!(defaults.user_for(&[license("cos-pcid")]), None);
+        assert_eq!(defaults.user_for(&[]), None);
+
+        let config =
+            UserConfig::parse("[os_users]\nrhel = \"cloud-user\"\nrhel-sap = \"sapadm\"\n")
+                .unwrap();
+        assert_eq!(
+            config.os_users.user_for(&[license("rhel-8-sap-ha")]),
+            Some("cloud-user")
+        );
+        assert_eq!(
+            config.os_users.user_for(&[license("rhel-sap-15")]),
+            Some("sapadm")
+        );
+        assert_eq!(
+            config.os_users.user_for(&[license("ubuntu-2204-lts")]),
+            None
+        );
+    }
+
+    /// Test that a profile sits between flags and the top-level configuration
+    #[test]
+    fn test_apply_profile() {
+        let mut config = UserConfig::parse(
+            "[listing]\nlimit = 25\n\n[profiles.prod]\nproject = \"prod-1\"\nfilter = \"labels.env=prod\"\nconnect = true\n",
+        )
+        .unwrap();
+        let mut options = cli::Options {
+            profile: Some("prod".to_string()),
+            project: Some("override".to_string()),
+            ..Default::default()
+        };
+        config.apply_profile(&mut options).unwrap();
+
+        assert_eq!(config.listing.default_filter, "labels.env=prod");
+        assert_eq!(config.listing.limit, Some(25));
+        assert_eq!(options.project.as_deref(), Some("override"));
+        assert_eq!(options.action, Some(cli::Action::Connect));
+
+        options.profile = Some("prd".to_string());
+        let err = config.apply_profile(&mut options).unwrap_err();
+        assert!(err.to_string().contains("available: prod"));
+    }
+
+    /// Test that the nearest local file is found and sits above the profile
+    #[test]
+    fn test_local_config() {
+        let root = tempfile::tempdir().unwrap();
+        let nested = root.path().join("service/src");
+        fs::create_dir_all(&nested).unwrap();
+        assert!(LocalConfig::discover(&nested).unwrap().is_none());
+
+        fs::write(root.path().join(LOCAL_CONFIG_FILE), "project = \"outer\"\n").unwrap();
+        fs::write(
+            root.path().join("service").join(LOCAL_CONFIG_FILE),
+            "project = \"repo\"\nzone = \"uc1a\"\nfilter = \"labels.team=web\"\n",
+        )
+        .unwrap();
+        let local = LocalConfig::discover(&nested).unwrap().unwrap();
+        assert_eq!(
+            local.path,
+            root.path().join("service").join(LOCAL_CONFIG_FILE)
+        );
+
+        let mut config = UserConfig::parse(
+            "[profiles.prod]\nproject = \"prod-1\"\nfilter = \"labels.env=prod\"\n",
+        )
+        .unwrap();
+        let mut options = cli::Options {
+            profile: Some("prod".to_string()),
+            zone: Some("europe-west1-b".to_string()),
+            ..Default::default()
+        };
+        config.resolve(Some(&local), &mut options).unwrap();
+
+        assert_eq!(options.project.as_deref(), Some("repo"));
+        assert_eq!(options.zone.as_deref(), Some("europe-west1-b"));
+        assert_eq!(config.listing.default_filter, "labels.team=web");
+
+        fs::write(root.path().join(LOCAL_CONFIG_FILE), "projct = \"typo\"\n").unwrap();
+        assert!(LocalConfig::discover(root.path()).is_err());
+    }
+}
@@ -0,0 +1,180 @@
+/// This module expands abbreviated zone names (such as `uc1a` or a unique
+/// prefix like `europe-west4`) into full Compute Engine zone names, caching
+/// the zone list between runs.
+use crate::{gcloud, logging, storage};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// File name of the zone cache inside the cache directory
+pub const ZONES_CACHE_FILE: &str = "zones.json";
+
+/// How long the cached zone list stays valid, in seconds
+pub const ZONES_CACHE_TTL_SECS: u64 = 24 * 60 * 60;
+
+/// The zone list as stored in the cache
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ZoneCache {
+    /// Unix timestamp of when the list was fetched
+    fetched_at: u64,
+
+    /// Zone names
+    zones: Vec<String>,
+}
+
+/// Returns the abbreviation of a zone name
+///
+/// Each dash-separated part contributes its first letter plus any trailing
+/// digits, so `us-central1-a` becomes `uc1a` and `europe-west4-b` becomes `ew4b`.
+///
+/// # Arguments
+/// * `zone` - The full zone name
+///
+/// # Returns
+/// * `String` - The abbreviation
+pub fn abbreviate(zone: &str) -> String {
+    zone.split('-')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let digits_start = part
+                .trim_end_matches(|c: char| c.is_ascii_digit())
+                .len()
+                .max(1);
+            let first = part.chars().next().map(String::from).unwrap_or_default();
+            first + &part[digits_start..]
+        })
+        .collect()
+}
+
+/// Expands user input into exactly one known zone
+///
+/// An exact name wins; otherwise the input must match a single zone either by
+/// abbreviation or as a prefix.
+///
+/// # Arguments
+/// * `input` - The zone as typed by the user
+/// * `zones` - All known zone names
+///
+/// # Returns
+/// * `Result<String>` - The full zone name, or an error listing the candidates
+pub fn expand(input: &str, zones: &[String]) -> Result<String> {
+    let input = input.trim().to_lowercase();
+    if zones.contains(&input) {
+        return Ok(input);
+    }
+
+    let candidates: Vec<&String> = zones
+        .iter()
+        .filter(|zone| abbreviate(zone) == input || zone.starts_with(&input))
+        .collect();
+
+    match candidates.as_slice() {
+        [zone] => Ok(zone.to_string()),
+        [] => bail!("Unknown zone '{}'", input),
+        many => bail!(
+            "Zone '{}' is ambiguous; it matches: {}",
+            input,
+            many.iter()
+                .map(|zone| zone.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// Returns the known zone names, using the cache when it is fresh
+///
+/// # Returns
+/// * `Result<Vec<String>>` - The zone names or error
+pub fn known_zones() -> Result<Vec<String>> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+
+    let cache_path = storage::cache_file(ZONES_CACHE_FILE).ok();
+    if let Some(path) = &cache_path {
+        if let Ok(cache) = storage::load_json::<ZoneCache>(path) {
+            if !cache.zones.is_empty()
+                && now.saturating_sub(cache.fetched_at) < ZONES_CACHE_TTL_SECS
+            {
+                logging::verbose("Using cached zone list");
+                return Ok(cache.zones);
+            }
+        }
+    }
+
+    let mut command = gcloud::command();
+    command.args(["compute", "zones", "list", "--format=value(name)"]);
+    let output = gcloud::capture(&mut command).context("Failed to run gcloud")?;
+    if !output.status.success() {
+        bail!(
+            "Failed to list zones: {}",
+            gcloud::error_message(&output.stderr)
+        );
+    }
+
+    let zones: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect();
+
+    // A failed cache write only costs a refetch next time
+    if let Some(path) = &cache_path {
+        let cache = ZoneCache {
+            fetched_at: now,
+            zones: zones.clone(),
+        };
+        if let Err(e) = storage::save_json(path, &cache) {
+            logging::verbose(&format!("Could not cache zone list: {}", e));
+        }
+    }
+
+    Ok(zones)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zones() -> Vec<String> {
+        [
+            "us-central1-a",
+            "us-central1-b",
+            "us-east1-b",
+            "europe-west4-a",
+            "northamerica-northeast1-a",
+        ]
+        .iter()
+        .map(|zone| zone.to_string())
+        .collect()
+    }
+
+    /// Test zone abbreviations
+    #[test]
+    fn test_abbreviate() {
+        assert_eq!(abbreviate("us-central1-a"), "uc1a");
+        assert_eq!(abbreviate("europe-west4-b"), "ew4b");
+        assert_eq!(abbreviate("northamerica-northeast1-a"), "nn1a");
+    }
+
+    /// Test expansion by exact name, abbreviation and unique prefix
+    #[test]
+    fn test_expand_unique() {
+        let zones = zones();
+        assert_eq!(expand("us-east1-b", &zones).unwrap(), "us-east1-b");
+        assert_eq!(expand("UC1A", &zones).unwrap(), "us-central1-a");
+        assert_eq!(expand("europe", &zones).unwrap(), "europe-west4-a");
+    }
+
+    /// Test that ambiguous and unknown input is rejected
+    #[test]
+    fn test_expand_ambiguous_or_unknown() {
+        let zones = zones();
+        let err = expand("us-central1", &zones).unwrap_err().to_string();
+        assert!(err.contains("us-central1-a") && err.contains("us-central1-b"));
+        assert!(expand("asia", &zones).is_err());
+    }
+}
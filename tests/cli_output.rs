@@ -0,0 +1,177 @@
+//! Integration tests that run the built binary with piped output.
+use std::process::Command;
+
+/// Runs the binary with the given arguments and a clean color environment
+fn run(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_hcloud"))
+        .args(args)
+        .env_remove("CLICOLOR_FORCE")
+        .env_remove("NO_COLOR")
+        .output()
+        .expect("failed to run the binary");
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).expect("output is not UTF-8")
+}
+
+/// Test that piped output carries no ANSI escape codes by default
+#[test]
+fn test_piped_output_has_no_escape_codes() {
+    for args in [&["--preview"][..], &["--help"], &["--version"]] {
+        let stdout = run(args);
+        assert!(!stdout.is_empty());
+        assert!(
+            !stdout.contains('\x1b'),
+            "escape codes in {:?} output",
+            args
+        );
+    }
+}
+
+/// Test that `--json`, env and inventory output are pure data: no escape codes, no emoji
+#[test]
+fn test_data_modes_are_pure_data() {
+    let home = tempfile::tempdir().unwrap();
+    let hosts = home.path().join("hosts.csv");
+    std::fs::write(
+        &hosts,
+        "name,host,user\nweb-1,203.0.113.10,deploy\nweb-2,203.0.113.11,\n",
+    )
+    .unwrap();
+    let hosts = hosts.to_str().unwrap();
+
+    // An empty home keeps the user's configuration and favorites out of the run
+    let run_isolated = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_hcloud"))
+            .args(args)
+            .current_dir(home.path())
+            .env("HOME", home.path())
+            .env("XDG_CONFIG_HOME", home.path().join(".config"))
+            .env("CLICOLOR_FORCE", "1")
+            .env_remove("NO_COLOR")
+            .output()
+            .expect("failed to run the binary");
+        assert!(output.status.success(), "{:?} failed", args);
+        String::from_utf8(output.stdout).expect("output is not UTF-8")
+    };
+    let assert_plain = |stdout: &str| {
+        assert!(!stdout.contains('\x1b'), "escape codes in {:?}", stdout);
+        assert!(
+            stdout.is_ascii(),
+            "emoji or other non-ASCII in {:?}",
+            stdout
+        );
+    };
+
+    let stdout = run_isolated(&["--json", "--hosts-file", hosts]);
+    assert_plain(&stdout);
+    let listed: serde_json::Value = serde_json::from_str(&stdout).expect("stdout is not JSON");
+    assert_eq!(listed[0]["name"], "web-1");
+    assert_eq!(listed[1]["external_ip"], "203.0.113.11");
+
+    let stdout = run_isolated(&[
+        "--info-format",
+        "env",
+        "--hosts-file",
+        hosts,
+        "--vm",
+        "web-1",
+    ]);
+    assert_plain(&stdout);
+    assert!(stdout.lines().all(|line| line.starts_with("export ")));
+    assert!(stdout.contains("export VM_IP='203.0.113.10'"));
+
+    let stdout = run_isolated(&["--ansible", "--hosts-file", hosts]);
+    assert_plain(&stdout);
+    assert!(stdout.contains("web-1 ansible_host=203.0.113.10 ansible_user=deploy"));
+}
+
+/// Test that `--read-only` refuses local state writes with the usage exit code
+#[test]
+fn test_read_only_refuses_writes() {
+    let home = tempfile::tempdir().unwrap();
+    let hosts = home.path().join("hosts.csv");
+    std::fs::write(&hosts, "name,host\nweb-1,203.0.113.10\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hcloud"))
+        .args(["--read-only", "--hosts-file", hosts.to_str().unwrap()])
+        .args(["--favorite", "web-1"])
+        .current_dir(home.path())
+        .env("HOME", home.path())
+        .env("XDG_CONFIG_HOME", home.path().join(".config"))
+        .output()
+        .expect("failed to run the binary");
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("read-only"));
+    assert!(!home.path().join(".config").exists());
+}
+
+/// Test that `--read-only` also refuses to write the `--write-command` file
+#[test]
+fn test_read_only_refuses_write_command() {
+    let home = tempfile::tempdir().unwrap();
+    let hosts = home.path().join("hosts.csv");
+    std::fs::write(&hosts, "name,host\nweb-1,203.0.113.10\n").unwrap();
+    let target = home.path().join("connect.sh");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hcloud"))
+        .args(["--read-only", "--hosts-file", hosts.to_str().unwrap()])
+        .args(["--vm", "web-1", "--write-command", target.to_str().unwrap()])
+        .current_dir(home.path())
+        .env("HOME", home.path())
+        .env("XDG_CONFIG_HOME", home.path().join(".config"))
+        .output()
+        .expect("failed to run the binary");
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("read-only"));
+    assert!(!target.exists());
+}
+
+/// Test that `--write-command /dev/stdout` leaves only the command on stdout
+#[cfg(unix)]
+#[test]
+fn test_write_command_to_stdout_is_bare() {
+    let home = tempfile::tempdir().unwrap();
+    let hosts = home.path().join("hosts.csv");
+    std::fs::write(&hosts, "name,host,user\nweb-1,203.0.113.10,deploy\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hcloud"))
+        .args(["--hosts-file", hosts.to_str().unwrap(), "--vm", "web-1"])
+        .args(["--write-command", "/dev/stdout"])
+        .current_dir(home.path())
+        .env("HOME", home.path())
+        .env("XDG_CONFIG_HOME", home.path().join(".config"))
+        .env("GCLOUD_SSH_TEST", "1")
+        .output()
+        .expect("failed to run the binary");
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "ssh deploy@203.0.113.10\n"
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("CONNECTION INFORMATION"));
+}
+
+/// Test that GCLOUD_SSH_TEST runs the animated interactive path end to end
+#[test]
+fn test_fast_mode_runs_interactive_flow() {
+    let home = tempfile::tempdir().unwrap();
+    let hosts = home.path().join("hosts.csv");
+    std::fs::write(&hosts, "name,host,user\nweb-1,203.0.113.10,deploy\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hcloud"))
+        .args(["--hosts-file", hosts.to_str().unwrap(), "--vm", "web-1"])
+        .current_dir(home.path())
+        .env("HOME", home.path())
+        .env("XDG_CONFIG_HOME", home.path().join(".config"))
+        .env("GCLOUD_SSH_TEST", "1")
+        .output()
+        .expect("failed to run the binary");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Welcome to"));
+    assert!(stdout.contains("ssh deploy@203.0.113.10"));
+}